@@ -1,10 +1,33 @@
-use clap::Parser;
-use libdinky;
+use clap::{Parser, Subcommand};
+use libdinky::{
+    bank::{read_bank, FsbSample},
+    decoder::encode_yack_data,
+    ktx_encoder::{is_ktx, ktx_to_ktxbz, png_to_ktxbz, KtxFormat},
+    vorbis::VorbisSetups,
+    yack::{compile_yack, lint, read_yack, YackScript},
+    yack_graph::YackGraph,
+    yack_player::{YackEvent, YackPlayer},
+    Error, Result,
+};
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 ///Return to Monkey Island ggpack tool
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
-enum Args {
+struct Args {
+    ///Directory containing the encryption keys
+    #[clap(long, env = "GGTOOL_KEYS", default_value = "keys", global = true)]
+    keys_dir: String,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
     ///Extracts encryption keys from Return to Monkey Island.exe
     ExtractKeys {
         ///Path to Return to Monkey Island.exe
@@ -26,41 +49,650 @@ enum Args {
         outpath: String,
         ///Optional: Decompile *.yack files
         decompile_yack: bool,
+        ///Sort dictionary keys in expanded json/wimpy/emitter files
+        #[clap(long)]
+        sort_keys: bool,
+        ///Which images of *.ktxbz/*.ktxaz textures to convert to PNG: "first" for the first
+        ///image, "all" for every mip level, cubemap face and array layer as separate PNGs or
+        ///"atlas" for all of them in a single PNG
+        #[clap(long, default_value = "first")]
+        ktx_export: libdinky::ggpack::KtxExport,
+        ///Language of the localized lines to add to decompiled *.yack files, for instance en.
+        ///The text table is read from the *Text_<LANGUAGE>.tsv-file in the ggpack.
+        #[clap(long)]
+        text_language: Option<String>,
+        ///Name or pattern of the text table file in the ggpack to read the localized lines
+        ///from, instead of the one found by --text-language
+        #[clap(long, conflicts_with = "text-language")]
+        text_table: Option<String>,
+    },
+    ///Lists the sounds in FMOD .bank files
+    ListSounds {
+        ///Path to the ggpack-file
+        pack_path: String,
+        ///Pattern of banks to list, for instance *.bank
+        pattern: String,
+    },
+    ///Extracts the sounds in FMOD .bank files
+    ///
+    ///PCM sounds are written as .wav-files and Vorbis sounds as .ogg-files. Other sounds are
+    ///written as .fsb-files containing only that sound.
+    ExtractSounds {
+        ///Path to the ggpack-file
+        pack_path: String,
+        ///Pattern of banks to extract sounds from, for instance *.bank
+        pattern: String,
+        ///Output path
+        outpath: String,
+        ///Directory of Vorbis setup headers named after their CRC32, for instance 3A1B2C4D.bin
+        #[clap(long)]
+        vorbis_setups: Option<String>,
+        ///Write Vorbis sounds whose setup header isn't known as .fsb-files instead of failing
+        #[clap(long)]
+        fsb_fallback: bool,
+    },
+    ///Cuts a sprite sheet texture into a PNG per frame
+    SliceSheet {
+        ///Path to the ggpack-file
+        pack_path: String,
+        ///Name of the sheet json-file in the ggpack, for instance GuybrushSheet.json
+        sheet: String,
+        ///Output path
+        outpath: String,
+    },
+    ///Compiles a decompiled *.yack.txt file back to a yack
+    CompileYack {
+        ///Path to the yack text
+        input_path: String,
+        ///Path to the yack-file to write
+        output_path: String,
+        ///Encrypt the yack as it is stored in a ggpack. The key depends on the file name
+        ///of the output path.
+        #[clap(long)]
+        encrypt: bool,
+    },
+    ///Writes graphs of the labels, gotos and dialogue choices in yacks
+    ///
+    ///Each yack gets a Graphviz <NAME>.dot-file and a <NAME>.json-file with the nodes and
+    ///edges
+    YackGraph {
+        ///Path to the ggpack-file
+        pack_path: String,
+        ///Pattern of yacks to write graphs of, for instance *.yack
+        pattern: String,
+        ///Output path
+        outpath: String,
+    },
+    ///Plays a yack in the terminal
+    ///
+    ///Choices are picked by their number. Conditions other than once-conditions and variables
+    ///are asked for once, answer y or n.
+    PlayYack {
+        ///Path to the ggpack-file
+        pack_path: String,
+        ///Name of the yack in the ggpack, for instance Guybrush.yack
+        file: String,
+        ///Label to start at instead of the start of the yack
+        #[clap(long)]
+        label: Option<String>,
+        ///Variable to set before playing, as <NAME>=<VALUE>. Can be given multiple times.
+        #[clap(long = "set")]
+        variables: Vec<String>,
+    },
+    ///Checks yacks for broken branching
+    ///
+    ///Reports unreachable labels, jumps to undefined labels, dialogues without a choice that
+    ///leads out of them, unknown opcodes and unbalanced begin and end of dialogues. Exits
+    ///with status 1 if anything is found.
+    LintYack {
+        ///Yack-files to check, either decrypted *.yack-files or decompiled *.yack.txt-files
+        #[clap(required = true)]
+        files: Vec<String>,
+        ///Label that may be jumped to without being defined in the yack, e.g. exit. Can be
+        ///given multiple times.
+        #[clap(long = "allow-label")]
+        allow_labels: Vec<String>,
+    },
+    ///Creates a ggpack from all files in a directory
+    CreatePack {
+        ///Directory containing the files to pack
+        input_dir: String,
+        ///Path to the ggpack-file to create
+        pack_path: String,
+    },
+    ///Replaces or adds files in a ggpack and writes the result to a new ggpack
+    Replace {
+        ///Path to the ggpack-file
+        pack_path: String,
+        ///Path to the ggpack-file to write
+        output_path: String,
+        ///Files to replace or add. Each file is stored under its file name.
+        ///Textures named *.ktxbz.png are encoded and stored as *.ktxbz
+        #[clap(required = true)]
+        files: Vec<String>,
     },
 }
 
-fn extract_keys(exe_path: &str) {
-    let keys =
-        libdinky::Keys::extract_from_exe(exe_path).expect("Failed to extract keys from exe file");
-    if !std::path::Path::new("keys").exists() {
-        std::fs::create_dir("keys").expect("Failed to create dir 'keys'");
-    }
-    std::fs::write("keys/key1.bin", keys.key1).expect("Failed to write keys/key1.bin");
-    std::fs::write("keys/key2.bin", keys.key2).expect("Failed to write keys/key2.bin");
-    std::fs::write("keys/key3.bin", keys.key3).expect("Failed to write keys/key3.bin");
+fn extract_keys(exe_path: &str, keys_dir: &str) -> Result<()> {
+    let keys = libdinky::Keys::extract_from_exe(exe_path)?;
+    keys.save_to_dir(keys_dir)?;
 
     println!("Keys extracted successfully!");
+    Ok(())
 }
 
-fn main() {
-    let args = Args::parse();
-    match args {
-        Args::ExtractKeys { exe_path } => extract_keys(&exe_path),
-        Args::ListFiles { pack_path } => {
-            let pack =
-                libdinky::ggpack::OpenGGPack::from_path(&pack_path).expect("Failed to open ggpack");
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Decompiled yacks are extracted as `<name>.yack.txt`. Returns `<name>.yack` for such files,
+/// so edited yacks can be compiled and stored under the name of the original yack.
+fn yack_name(filename: &str) -> Option<&str> {
+    filename
+        .strip_suffix(".txt")
+        .filter(|name| name.ends_with(".yack"))
+}
+
+/// Reads a file to pack. Expanded json/wimpy/emitter files are converted back to the
+/// binary format, decompiled yacks are compiled and inflated textures are compressed again, so
+/// files written by extract-files can be packed again.
+fn read_input_file(path: &Path, filename: &str) -> Result<Vec<u8>> {
+    let data = std::fs::read(path)?;
+
+    if yack_name(filename).is_some() {
+        return compile_yack(&String::from_utf8_lossy(&data));
+    }
+
+    if is_texture(filename) && is_ktx(&data) {
+        return ktx_to_ktxbz(&data);
+    }
+
+    let is_expanded = (filename.ends_with(".json")
+        || filename.ends_with(".wimpy")
+        || filename.ends_with(".emitter"))
+        && !data.starts_with(&[0x01, 0x02, 0x03, 0x04]);
+
+    if is_expanded {
+        libdinky::directory::GGValue::from_json(&data)?.to_bytes()
+    } else {
+        Ok(data)
+    }
+}
+
+fn create_pack(input_dir: &str, pack_path: &str, keys: libdinky::Keys) -> Result<()> {
+    let mut files = Vec::new();
+    collect_files(Path::new(input_dir), &mut files)?;
+    files.sort();
+
+    let mut writer = libdinky::ggpack::GGPackWriter::create(pack_path, keys)?;
+
+    for path in files {
+        let filename = path
+            .strip_prefix(input_dir)
+            .unwrap_or(&path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if is_texture_preview(&filename) {
+            println!("Skipping texture preview {}", filename);
+            continue;
+        }
+
+        let data = read_input_file(&path, &filename)?;
+        let filename = yack_name(&filename).unwrap_or(&filename);
+        println!("Adding {}", filename);
+        writer.add_file(filename, &data)?;
+    }
+
+    writer.finish()?;
+    println!("Created {}", pack_path);
+    Ok(())
+}
+
+fn is_texture(filename: &str) -> bool {
+    filename.ends_with(".ktxbz") || filename.ends_with(".ktxaz")
+}
+
+/// Textures are extracted as `<name>.ktxbz.png`. Returns `<name>.ktxbz` for such files, so
+/// edited textures can be encoded and stored under the name of the original texture.
+fn texture_name(filename: &str) -> Option<&str> {
+    filename
+        .strip_suffix(".png")
+        .filter(|name| is_texture(name))
+}
+
+/// Whether `filename` is a PNG written next to a texture by extract-files, i.e.
+/// `<name>.ktxbz.png`, `<name>.ktxbz.atlas.png` or `<name>.ktxbz.mip<N>[.layer<N>].png`
+fn is_texture_preview(filename: &str) -> bool {
+    let mut name = match filename.strip_suffix(".png") {
+        Some(name) => name,
+        None => return false,
+    };
+
+    if let Some(texture) = name.strip_suffix(".atlas") {
+        name = texture;
+    }
+    for suffix in ["layer", "mip"] {
+        if let Some((texture, index)) = name.rsplit_once(&format!(".{}", suffix)) {
+            if !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()) {
+                name = texture;
+            }
+        }
+    }
+
+    is_texture(name)
+}
+
+fn replace_files(
+    pack_path: &str,
+    output_path: &str,
+    files: &[String],
+    keys: &libdinky::Keys,
+) -> Result<()> {
+    // The output is created before the input is read, so writing a pack onto itself would
+    // truncate it
+    if let (Ok(input), Ok(output)) = (
+        std::fs::canonicalize(pack_path),
+        std::fs::canonicalize(output_path),
+    ) {
+        if input == output {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the output path must not be the ggpack being replaced in",
+            )
+            .into());
+        }
+    }
+
+    let mut pack = libdinky::ggpack::OpenGGPack::open_with_keys(pack_path, keys)?;
+    let existing_files = pack.get_files()?;
+
+    for path in files {
+        let path = Path::new(path);
+        let mut filename = path
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string());
+
+        let data = match texture_name(&filename) {
+            Some(texture) => {
+                let format = match existing_files.iter().find(|f| f.filename == texture) {
+                    Some(file) => KtxFormat::from_ktxbz(&pack.read_file(file)?)?,
+                    None => KtxFormat::default(),
+                };
+
+                println!("Encoding {} as {}", filename, texture);
+                let data = png_to_ktxbz(&std::fs::read(path)?, format)?;
+                filename = texture.to_string();
+                data
+            }
+            None => {
+                let data = read_input_file(path, &filename)?;
+                if let Some(yack) = yack_name(&filename) {
+                    filename = yack.to_string();
+                }
+                data
+            }
+        };
+
+        if existing_files.iter().any(|f| f.filename == filename) {
+            println!("Replacing {}", filename);
+            pack.replace_file(&filename, data)?;
+        } else {
+            println!("Adding {}", filename);
+            pack.add_file(&filename, data)?;
+        }
+    }
+
+    pack.write_pack(output_path)?;
+    println!("Wrote {}", output_path);
+    Ok(())
+}
+
+/// Name of a sound in a bank, sounds without a name are named after the bank
+fn sound_name(bank_name: &str, index: usize, sample: &FsbSample) -> String {
+    match &sample.name {
+        Some(name) => name.replace(['/', '\\'], "_"),
+        None => format!("{}_{}", bank_name.trim_end_matches(".bank"), index),
+    }
+}
+
+fn list_sounds(pack_path: &str, pattern: &str, keys: &libdinky::Keys) -> Result<()> {
+    let mut pack = libdinky::ggpack::OpenGGPack::open_with_keys(pack_path, keys)?;
+
+    for file in pack.get_matching_files(pattern)? {
+        let samples = read_bank(&pack.read_file(&file)?)?;
+        println!("{}: {} sounds", file.filename, samples.len());
+
+        for (i, sample) in samples.iter().enumerate() {
+            let setup = match sample.vorbis_crc32 {
+                Some(crc32) => format!(", setup header {:08X}", crc32),
+                None => String::new(),
+            };
+            println!(
+                "  {}: {}, {} channels, {} Hz, {:.2} s{}",
+                sound_name(&file.filename, i, sample),
+                sample.codec,
+                sample.channels,
+                sample.frequency,
+                sample.duration_secs(),
+                setup
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn extract_sounds(
+    pack_path: &str,
+    pattern: &str,
+    outpath: &str,
+    vorbis_setups: Option<&str>,
+    fsb_fallback: bool,
+    keys: &libdinky::Keys,
+) -> Result<()> {
+    let vorbis_setups = match vorbis_setups {
+        Some(dir) => VorbisSetups::from_dir(dir)?,
+        None => VorbisSetups::new(),
+    };
+    let mut pack = libdinky::ggpack::OpenGGPack::open_with_keys(pack_path, keys)?;
+
+    for file in pack.get_matching_files(pattern)? {
+        let samples = read_bank(&pack.read_file(&file)?)?;
+        println!("Extracting {} sounds from {}", samples.len(), file.filename);
+
+        for (i, sample) in samples.iter().enumerate() {
+            let (extension, data) = match sample.to_file(&vorbis_setups) {
+                Err(Error::VorbisSetupNotFound(_)) if fsb_fallback => ("fsb", sample.to_fsb()?),
+                data => (sample.file_extension(), data?),
+            };
+            let path = format!(
+                "{}/{}.{}",
+                outpath,
+                sound_name(&file.filename, i, sample),
+                extension
+            );
+            std::fs::write(path, data)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_yack_graphs(
+    pack_path: &str,
+    pattern: &str,
+    outpath: &str,
+    keys: &libdinky::Keys,
+) -> Result<()> {
+    let mut pack = libdinky::ggpack::OpenGGPack::open_with_keys(pack_path, keys)?;
+
+    for file in pack.get_matching_files(pattern)? {
+        if !file.filename.ends_with(".yack") {
+            continue;
+        }
+
+        println!("Writing graph of {}", file.filename);
+        let graph = YackGraph::from_script(&read_yack(&pack.read_file(&file)?)?);
+
+        let path = format!("{}/{}", outpath, file.filename);
+        std::fs::write(format!("{}.dot", path), graph.to_dot(&file.filename))?;
+        std::fs::write(format!("{}.json", path), graph.to_json()?)?;
+    }
+
+    Ok(())
+}
+
+/// Prints `prompt` and reads a line from stdin, `None` at the end of input
+fn prompt(prompt: &str) -> Result<Option<String>> {
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+fn play_yack(
+    pack_path: &str,
+    filename: &str,
+    label: Option<&str>,
+    variables: &[String],
+    keys: &libdinky::Keys,
+) -> Result<()> {
+    let mut pack = libdinky::ggpack::OpenGGPack::open_with_keys(pack_path, keys)?;
+    let file = pack.get_file(filename)?;
+    let script = read_yack(&pack.read_file(&file)?)?;
+
+    let mut player = YackPlayer::new(&script);
+    for variable in variables {
+        let (name, value) =
+            variable
+                .split_once('=')
+                .ok_or_else(|| libdinky::Error::InvalidValue {
+                    kind: "variable, expected <NAME>=<VALUE>",
+                    value: variable.clone(),
+                })?;
+        player.set_variable(name.trim(), value.trim());
+    }
+    if let Some(label) = label {
+        player.goto(label);
+    }
+
+    // Each condition is asked for once. Variables take precedence, so assigning a variable in
+    // the yack overrides the answer. Conditions are answered with n when the input ends.
+    let mut answers: HashMap<String, bool> = HashMap::new();
+    let mut condition = |condition: &str| {
+        *answers
+            .entry(condition.to_string())
+            .or_insert_with(|| loop {
+                match prompt(&format!("Does [{}] hold? (y/n) ", condition)) {
+                    Ok(Some(answer)) if answer == "y" => break true,
+                    Ok(Some(answer)) if answer != "n" => continue,
+                    _ => break false,
+                }
+            })
+    };
+
+    loop {
+        match player.next(&mut condition)? {
+            YackEvent::Say { actor, text } => println!("{}: {}", actor, text),
+            YackEvent::Code(code) => println!("(run {})", code),
+            YackEvent::Pause(time) => println!("(pause {})", time),
+            YackEvent::WaitFor(actor) => println!("(wait for {})", actor),
+            YackEvent::Choices(choices) => {
+                for choice in &choices {
+                    println!("  {}. {}", choice.number, choice.text);
+                }
+
+                let choice = loop {
+                    let answer = match prompt("> ")? {
+                        Some(answer) => answer,
+                        None => return Ok(()),
+                    };
+                    if let Some(choice) = choices
+                        .iter()
+                        .find(|choice| answer == choice.number.to_string())
+                    {
+                        break choice;
+                    }
+                };
+                player.choose(choice);
+            }
+            YackEvent::End { label } => {
+                match label {
+                    Some(label) => println!("End of dialogue at {}", label),
+                    None => println!("End of dialogue"),
+                }
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Lints yack-files and returns the number of problems found
+fn lint_yack_files(files: &[String], allow_labels: &[String]) -> Result<usize> {
+    let allow_labels: Vec<&str> = allow_labels.iter().map(|label| label.as_str()).collect();
+
+    let mut problems = 0;
+    for path in files {
+        let data = std::fs::read(path)?;
+        let script = if path.ends_with(".txt") {
+            YackScript::from_text(&String::from_utf8_lossy(&data))?
+        } else {
+            read_yack(&data)?
+        };
+
+        for lint in lint(&script, &allow_labels) {
+            println!("{}: {}", path, lint);
+            problems += 1;
+        }
+    }
+
+    Ok(problems)
+}
+
+fn compile_yack_file(
+    input_path: &str,
+    output_path: &str,
+    encrypt: bool,
+    keys_dir: &str,
+) -> Result<()> {
+    let mut data = compile_yack(&std::fs::read_to_string(input_path)?)?;
+
+    if encrypt {
+        let keys = libdinky::Keys::from_dir(keys_dir)?;
+        let filename = Path::new(output_path)
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        encode_yack_data(&mut data, keys.key3(), &filename);
+    }
+
+    std::fs::write(output_path, data)?;
+    println!("Wrote {}", output_path);
+    Ok(())
+}
+
+fn run(args: Args) -> Result<()> {
+    let keys_dir = &args.keys_dir;
+    let load_keys = || libdinky::Keys::from_dir(keys_dir);
+
+    match args.command {
+        Command::ExtractKeys { exe_path } => extract_keys(&exe_path, keys_dir),
+        Command::ListFiles { pack_path } => {
+            let pack = libdinky::ggpack::OpenGGPack::open_with_keys(&pack_path, &load_keys()?)?;
             pack.list_files()
         }
-        Args::ExtractFiles {
+        Command::ExtractFiles {
             pack_path,
             pattern,
             outpath,
             decompile_yack,
+            sort_keys,
+            ktx_export,
+            text_language,
+            text_table,
         } => {
-            let mut pack =
-                libdinky::ggpack::OpenGGPack::from_path(&pack_path).expect("Failed to open ggpack");
+            let mut pack = libdinky::ggpack::OpenGGPack::open_with_keys(&pack_path, &load_keys()?)?;
+            pack.set_ktx_export(ktx_export);
+            if let Some(language) = text_language {
+                pack.set_text_language(&language)?;
+            }
+            if let Some(text_table) = text_table {
+                pack.set_text_table(&text_table)?;
+            }
 
-            pack.extract_files(&pattern, &outpath, decompile_yack)
+            pack.extract_files(&pattern, &outpath, decompile_yack, sort_keys)
         }
+        Command::ListSounds { pack_path, pattern } => {
+            list_sounds(&pack_path, &pattern, &load_keys()?)
+        }
+        Command::ExtractSounds {
+            pack_path,
+            pattern,
+            outpath,
+            vorbis_setups,
+            fsb_fallback,
+        } => extract_sounds(
+            &pack_path,
+            &pattern,
+            &outpath,
+            vorbis_setups.as_deref(),
+            fsb_fallback,
+            &load_keys()?,
+        ),
+        Command::SliceSheet {
+            pack_path,
+            sheet,
+            outpath,
+        } => {
+            let mut pack = libdinky::ggpack::OpenGGPack::open_with_keys(&pack_path, &load_keys()?)?;
+            pack.slice_sheet(&sheet, &outpath)
+        }
+        Command::CompileYack {
+            input_path,
+            output_path,
+            encrypt,
+        } => compile_yack_file(&input_path, &output_path, encrypt, keys_dir),
+        Command::YackGraph {
+            pack_path,
+            pattern,
+            outpath,
+        } => write_yack_graphs(&pack_path, &pattern, &outpath, &load_keys()?),
+        Command::PlayYack {
+            pack_path,
+            file,
+            label,
+            variables,
+        } => play_yack(
+            &pack_path,
+            &file,
+            label.as_deref(),
+            &variables,
+            &load_keys()?,
+        ),
+        Command::LintYack {
+            files,
+            allow_labels,
+        } => {
+            let problems = lint_yack_files(&files, &allow_labels)?;
+            if problems > 0 {
+                eprintln!("Found {} problems", problems);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::CreatePack {
+            input_dir,
+            pack_path,
+        } => create_pack(&input_dir, &pack_path, load_keys()?),
+        Command::Replace {
+            pack_path,
+            output_path,
+            files,
+        } => replace_files(&pack_path, &output_path, &files, &load_keys()?),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Err(e) = run(args) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
     }
 }
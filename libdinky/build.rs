@@ -9,7 +9,7 @@ fn main() {
     #[cfg(feature = "gl_generator")]
     {
         let dest = env::var("OUT_DIR").unwrap();
-        let mut file = File::create(&Path::new(&dest).join("bindings.rs")).unwrap();
+        let mut file = File::create(Path::new(&dest).join("bindings.rs")).unwrap();
 
         Registry::new(Api::Gl, (4, 6), Profile::Core, Fallbacks::All, [])
             .write_bindings(GlobalGenerator, &mut file)
@@ -0,0 +1,637 @@
+use std::{
+    fmt::Display,
+    io::{Cursor, Seek, SeekFrom},
+};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    easy_br::EasyRead,
+    vorbis::{rebuild_ogg, VorbisSetups},
+    Error, Result,
+};
+
+const FSB5_MAGIC: u32 = 0x35_42_53_46;
+
+/// Frequencies by the 4 bit frequency index of a sample header
+const FREQUENCIES: [u32; 11] = [
+    4000, 8000, 11000, 11025, 16000, 22050, 24000, 32000, 44100, 48000, 96000,
+];
+
+/// Channel counts by the 2 bit channel index of a sample header
+const CHANNELS: [u16; 4] = [1, 2, 6, 8];
+
+const CHUNK_CHANNELS: u32 = 1;
+const CHUNK_FREQUENCY: u32 = 2;
+const CHUNK_VORBIS_DATA: u32 = 11;
+
+/// Audio codec of the samples in an FSB5 sound bank
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsbCodec {
+    Pcm8,
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    PcmFloat,
+    GcAdpcm,
+    ImaAdpcm,
+    Vag,
+    HeVag,
+    Xma,
+    Mpeg,
+    Celt,
+    Atrac9,
+    XWma,
+    Vorbis,
+    FAdpcm,
+    Opus,
+    Unknown(u32),
+}
+
+/// Codecs by their FSB5 mode, starting at mode 1
+const CODECS: [FsbCodec; 17] = [
+    FsbCodec::Pcm8,
+    FsbCodec::Pcm16,
+    FsbCodec::Pcm24,
+    FsbCodec::Pcm32,
+    FsbCodec::PcmFloat,
+    FsbCodec::GcAdpcm,
+    FsbCodec::ImaAdpcm,
+    FsbCodec::Vag,
+    FsbCodec::HeVag,
+    FsbCodec::Xma,
+    FsbCodec::Mpeg,
+    FsbCodec::Celt,
+    FsbCodec::Atrac9,
+    FsbCodec::XWma,
+    FsbCodec::Vorbis,
+    FsbCodec::FAdpcm,
+    FsbCodec::Opus,
+];
+
+impl From<u32> for FsbCodec {
+    fn from(mode: u32) -> Self {
+        CODECS
+            .get(mode.wrapping_sub(1) as usize)
+            .copied()
+            .unwrap_or(FsbCodec::Unknown(mode))
+    }
+}
+
+impl FsbCodec {
+    pub fn mode(&self) -> u32 {
+        match self {
+            FsbCodec::Unknown(mode) => *mode,
+            codec => CODECS.iter().position(|c| c == codec).unwrap() as u32 + 1,
+        }
+    }
+}
+
+impl Display for FsbCodec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FsbCodec::Pcm8 => write!(f, "PCM8"),
+            FsbCodec::Pcm16 => write!(f, "PCM16"),
+            FsbCodec::Pcm24 => write!(f, "PCM24"),
+            FsbCodec::Pcm32 => write!(f, "PCM32"),
+            FsbCodec::PcmFloat => write!(f, "PCM float"),
+            FsbCodec::GcAdpcm => write!(f, "GC ADPCM"),
+            FsbCodec::ImaAdpcm => write!(f, "IMA ADPCM"),
+            FsbCodec::Vag => write!(f, "VAG"),
+            FsbCodec::HeVag => write!(f, "HEVAG"),
+            FsbCodec::Xma => write!(f, "XMA"),
+            FsbCodec::Mpeg => write!(f, "MPEG"),
+            FsbCodec::Celt => write!(f, "CELT"),
+            FsbCodec::Atrac9 => write!(f, "ATRAC9"),
+            FsbCodec::XWma => write!(f, "XWMA"),
+            FsbCodec::Vorbis => write!(f, "Vorbis"),
+            FsbCodec::FAdpcm => write!(f, "FADPCM"),
+            FsbCodec::Opus => write!(f, "Opus"),
+            FsbCodec::Unknown(mode) => write!(f, "unknown codec {}", mode),
+        }
+    }
+}
+
+/// A sub-sound of an FSB5 sound bank
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsbSample {
+    /// Name from the name table, if the bank was built with names
+    pub name: Option<String>,
+    pub codec: FsbCodec,
+    pub channels: u16,
+    pub frequency: u32,
+    /// Length in samples per channel
+    pub samples: u32,
+    /// CRC32 of the setup header of a Vorbis sample, which FMOD strips from the sample
+    pub vorbis_crc32: Option<u32>,
+    pub data: Vec<u8>,
+    /// Sample header and its extra chunks, as stored in the bank
+    header: Vec<u8>,
+}
+
+impl FsbSample {
+    pub fn duration_secs(&self) -> f64 {
+        self.samples as f64 / self.frequency.max(1) as f64
+    }
+
+    /// Extension of the file written by `to_file`
+    pub fn file_extension(&self) -> &'static str {
+        match self.codec {
+            FsbCodec::Pcm8
+            | FsbCodec::Pcm16
+            | FsbCodec::Pcm24
+            | FsbCodec::Pcm32
+            | FsbCodec::PcmFloat => "wav",
+            FsbCodec::Vorbis => "ogg",
+            _ => "fsb",
+        }
+    }
+
+    /// Returns PCM samples as a WAV file and Vorbis samples as an Ogg Vorbis file. Other
+    /// samples are returned as an FSB5 bank of their own. Fails with
+    /// `Error::VorbisSetupNotFound` for Vorbis samples whose setup header isn't in
+    /// `vorbis_setups`, `to_fsb` still writes those as an FSB5 bank.
+    pub fn to_file(&self, vorbis_setups: &VorbisSetups) -> Result<Vec<u8>> {
+        if let Some(wav) = self.to_wav()? {
+            return Ok(wav);
+        }
+        match self.to_ogg(vorbis_setups)? {
+            Some(ogg) => Ok(ogg),
+            None => self.to_fsb(),
+        }
+    }
+
+    /// Rebuilds an Ogg Vorbis file from a Vorbis sample and its setup header from
+    /// `vorbis_setups`, `None` for other codecs
+    pub fn to_ogg(&self, vorbis_setups: &VorbisSetups) -> Result<Option<Vec<u8>>> {
+        if self.codec != FsbCodec::Vorbis {
+            return Ok(None);
+        }
+
+        let crc32 = self.vorbis_crc32.ok_or_else(|| {
+            Error::Audio("The Vorbis sample has no setup header CRC32".to_string())
+        })?;
+        let setup_header = vorbis_setups
+            .get(crc32)
+            .ok_or(Error::VorbisSetupNotFound(crc32))?;
+
+        rebuild_ogg(
+            &self.data,
+            self.channels,
+            self.frequency,
+            self.samples,
+            setup_header,
+        )
+        .map(Some)
+    }
+
+    /// Wraps PCM samples in a WAV file, `None` for other codecs
+    pub fn to_wav(&self) -> Result<Option<Vec<u8>>> {
+        let (format, bits) = match self.codec {
+            FsbCodec::Pcm8 => (1, 8),
+            FsbCodec::Pcm16 => (1, 16),
+            FsbCodec::Pcm24 => (1, 24),
+            FsbCodec::Pcm32 => (1, 32),
+            FsbCodec::PcmFloat => (3, 32),
+            _ => return Ok(None),
+        };
+
+        let block_align = self.channels * bits / 8;
+        // Sample data is padded, only keep the actual samples
+        let length = (self.samples as usize * block_align as usize).min(self.data.len());
+        let mut data = self.data[..length].to_vec();
+        if self.codec == FsbCodec::Pcm8 {
+            // 8 bit WAV samples are unsigned
+            data.iter_mut().for_each(|sample| *sample ^= 0x80);
+        }
+
+        let too_large = || Error::Audio("The sample is too large for a WAV file".to_string());
+        let byte_rate = self
+            .frequency
+            .checked_mul(block_align as u32)
+            .ok_or_else(too_large)?;
+        let data_size = u32::try_from(data.len())
+            .ok()
+            .filter(|size| size.checked_add(36).is_some())
+            .ok_or_else(too_large)?;
+
+        let mut wav = Vec::with_capacity(44 + data.len());
+        wav.extend_from_slice(b"RIFF");
+        wav.write_u32::<LittleEndian>(36 + data_size)?;
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.write_u32::<LittleEndian>(16)?;
+        wav.write_u16::<LittleEndian>(format)?;
+        wav.write_u16::<LittleEndian>(self.channels)?;
+        wav.write_u32::<LittleEndian>(self.frequency)?;
+        wav.write_u32::<LittleEndian>(byte_rate)?;
+        wav.write_u16::<LittleEndian>(block_align)?;
+        wav.write_u16::<LittleEndian>(bits)?;
+        wav.extend_from_slice(b"data");
+        wav.write_u32::<LittleEndian>(data_size)?;
+        wav.extend_from_slice(&data);
+
+        Ok(Some(wav))
+    }
+
+    /// Writes the sample as an FSB5 bank containing only this sample
+    pub fn to_fsb(&self) -> Result<Vec<u8>> {
+        // The data offset of the only sample is zero
+        let mut header = self.header.clone();
+        let raw = u64::from_le_bytes(header[..8].try_into().unwrap()) & !(0x07FF_FFFF << 7);
+        header[..8].copy_from_slice(&raw.to_le_bytes());
+
+        let mut names = Vec::new();
+        if let Some(name) = &self.name {
+            names.write_u32::<LittleEndian>(4)?;
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+            names.resize(names.len().next_multiple_of(16), 0);
+        }
+
+        let mut fsb = Vec::new();
+        fsb.write_u32::<LittleEndian>(FSB5_MAGIC)?;
+        for value in [
+            1,
+            1,
+            header.len() as u32,
+            names.len() as u32,
+            self.data.len() as u32,
+            self.codec.mode(),
+        ] {
+            fsb.write_u32::<LittleEndian>(value)?;
+        }
+        // Unused fields and the hash of the bank
+        fsb.resize(0x3C, 0);
+        fsb.extend_from_slice(&header);
+        fsb.extend_from_slice(&names);
+        fsb.extend_from_slice(&self.data);
+
+        Ok(fsb)
+    }
+}
+
+/// Finds the `SND ` chunk of an FMOD bank, descending into `LIST` chunks
+fn find_sound_chunk(data: &[u8]) -> Option<&[u8]> {
+    let mut position = 0;
+    while position + 8 <= data.len() {
+        let id = &data[position..position + 4];
+        let size = u32::from_le_bytes(data[position + 4..position + 8].try_into().unwrap());
+        let end = (position + 8).checked_add(size as usize)?.min(data.len());
+        let chunk = &data[position + 8..end];
+
+        match id {
+            b"SND " => return Some(chunk),
+            b"LIST" if chunk.len() >= 4 => {
+                if let Some(sounds) = find_sound_chunk(&chunk[4..]) {
+                    return Some(sounds);
+                }
+            }
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        position = end + (end & 1);
+    }
+
+    None
+}
+
+/// Reads the samples of the FSB5 sound bank in an FMOD .bank file. Plain .fsb files are read
+/// as well.
+pub fn read_bank(data: &[u8]) -> Result<Vec<FsbSample>> {
+    if data.starts_with(b"FSB5") {
+        return read_fsb5(data);
+    }
+
+    if data.len() < 12 || !data.starts_with(b"RIFF") {
+        return Err(Error::Audio("Not an FMOD bank".to_string()));
+    }
+
+    let sounds = find_sound_chunk(&data[12..])
+        .ok_or_else(|| Error::Audio("The bank contains no sounds".to_string()))?;
+
+    // The sound bank is aligned within the chunk, so it's preceded by some padding
+    let start = sounds
+        .windows(4)
+        .position(|magic| magic == b"FSB5")
+        .ok_or_else(|| Error::Audio("No FSB5 sound bank found in the bank".to_string()))?;
+
+    read_fsb5(&sounds[start..])
+}
+
+/// Reads the samples of an FSB5 sound bank
+pub fn read_fsb5(data: &[u8]) -> Result<Vec<FsbSample>> {
+    let mut reader = Cursor::new(data);
+
+    let magic = reader.read_u32_le()?;
+    if magic != FSB5_MAGIC {
+        return Err(Error::BadMagic {
+            expected: FSB5_MAGIC,
+            found: magic,
+        });
+    }
+
+    let version = reader.read_u32_le()?;
+    let sample_count = reader.read_u32_le()?;
+    let sample_headers_size = reader.read_u32_le()? as u64;
+    let name_table_size = reader.read_u32_le()? as u64;
+    let data_size = reader.read_u32_le()? as u64;
+    let codec = FsbCodec::from(reader.read_u32_le()?);
+
+    let header_size = if version == 0 { 0x40 } else { 0x3C };
+    let name_table_start = header_size + sample_headers_size;
+    let data_start = name_table_start + name_table_size;
+    reader.seek(SeekFrom::Start(header_size))?;
+
+    let mut samples = Vec::new();
+    let mut offsets = Vec::new();
+    for _ in 0..sample_count {
+        let header_start = reader.position() as usize;
+        let raw = reader.read_u64::<LittleEndian>()?;
+
+        let mut frequency = FREQUENCIES
+            .get(((raw >> 1) & 0xF) as usize)
+            .copied()
+            .unwrap_or(44100);
+        let mut channels = CHANNELS[((raw >> 5) & 0x3) as usize];
+        offsets.push(((raw >> 7) & 0x07FF_FFFF) << 5);
+        let sample_length = (raw >> 34) as u32;
+        let mut vorbis_crc32 = None;
+
+        let mut has_chunks = raw & 1 == 1;
+        while has_chunks {
+            let chunk = reader.read_u32_le()?;
+            has_chunks = chunk & 1 == 1;
+            let size = ((chunk >> 1) & 0xFF_FFFF) as usize;
+            let chunk_data = reader.read_bytes(size)?;
+
+            match chunk >> 25 {
+                CHUNK_CHANNELS if size >= 1 => channels = chunk_data[0] as u16,
+                CHUNK_FREQUENCY if size >= 4 => {
+                    frequency = u32::from_le_bytes(chunk_data[..4].try_into().unwrap())
+                }
+                CHUNK_VORBIS_DATA if size >= 4 => {
+                    vorbis_crc32 = Some(u32::from_le_bytes(chunk_data[..4].try_into().unwrap()))
+                }
+                _ => {}
+            }
+        }
+
+        samples.push(FsbSample {
+            name: None,
+            codec,
+            channels,
+            frequency,
+            samples: sample_length,
+            vorbis_crc32,
+            data: Vec::new(),
+            header: data[header_start..reader.position() as usize].to_vec(),
+        });
+    }
+
+    if name_table_size > 0 {
+        reader.seek(SeekFrom::Start(name_table_start))?;
+        for sample in samples.iter_mut() {
+            let offset = reader.read_u32_le()? as u64;
+            sample.name = Some(
+                reader.read_at(SeekFrom::Start(name_table_start + offset), |reader| {
+                    reader.read_cstring()
+                })?,
+            );
+        }
+    }
+
+    for (i, sample) in samples.iter_mut().enumerate() {
+        let start = data_start + offsets[i];
+        let end = data_start + offsets.get(i + 1).copied().unwrap_or(data_size);
+        sample.data = data
+            .get(start as usize..end as usize)
+            .ok_or_else(|| Error::Audio(format!("Sample {} is outside of the sound bank", i)))?
+            .to_vec();
+    }
+
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an FSB5 bank of 16 bit PCM samples, one of them stereo at 22050 Hz through
+    /// extra chunks
+    fn build_fsb5() -> Vec<u8> {
+        let sample_data: [&[u8]; 2] = [&[1, 0, 2, 0, 3, 0], &[4, 0, 5, 0, 6, 0, 7, 0]];
+
+        let mut headers = Vec::new();
+        // 44100 Hz mono, 3 samples
+        let first: u64 = (8 << 1) | (3 << 34);
+        headers.write_u64::<LittleEndian>(first).unwrap();
+        // Offset 32, 2 samples and a frequency and a channels chunk
+        let second: u64 = 1 | (8 << 1) | (1 << 7) | (2 << 34);
+        headers.write_u64::<LittleEndian>(second).unwrap();
+        headers
+            .write_u32::<LittleEndian>(1 | (4 << 1) | (CHUNK_FREQUENCY << 25))
+            .unwrap();
+        headers.write_u32::<LittleEndian>(22050).unwrap();
+        headers
+            .write_u32::<LittleEndian>((1 << 1) | (CHUNK_CHANNELS << 25))
+            .unwrap();
+        headers.push(2);
+
+        let names = b"\x08\x00\x00\x00\x0e\x00\x00\x00Hello\0World\0\0\0".to_vec();
+
+        let mut data = sample_data[0].to_vec();
+        data.resize(32, 0);
+        data.extend_from_slice(sample_data[1]);
+
+        let mut fsb = Vec::new();
+        for value in [
+            FSB5_MAGIC,
+            1,
+            2,
+            headers.len() as u32,
+            names.len() as u32,
+            data.len() as u32,
+            2,
+        ] {
+            fsb.write_u32::<LittleEndian>(value).unwrap();
+        }
+        fsb.resize(0x3C, 0);
+        fsb.extend_from_slice(&headers);
+        fsb.extend_from_slice(&names);
+        fsb.extend_from_slice(&data);
+        fsb
+    }
+
+    /// A 440 Hz sine in stereo, from the samples of the audrey crate
+    const SINE_OGG: &[u8] = include_bytes!("../testdata/sine_440hz_stereo.ogg");
+
+    /// Reads the packets of an Ogg file and the granule position of its last page
+    fn read_ogg_packets(ogg: &[u8]) -> (Vec<Vec<u8>>, u64) {
+        let mut reader = ogg::PacketReader::new(Cursor::new(ogg));
+        let mut packets = Vec::new();
+        let mut granule = 0;
+        while let Some(packet) = reader.read_packet().unwrap() {
+            granule = packet.absgp_page();
+            packets.push(packet.data);
+        }
+        (packets, granule)
+    }
+
+    fn decode_ogg(ogg: &[u8]) -> Vec<i16> {
+        let mut reader = lewton::inside_ogg::OggStreamReader::new(Cursor::new(ogg)).unwrap();
+        let mut samples = Vec::new();
+        while let Some(packet) = reader.read_dec_packet_itl().unwrap() {
+            samples.extend(packet);
+        }
+        samples
+    }
+
+    /// Builds an FSB5 bank of a Vorbis sample with the audio packets of an Ogg Vorbis file,
+    /// the way FMOD stores them
+    fn build_vorbis_fsb5(ogg: &[u8], crc32: u32) -> Vec<u8> {
+        let (packets, granule) = read_ogg_packets(ogg);
+        let mut data = Vec::new();
+        for packet in &packets[3..] {
+            data.write_u16::<LittleEndian>(packet.len() as u16).unwrap();
+            data.extend_from_slice(packet);
+        }
+        data.resize(data.len().next_multiple_of(32), 0);
+
+        // 44100 Hz stereo, and a Vorbis chunk with the CRC32 of the setup header
+        let mut headers = Vec::new();
+        let raw: u64 = 1 | (8 << 1) | (1 << 5) | (granule << 34);
+        headers.write_u64::<LittleEndian>(raw).unwrap();
+        headers
+            .write_u32::<LittleEndian>((4 << 1) | (CHUNK_VORBIS_DATA << 25))
+            .unwrap();
+        headers.write_u32::<LittleEndian>(crc32).unwrap();
+
+        let mut fsb = Vec::new();
+        for value in [
+            FSB5_MAGIC,
+            1,
+            1,
+            headers.len() as u32,
+            0,
+            data.len() as u32,
+            FsbCodec::Vorbis.mode(),
+        ] {
+            fsb.write_u32::<LittleEndian>(value).unwrap();
+        }
+        fsb.resize(0x3C, 0);
+        fsb.extend_from_slice(&headers);
+        fsb.extend_from_slice(&data);
+        fsb
+    }
+
+    fn build_bank(fsb: &[u8]) -> Vec<u8> {
+        let mut sounds = vec![0; 12];
+        sounds.extend_from_slice(fsb);
+
+        let mut chunks = Vec::new();
+        chunks.extend_from_slice(b"FMT \x03\x00\x00\x00abc\0");
+        chunks.extend_from_slice(b"SND ");
+        chunks
+            .write_u32::<LittleEndian>(sounds.len() as u32)
+            .unwrap();
+        chunks.extend_from_slice(&sounds);
+
+        let mut bank = b"RIFF".to_vec();
+        bank.write_u32::<LittleEndian>(chunks.len() as u32 + 4)
+            .unwrap();
+        bank.extend_from_slice(b"FEV ");
+        bank.extend_from_slice(&chunks);
+        bank
+    }
+
+    #[test]
+    fn read_samples_from_bank() {
+        let samples = read_bank(&build_bank(&build_fsb5())).unwrap();
+        assert_eq!(samples.len(), 2);
+
+        assert_eq!(samples[0].name.as_deref(), Some("Hello"));
+        assert_eq!(samples[0].codec, FsbCodec::Pcm16);
+        assert_eq!(samples[0].channels, 1);
+        assert_eq!(samples[0].frequency, 44100);
+        assert_eq!(samples[0].samples, 3);
+        assert_eq!(samples[0].data.len(), 32);
+
+        assert_eq!(samples[1].name.as_deref(), Some("World"));
+        assert_eq!(samples[1].channels, 2);
+        assert_eq!(samples[1].frequency, 22050);
+        assert_eq!(samples[1].data, [4, 0, 5, 0, 6, 0, 7, 0]);
+    }
+
+    #[test]
+    fn write_wav() {
+        let samples = read_fsb5(&build_fsb5()).unwrap();
+        let wav = samples[0].to_file(&VorbisSetups::new()).unwrap();
+
+        assert_eq!(samples[0].file_extension(), "wav");
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u16::from_le_bytes([wav[22], wav[23]]), 1);
+        assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 44100);
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(&wav[44..], [1, 0, 2, 0, 3, 0]);
+
+        let mut sample = samples[1].clone();
+        sample.frequency = u32::MAX;
+        assert!(matches!(sample.to_wav(), Err(Error::Audio(_))));
+    }
+
+    #[test]
+    fn write_single_sample_fsb() {
+        let mut sample = read_fsb5(&build_fsb5()).unwrap().remove(1);
+        sample.codec = FsbCodec::Mpeg;
+        assert_eq!(sample.file_extension(), "fsb");
+
+        let fsb = sample.to_file(&VorbisSetups::new()).unwrap();
+        let written = read_fsb5(&fsb).unwrap().remove(0);
+        assert_eq!(written.name, sample.name);
+        assert_eq!(written.codec, FsbCodec::Mpeg);
+        assert_eq!(written.channels, 2);
+        assert_eq!(written.frequency, 22050);
+        assert_eq!(written.samples, 2);
+        assert_eq!(written.data, sample.data);
+    }
+
+    #[test]
+    fn rebuild_ogg_vorbis() {
+        let sample = read_fsb5(&build_vorbis_fsb5(SINE_OGG, 0x1234_5678))
+            .unwrap()
+            .remove(0);
+        assert_eq!(sample.codec, FsbCodec::Vorbis);
+        assert_eq!(sample.vorbis_crc32, Some(0x1234_5678));
+
+        // Without the setup header the sample can only be written as an FSB5 bank
+        let mut setups = VorbisSetups::new();
+        assert!(matches!(
+            sample.to_file(&setups),
+            Err(Error::VorbisSetupNotFound(0x1234_5678))
+        ));
+        let written = read_fsb5(&sample.to_fsb().unwrap()).unwrap().remove(0);
+        assert_eq!(written.vorbis_crc32, Some(0x1234_5678));
+        assert_eq!(written.data, sample.data);
+
+        let (packets, granule) = read_ogg_packets(SINE_OGG);
+        setups.insert(0x1234_5678, packets[2].clone());
+        assert_eq!(sample.file_extension(), "ogg");
+
+        let ogg = sample.to_file(&setups).unwrap();
+        let (rebuilt_packets, rebuilt_granule) = read_ogg_packets(&ogg);
+        assert_eq!(rebuilt_packets[2..], packets[2..]);
+        assert_eq!(rebuilt_granule, granule);
+        assert_eq!(decode_ogg(&ogg), decode_ogg(SINE_OGG));
+    }
+
+    #[test]
+    fn reject_files_without_sounds() {
+        assert!(matches!(
+            read_bank(b"RIFF\0\0\0\0FEV "),
+            Err(Error::Audio(_))
+        ));
+        assert!(matches!(read_bank(b"OggS"), Err(Error::Audio(_))));
+    }
+}
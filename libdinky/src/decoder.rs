@@ -1,18 +1,150 @@
-pub fn decode_data(data: &mut Vec<u8>, key1: &Vec<u8>, key2: &Vec<u8>) {
-    let mut xor_sum = (data.len() + 120) as u16;
+/// Keystream state of `decode_data`, for decoding a file in several chunks
+pub struct DataDecoder<'a> {
+    key1: &'a [u8],
+    key2: &'a [u8],
+    xor_sum: u16,
+}
+
+impl<'a> DataDecoder<'a> {
+    /// `data_len` is the length of the whole file, not of the first chunk
+    pub fn new(data_len: usize, key1: &'a [u8], key2: &'a [u8]) -> Self {
+        Self {
+            key1,
+            key2,
+            xor_sum: (data_len + 120) as u16,
+        }
+    }
+
+    pub fn decode(&mut self, data: &mut [u8]) {
+        for c in data {
+            *c ^= self.key1[self.xor_sum as usize]
+                ^ self.key2[(((self.xor_sum as usize) + 120) as u8) as usize];
+            self.xor_sum = self
+                .xor_sum
+                .wrapping_add(self.key2[(self.xor_sum as u8) as usize] as u16);
+        }
+    }
+}
+
+/// Keystream state of `decode_yack_data`, for decoding a file in several chunks
+pub struct YackDecoder<'a> {
+    key: &'a [u8],
+    position: usize,
+}
+
+impl<'a> YackDecoder<'a> {
+    pub fn new(key: &'a [u8], filename: &str) -> Self {
+        Self {
+            key,
+            position: filename.len().saturating_sub(5),
+        }
+    }
 
-    for c in data {
-        *c ^= key1[xor_sum as usize] ^ key2[(((xor_sum as usize) + 120) as u8) as usize];
-        xor_sum = xor_sum.wrapping_add(key2[(xor_sum as u8) as usize] as u16);
+    pub fn decode(&mut self, data: &mut [u8]) {
+        for c in data {
+            *c ^= self.key[self.position & 0x3FF];
+            self.position += 1;
+        }
     }
 }
 
-pub fn decode_yack_data(data: &mut Vec<u8>, key: &Vec<u8>, filename: &str) {
-    let val = filename.len() - 5;
-    let mut i = 0;
-    for c in data {
-        let idx = i + val & 0x3FF;
-        i += 1;
-        *c ^= key[idx];
+pub fn decode_data(data: &mut [u8], key1: &[u8], key2: &[u8]) {
+    DataDecoder::new(data.len(), key1, key2).decode(data);
+}
+
+pub fn decode_yack_data(data: &mut [u8], key: &[u8], filename: &str) {
+    YackDecoder::new(key, filename).decode(data);
+}
+
+/// Inverse of `decode_data`. The keystream only depends on the data length and the keys,
+/// so encoding applies the same XOR as decoding.
+pub fn encode_data(data: &mut [u8], key1: &[u8], key2: &[u8]) {
+    decode_data(data, key1, key2);
+}
+
+/// Inverse of `decode_yack_data`.
+pub fn encode_yack_data(data: &mut [u8], key: &[u8], filename: &str) {
+    decode_yack_data(data, key, filename);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next_u8(&mut self) -> u8 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 24) as u8
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next_u8()).collect()
+        }
+    }
+
+    #[test]
+    fn encode_data_round_trip() {
+        let mut rng = XorShift(0x2545F4914F6CDD1D);
+        let key1 = rng.bytes(65536);
+        let key2 = rng.bytes(256);
+
+        for len in [0, 1, 2, 119, 120, 255, 256, 4096, 65535, 70000] {
+            let original = rng.bytes(len);
+
+            let mut data = original.clone();
+            encode_data(&mut data, &key1, &key2);
+            if len > 16 {
+                assert_ne!(data, original);
+            }
+
+            decode_data(&mut data, &key1, &key2);
+            assert_eq!(data, original);
+        }
+    }
+
+    #[test]
+    fn chunked_decoding_matches_decode_data() {
+        let mut rng = XorShift(0xD1B54A32D192ED03);
+        let key1 = rng.bytes(65536);
+        let key2 = rng.bytes(256);
+        let key3 = rng.bytes(1024);
+        let original = rng.bytes(3000);
+
+        let mut expected = original.clone();
+        decode_data(&mut expected, &key1, &key2);
+        decode_yack_data(&mut expected, &key3, "Chunked.yack");
+
+        let mut data = original;
+        let mut data_decoder = DataDecoder::new(data.len(), &key1, &key2);
+        let mut yack_decoder = YackDecoder::new(&key3, "Chunked.yack");
+        for chunk in data.chunks_mut(7) {
+            data_decoder.decode(chunk);
+            yack_decoder.decode(chunk);
+        }
+
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn encode_yack_data_round_trip() {
+        let mut rng = XorShift(0x9E3779B97F4A7C15);
+        let key3 = rng.bytes(1024);
+
+        for (len, filename) in [(0, "a.yack"), (17, "Opening.yack"), (5000, "MeleeMap.yack")] {
+            let original = rng.bytes(len);
+
+            let mut data = original.clone();
+            encode_yack_data(&mut data, &key3, filename);
+            if len > 16 {
+                assert_ne!(data, original);
+            }
+
+            decode_yack_data(&mut data, &key3, filename);
+            assert_eq!(data, original);
+        }
     }
 }
@@ -1,28 +1,214 @@
 use std::io::Cursor;
 
-use crate::easy_br::EasyRead;
+use crate::{easy_br::EasyRead, Error, Result};
 
-pub fn read_dink(data: &Vec<u8>) -> Result<(), std::io::Error> {
-	let mut reader = Cursor::new(data);
+const BLOCK_MARKER: u32 = 0x45_41_78_9C;
+const FUNCTION_MARKER: u32 = 0x7F_46_A1_25;
+const NAME_MARKER: u32 = 0x16_F9_4B_62;
 
-	let start_marker = reader.read_u32_le()?;
-	assert!(start_marker == 0x45_41_78_9C);
+/// A compiled Dinky script. Only the functions and their names are decoded; constants and
+/// bytecode are kept as raw sections, see `read_dink`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DinkModule {
+    pub functions: Vec<DinkFunction>,
+}
 
-	let _block_size = reader.read_u32_le()?;
-	
-	let header_start_marker = reader.read_u32_le()?;
-	assert!(header_start_marker == 0x7F_46_A1_25);
+/// A function in a compiled Dinky script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DinkFunction {
+    /// Fields following the function marker whose meaning isn't known yet
+    pub header: (u32, u32, u16),
+    pub name: String,
+    /// The sections following the name, in file order. Their layout isn't known yet, so they
+    /// are kept as tagged raw data.
+    pub sections: Vec<DinkSection>,
+}
 
-	let _unk = reader.read_u32_le()?;
-	let _unk2 = reader.read_u32_le()?;
-	let _unk3 = reader.read_u16_le()?;
+/// A section of a function, assumed to be stored as a marker followed by the size of the
+/// data. Which sections hold constants and which hold bytecode isn't known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DinkSection {
+    pub marker: u32,
+    pub data: Vec<u8>,
+}
 
-	let name_start_marker = reader.read_u32_le()?;
-	assert!(name_start_marker == 0x16_F9_4B_62);
+impl DinkSection {
+    /// The data as little endian 32 bit words. Trailing bytes that don't fill a word are
+    /// left out.
+    pub fn words(&self) -> Vec<u32> {
+        self.data
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect()
+    }
+}
 
-	let _name_size = reader.read_u32_le()?;
+fn read_marker(reader: &mut Cursor<&[u8]>, expected: u32) -> Result<()> {
+    let found = reader.read_u32_le()?;
+    if found != expected {
+        return Err(Error::BadMagic { expected, found });
+    }
 
+    Ok(())
+}
 
+/// Returns the next marker without consuming it, `None` at the end of the data
+fn peek_marker(reader: &mut Cursor<&[u8]>) -> Result<Option<u32>> {
+    if reader.position() + 4 > reader.get_ref().len() as u64 {
+        return Ok(None);
+    }
 
-	todo!()
-}
\ No newline at end of file
+    let marker = reader.read_u32_le()?;
+    reader.set_position(reader.position() - 4);
+    Ok(Some(marker))
+}
+
+fn read_function(reader: &mut Cursor<&[u8]>) -> Result<DinkFunction> {
+    read_marker(reader, FUNCTION_MARKER)?;
+
+    let header = (
+        reader.read_u32_le()?,
+        reader.read_u32_le()?,
+        reader.read_u16_le()?,
+    );
+
+    read_marker(reader, NAME_MARKER)?;
+    let name_size = reader.read_u32_le()? as usize;
+    let name =
+        String::from_utf8(reader.read_bytes(name_size)?).map_err(|e| Error::InvalidValue {
+            kind: "function name",
+            value: String::from_utf8_lossy(e.as_bytes()).to_string(),
+        })?;
+
+    let mut sections = Vec::new();
+    while let Some(marker) = peek_marker(reader)? {
+        if marker == FUNCTION_MARKER {
+            break;
+        }
+
+        reader.read_u32_le()?;
+        let size = reader.read_u32_le()? as usize;
+        sections.push(DinkSection {
+            marker,
+            data: reader.read_bytes(size)?,
+        });
+    }
+
+    Ok(DinkFunction {
+        header,
+        name,
+        sections,
+    })
+}
+
+/// Reads a compiled Dinky script. The script is made of blocks, each holding one or more
+/// functions.
+///
+/// The block, function and name markers come from the original reader, but the layout of the
+/// sections after the name is a guess that hasn't been checked against the game's scripts.
+/// Real .dink files may therefore fail with `Error::BadMagic` or `Error::Io`, or be read into
+/// sections that don't match their actual structure.
+pub fn read_dink(data: &[u8]) -> Result<DinkModule> {
+    let mut reader = Cursor::new(data);
+    let mut functions = Vec::new();
+
+    while peek_marker(&mut reader)?.is_some() {
+        read_marker(&mut reader, BLOCK_MARKER)?;
+
+        let block_size = reader.read_u32_le()? as usize;
+        let block = reader.read_bytes(block_size)?;
+
+        let mut block_reader = Cursor::new(&block[..]);
+        while peek_marker(&mut block_reader)?.is_some() {
+            functions.push(read_function(&mut block_reader)?);
+        }
+    }
+
+    Ok(DinkModule { functions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(name: &str, sections: &[(u32, &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for value in [FUNCTION_MARKER, 1, 2] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&3u16.to_le_bytes());
+        data.extend_from_slice(&NAME_MARKER.to_le_bytes());
+        data.extend_from_slice(&(name.len() as u32).to_le_bytes());
+        data.extend_from_slice(name.as_bytes());
+
+        for (marker, section) in sections {
+            data.extend_from_slice(&marker.to_le_bytes());
+            data.extend_from_slice(&(section.len() as u32).to_le_bytes());
+            data.extend_from_slice(section);
+        }
+        data
+    }
+
+    fn block(functions: &[Vec<u8>]) -> Vec<u8> {
+        let functions = functions.concat();
+        let mut data = BLOCK_MARKER.to_le_bytes().to_vec();
+        data.extend_from_slice(&(functions.len() as u32).to_le_bytes());
+        data.extend_from_slice(&functions);
+        data
+    }
+
+    #[test]
+    fn read_functions() {
+        let data = [
+            block(&[
+                function("main", &[(0x1234, &[1, 0, 0, 0, 2, 0, 0, 0, 3])]),
+                function("empty", &[]),
+            ]),
+            block(&[function("other", &[(0x1, b"ab"), (0x2, b"")])]),
+        ]
+        .concat();
+
+        let module = read_dink(&data).unwrap();
+        let names: Vec<_> = module.functions.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(names, ["main", "empty", "other"]);
+
+        let main = &module.functions[0];
+        assert_eq!(main.header, (1, 2, 3));
+        assert_eq!(main.sections.len(), 1);
+        assert_eq!(main.sections[0].marker, 0x1234);
+        assert_eq!(main.sections[0].words(), [1, 2]);
+
+        assert!(module.functions[1].sections.is_empty());
+        assert_eq!(module.functions[2].sections[1].data, b"");
+    }
+
+    #[test]
+    fn reject_bad_markers() {
+        let mut data = block(&[function("main", &[])]);
+        data[8] ^= 0xFF;
+
+        assert!(matches!(
+            read_dink(&data),
+            Err(Error::BadMagic {
+                expected: FUNCTION_MARKER,
+                ..
+            })
+        ));
+        assert!(matches!(
+            read_dink(&[0; 8]),
+            Err(Error::BadMagic {
+                expected: BLOCK_MARKER,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn reject_truncated_sections() {
+        let mut data = block(&[function("main", &[(0x1234, &[1, 2, 3, 4])])]);
+        data.truncate(data.len() - 1);
+        data[4] -= 1;
+
+        assert!(matches!(read_dink(&data), Err(Error::Io(_))));
+    }
+}
@@ -1,14 +1,20 @@
-use crate::easy_br::EasyRead;
-use byteorder::ReadBytesExt;
+use crate::{easy_br::EasyRead, Error, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use indexmap::IndexMap;
 use serde::{
+    de::{DeserializeSeed, MapAccess, SeqAccess, Visitor},
     ser::{SerializeMap, SerializeSeq},
-    Serialize,
+    Deserialize, Serialize,
 };
 use std::{
+    cell::Cell,
     collections::HashMap,
+    fmt::Display,
     io::{Cursor, SeekFrom},
+    str::FromStr,
 };
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum GGValueType {
     Null = 1,
     Dictionary = 2,
@@ -21,35 +27,222 @@ pub enum GGValueType {
     Hotspot = 11,
 }
 
-impl From<u8> for GGValueType {
-    fn from(a: u8) -> Self {
+impl TryFrom<u8> for GGValueType {
+    type Error = Error;
+
+    fn try_from(a: u8) -> Result<Self> {
         match a {
-            1 => GGValueType::Null,
-            2 => GGValueType::Dictionary,
-            3 => GGValueType::List,
-            4 => GGValueType::String,
-            5 => GGValueType::Integer,
-            6 => GGValueType::Float,
-            9 => GGValueType::Coordinate,
-            10 => GGValueType::CoordinateList,
-            11 => GGValueType::Hotspot,
-            _ => panic!("{} is not a known GGValueType", a),
+            1 => Ok(GGValueType::Null),
+            2 => Ok(GGValueType::Dictionary),
+            3 => Ok(GGValueType::List),
+            4 => Ok(GGValueType::String),
+            5 => Ok(GGValueType::Integer),
+            6 => Ok(GGValueType::Float),
+            9 => Ok(GGValueType::Coordinate),
+            10 => Ok(GGValueType::CoordinateList),
+            11 => Ok(GGValueType::Hotspot),
+            _ => Err(Error::UnknownValueType(a)),
         }
     }
 }
 
-type IOResult<T> = Result<T, std::io::Error>;
-
+#[derive(Debug, Clone, PartialEq)]
 pub enum GGValue {
     GGNull(),
-    GGDict(HashMap<String, GGValue>),
+    GGDict(IndexMap<String, GGValue>),
     GGList(Vec<GGValue>),
     GGString(String),
-    GGNumber(f32),
+    GGInteger(i64),
+    GGFloat(GGParsed<f64>),
+    GGCoordinate(GGParsed<GGCoordinate>),
+    GGCoordinateList(GGParsed<Vec<GGCoordinate>>),
+    GGHotspot(GGParsed<GGHotspot>),
+}
+
+/// A value that is stored as text, such as a float or a coordinate. The text is kept, so the
+/// value is written back exactly as it was read, e.g. `1` isn't turned into `1.0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GGParsed<T> {
+    value: T,
+    text: String,
+}
+
+impl<T> GGParsed<T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Values that are stored as text in a directory
+pub trait GGText: Sized {
+    fn parse_text(s: &str) -> Result<Self>;
+    fn format_text(&self) -> String;
+}
+
+impl<T: GGText> From<T> for GGParsed<T> {
+    fn from(value: T) -> Self {
+        let text = value.format_text();
+        Self { value, text }
+    }
+}
+
+impl<T: GGText> FromStr for GGParsed<T> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Self {
+            value: T::parse_text(s)?,
+            text: s.to_string(),
+        })
+    }
+}
+
+impl GGText for f64 {
+    fn parse_text(s: &str) -> Result<Self> {
+        s.parse().map_err(|_| invalid_value("float", s))
+    }
+
+    /// Debug formatting keeps the decimal point of whole numbers and round trips exactly
+    fn format_text(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// A point, stored as `{x,y}`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GGCoordinate {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A rectangle given by two corners, stored as `{{x1,y1},{x2,y2}}`
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct GGHotspot {
+    pub min: GGCoordinate,
+    pub max: GGCoordinate,
+}
+
+fn invalid_value(kind: &'static str, value: &str) -> Error {
+    Error::InvalidValue {
+        kind,
+        value: value.to_string(),
+    }
+}
+
+impl FromStr for GGCoordinate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (x, y) = s
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .and_then(|s| s.split_once(','))
+            .ok_or_else(|| invalid_value("coordinate", s))?;
+
+        Ok(GGCoordinate {
+            x: x.trim()
+                .parse()
+                .map_err(|_| invalid_value("coordinate", s))?,
+            y: y.trim()
+                .parse()
+                .map_err(|_| invalid_value("coordinate", s))?,
+        })
+    }
+}
+
+impl Display for GGCoordinate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{{},{}}}", self.x, self.y)
+    }
+}
+
+impl GGText for GGCoordinate {
+    fn parse_text(s: &str) -> Result<Self> {
+        s.parse()
+    }
+
+    fn format_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl GGText for Vec<GGCoordinate> {
+    fn parse_text(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        s.split(';').map(|c| c.parse()).collect()
+    }
+
+    fn format_text(&self) -> String {
+        self.iter()
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+impl FromStr for GGHotspot {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (min, max) = s
+            .strip_prefix('{')
+            .and_then(|s| s.strip_suffix('}'))
+            .and_then(|s| s.split_once("},{"))
+            .ok_or_else(|| invalid_value("hotspot", s))?;
+
+        Ok(GGHotspot {
+            min: format!("{}}}", min).parse()?,
+            max: format!("{{{}", max).parse()?,
+        })
+    }
+}
+
+impl Display for GGHotspot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{{{},{}}}", self.min, self.max)
+    }
+}
+
+impl GGText for GGHotspot {
+    fn parse_text(s: &str) -> Result<Self> {
+        s.parse()
+    }
+
+    fn format_text(&self) -> String {
+        self.to_string()
+    }
+}
+
+/// Coordinates, coordinate lists and hotspots are serialized to JSON as a dict with one of these
+/// keys, e.g. `{"$coordinate": "{10,20}"}`, so they can't be mistaken for strings. Floats are
+/// only tagged when their text wouldn't survive as a JSON number, e.g. `{"$float": "1"}`.
+const FLOAT_TAG: &str = "$float";
+const COORDINATE_TAG: &str = "$coordinate";
+const COORDINATE_LIST_TAG: &str = "$coordinate_list";
+const HOTSPOT_TAG: &str = "$hotspot";
+
+fn serialize_tagged<S>(
+    serializer: S,
+    tag: &str,
+    value: &str,
+) -> std::result::Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(tag, value)?;
+    map.end()
 }
 
 impl Serialize for GGValue {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
@@ -69,43 +262,181 @@ impl Serialize for GGValue {
                 }
                 seq.end()
             }
-            GGValue::GGString(s) => serializer.serialize_str(&s),
-            GGValue::GGNumber(f) => serializer.serialize_f32(*f),
+            GGValue::GGString(s) => serializer.serialize_str(s),
+            GGValue::GGInteger(i) => serializer.serialize_i64(*i),
+            GGValue::GGFloat(f) => {
+                if f.value().is_finite() && f.text() == f.value().format_text() {
+                    serializer.serialize_f64(*f.value())
+                } else {
+                    serialize_tagged(serializer, FLOAT_TAG, f.text())
+                }
+            }
+            GGValue::GGCoordinate(c) => serialize_tagged(serializer, COORDINATE_TAG, c.text()),
+            GGValue::GGCoordinateList(l) => {
+                serialize_tagged(serializer, COORDINATE_LIST_TAG, l.text())
+            }
+            GGValue::GGHotspot(h) => serialize_tagged(serializer, HOTSPOT_TAG, h.text()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for GGValue {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let error = Cell::new(None);
+        GGValueVisitor { error: &error }.deserialize(deserializer)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct GGValueVisitor<'a> {
+    /// The first error that isn't a JSON syntax error, so `GGValue::from_json` can return it
+    /// as it is instead of as a JSON error
+    error: &'a Cell<Option<Error>>,
+}
+
+impl GGValueVisitor<'_> {
+    fn fail<E: serde::de::Error>(&self, error: Error) -> E {
+        let json_error = E::custom(&error);
+        self.error.set(Some(error));
+        json_error
+    }
+}
+
+impl<'de> DeserializeSeed<'de> for GGValueVisitor<'_> {
+    type Value = GGValue;
+
+    fn deserialize<D>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for GGValueVisitor<'_> {
+    type Value = GGValue;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a null, dictionary, list, string, number or boolean")
+    }
+
+    fn visit_unit<E>(self) -> std::result::Result<Self::Value, E> {
+        Ok(GGValue::GGNull())
+    }
+
+    fn visit_bool<E>(self, v: bool) -> std::result::Result<Self::Value, E> {
+        Ok(GGValue::GGInteger(v as i64))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> std::result::Result<Self::Value, E> {
+        Ok(GGValue::GGInteger(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> std::result::Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        i64::try_from(v)
+            .map(GGValue::GGInteger)
+            .map_err(|_| self.fail(invalid_value("integer", &v.to_string())))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> std::result::Result<Self::Value, E> {
+        Ok(GGValue::GGFloat(v.into()))
+    }
+
+    fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E> {
+        Ok(GGValue::GGString(v.to_string()))
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> std::result::Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut list = Vec::new();
+        while let Some(element) = seq.next_element_seed(self)? {
+            list.push(element);
+        }
+
+        Ok(GGValue::GGList(list))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> std::result::Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut dict: IndexMap<String, GGValue> = IndexMap::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let value = map.next_value_seed(self)?;
+            dict.insert(key, value);
         }
+
+        if let (1, Some((tag, GGValue::GGString(value)))) = (dict.len(), dict.first()) {
+            let typed = match tag.as_str() {
+                FLOAT_TAG => value.parse().map(GGValue::GGFloat),
+                COORDINATE_TAG => value.parse().map(GGValue::GGCoordinate),
+                COORDINATE_LIST_TAG => value.parse().map(GGValue::GGCoordinateList),
+                HOTSPOT_TAG => value.parse().map(GGValue::GGHotspot),
+                _ => return Ok(GGValue::GGDict(dict)),
+            };
+            return typed.map_err(|e| self.fail(e));
+        }
+
+        Ok(GGValue::GGDict(dict))
     }
 }
 
 impl GGValue {
-    pub fn expect_dict(&self) -> &HashMap<String, GGValue> {
+    pub fn expect_dict(&self) -> Result<&IndexMap<String, GGValue>> {
         match self {
-            GGValue::GGDict(d) => d,
-            _ => panic!("Expected dict"),
+            GGValue::GGDict(d) => Ok(d),
+            _ => Err(Error::UnexpectedValueType("dict")),
         }
     }
-    pub fn expect_list(&self) -> &Vec<GGValue> {
+    pub fn expect_list(&self) -> Result<&Vec<GGValue>> {
         match self {
-            GGValue::GGList(l) => l,
-            _ => panic!("Expected list"),
+            GGValue::GGList(l) => Ok(l),
+            _ => Err(Error::UnexpectedValueType("list")),
         }
     }
-    pub fn expect_string(&self) -> &String {
+    pub fn expect_string(&self) -> Result<&String> {
         match self {
-            GGValue::GGString(s) => s,
-            _ => panic!("Expected string"),
+            GGValue::GGString(s) => Ok(s),
+            _ => Err(Error::UnexpectedValueType("string")),
         }
     }
-    pub fn expect_number(&self) -> &f32 {
+    pub fn expect_integer(&self) -> Result<&i64> {
         match self {
-            GGValue::GGNumber(f) => f,
-            _ => panic!("Expected int"),
+            GGValue::GGInteger(i) => Ok(i),
+            _ => Err(Error::UnexpectedValueType("int")),
         }
     }
+    pub fn expect_float(&self) -> Result<&f64> {
+        match self {
+            GGValue::GGFloat(f) => Ok(f.value()),
+            _ => Err(Error::UnexpectedValueType("float")),
+        }
+    }
+    /// Looks up `key` in a dict, failing with `Error::MissingKey` if it isn't there
+    pub fn expect_entry(&self, key: &str) -> Result<&GGValue> {
+        self.expect_dict()?
+            .get(key)
+            .ok_or_else(|| Error::MissingKey(key.to_string()))
+    }
 
-    pub fn parse(data: Vec<u8>) -> IOResult<Self> {
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
         let mut reader = Cursor::new(data);
 
         let magic = reader.read_u32_le()?;
-        assert!(magic == 0x04030201, "Magic must be 01 02 03 04");
+        if magic != 0x04030201 {
+            return Err(Error::BadMagic {
+                expected: 0x04030201,
+                found: magic,
+            });
+        }
 
         let _num_tables = reader.read_u32_le()?; // Skip for now
 
@@ -114,7 +445,12 @@ impl GGValue {
         let offsets = reader.read_at(SeekFrom::Start(offset_to_table), |reader| {
             // This may be cheating but let's just do it for now
             let table_type = reader.read_u8()?;
-            assert!(table_type == 7);
+            if table_type != 7 {
+                return Err(Error::BadMagic {
+                    expected: 7,
+                    found: table_type as u32,
+                });
+            }
 
             let mut offsets = Vec::new();
             loop {
@@ -132,6 +468,42 @@ impl GGValue {
         let mut directory_builder = DirectoryBuilder { reader, offsets };
         directory_builder.read_ggvalue()
     }
+
+    /// Recursively sorts all dictionary keys, for canonical output independent of the
+    /// order the keys were stored in.
+    pub fn sort_keys(&mut self) {
+        match self {
+            GGValue::GGDict(d) => {
+                d.sort_keys();
+                d.values_mut().for_each(|v| v.sort_keys());
+            }
+            GGValue::GGList(l) => l.iter_mut().for_each(|v| v.sort_keys()),
+            _ => {}
+        }
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self> {
+        let error = Cell::new(None);
+        let mut deserializer = serde_json::Deserializer::from_slice(json);
+        let value = GGValueVisitor { error: &error }.deserialize(&mut deserializer);
+        if let Some(error) = error.take() {
+            return Err(error);
+        }
+
+        let value = value?;
+        deserializer.end()?;
+        Ok(value)
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut directory_writer = DirectoryWriter {
+            data: Vec::new(),
+            strings: Vec::new(),
+            string_indices: HashMap::new(),
+        };
+        directory_writer.write_ggvalue(self)?;
+        directory_writer.finish()
+    }
 }
 
 struct DirectoryBuilder {
@@ -140,20 +512,24 @@ struct DirectoryBuilder {
 }
 
 impl DirectoryBuilder {
-    fn read_table_entry(&mut self) -> IOResult<String> {
+    fn read_table_entry(&mut self) -> Result<String> {
         let offset = self.reader.read_u16_le()? as usize;
+        let string_offset = *self
+            .offsets
+            .get(offset)
+            .ok_or(Error::BadStringIndex(offset))?;
 
         let str = self
             .reader
-            .read_at(SeekFrom::Start(self.offsets[offset] as u64), |reader| {
+            .read_at(SeekFrom::Start(string_offset as u64), |reader| {
                 reader.read_cstring()
             })?;
 
         Ok(str)
     }
 
-    fn read_dict(&mut self) -> IOResult<GGValue> {
-        let mut dict = HashMap::new();
+    fn read_dict(&mut self) -> Result<GGValue> {
+        let mut dict = IndexMap::new();
         let len = self.reader.read_u32_le()?;
         for _ in 0..len {
             let key = self.read_table_entry()?;
@@ -161,13 +537,12 @@ impl DirectoryBuilder {
             let _ = dict.insert(key, value);
         }
 
-        let end_marker = self.reader.read_u8()?;
-        assert!(end_marker == (GGValueType::Dictionary as u8));
+        self.read_end_marker(GGValueType::Dictionary)?;
 
         Ok(GGValue::GGDict(dict))
     }
 
-    fn read_list(&mut self) -> IOResult<GGValue> {
+    fn read_list(&mut self) -> Result<GGValue> {
         let mut list = Vec::new();
         let len = self.reader.read_u32_le()?;
         for _ in 0..len {
@@ -175,42 +550,403 @@ impl DirectoryBuilder {
             list.push(value);
         }
 
-        let end_marker = self.reader.read_u8()?;
-        assert!(end_marker == (GGValueType::List as u8));
+        self.read_end_marker(GGValueType::List)?;
 
         Ok(GGValue::GGList(list))
     }
 
-    fn read_string(&mut self) -> IOResult<GGValue> {
+    fn read_end_marker(&mut self, type_: GGValueType) -> Result<()> {
+        let expected = type_ as u8;
+        let end_marker = self.reader.read_u8()?;
+        if end_marker != expected {
+            return Err(Error::BadEndMarker {
+                expected,
+                found: end_marker,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn read_string(&mut self) -> Result<GGValue> {
         let entry = self.read_table_entry()?;
         Ok(GGValue::GGString(entry))
     }
 
-    fn read_number(&mut self) -> IOResult<GGValue> {
+    fn read_integer(&mut self) -> Result<GGValue> {
         let entry = self.read_table_entry()?;
-        let num: f32 = entry
+        let num: i64 = entry
             .parse()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+            .map_err(|_| invalid_value("integer", &entry))?;
+
+        Ok(GGValue::GGInteger(num))
+    }
+
+    fn read_float(&mut self) -> Result<GGValue> {
+        let entry = self.read_table_entry()?;
+        Ok(GGValue::GGFloat(entry.parse()?))
+    }
+
+    fn read_coordinate(&mut self) -> Result<GGValue> {
+        let entry = self.read_table_entry()?;
+        Ok(GGValue::GGCoordinate(entry.parse()?))
+    }
+
+    fn read_coordinate_list(&mut self) -> Result<GGValue> {
+        let entry = self.read_table_entry()?;
+        Ok(GGValue::GGCoordinateList(entry.parse()?))
+    }
 
-        Ok(GGValue::GGNumber(num))
+    fn read_hotspot(&mut self) -> Result<GGValue> {
+        let entry = self.read_table_entry()?;
+        Ok(GGValue::GGHotspot(entry.parse()?))
     }
 
-    fn read_null(&mut self) -> IOResult<GGValue> {
+    fn read_null(&mut self) -> Result<GGValue> {
         Ok(GGValue::GGNull())
     }
 
-    fn read_ggvalue(&mut self) -> IOResult<GGValue> {
-        let type_: GGValueType = self.reader.read_u8()?.into();
+    fn read_ggvalue(&mut self) -> Result<GGValue> {
+        let type_ = GGValueType::try_from(self.reader.read_u8()?)?;
         match type_ {
             GGValueType::Null => self.read_null(),
             GGValueType::Dictionary => self.read_dict(),
             GGValueType::List => self.read_list(),
             GGValueType::String => self.read_string(),
-            GGValueType::Integer => self.read_number(),
-            GGValueType::Float => self.read_number(),
-            GGValueType::Coordinate => self.read_string(),
-            GGValueType::CoordinateList => self.read_string(),
-            GGValueType::Hotspot => self.read_string(),
+            GGValueType::Integer => self.read_integer(),
+            GGValueType::Float => self.read_float(),
+            GGValueType::Coordinate => self.read_coordinate(),
+            GGValueType::CoordinateList => self.read_coordinate_list(),
+            GGValueType::Hotspot => self.read_hotspot(),
+        }
+    }
+}
+
+struct DirectoryWriter {
+    data: Vec<u8>,
+    strings: Vec<String>,
+    string_indices: HashMap<String, u16>,
+}
+
+impl DirectoryWriter {
+    fn write_table_entry(&mut self, str: &str) -> Result<()> {
+        let index = match self.string_indices.get(str) {
+            Some(index) => *index,
+            None => {
+                let index = u16::try_from(self.strings.len()).map_err(|_| Error::TooManyStrings)?;
+                self.strings.push(str.to_string());
+                self.string_indices.insert(str.to_string(), index);
+                index
+            }
+        };
+
+        self.data.write_u16::<LittleEndian>(index)?;
+        Ok(())
+    }
+
+    fn write_dict(&mut self, dict: &IndexMap<String, GGValue>) -> Result<()> {
+        self.data.write_u8(GGValueType::Dictionary as u8)?;
+        self.data.write_u32::<LittleEndian>(dict.len() as u32)?;
+        for (key, value) in dict {
+            self.write_table_entry(key)?;
+            self.write_ggvalue(value)?;
         }
+
+        self.data.write_u8(GGValueType::Dictionary as u8)?;
+        Ok(())
+    }
+
+    fn write_list(&mut self, list: &[GGValue]) -> Result<()> {
+        self.data.write_u8(GGValueType::List as u8)?;
+        self.data.write_u32::<LittleEndian>(list.len() as u32)?;
+        for value in list {
+            self.write_ggvalue(value)?;
+        }
+
+        self.data.write_u8(GGValueType::List as u8)?;
+        Ok(())
+    }
+
+    fn write_typed_string(&mut self, type_: GGValueType, str: &str) -> Result<()> {
+        self.data.write_u8(type_ as u8)?;
+        self.write_table_entry(str)
+    }
+
+    fn write_null(&mut self) -> Result<()> {
+        self.data.write_u8(GGValueType::Null as u8)?;
+        Ok(())
+    }
+
+    fn write_ggvalue(&mut self, value: &GGValue) -> Result<()> {
+        match value {
+            GGValue::GGNull() => self.write_null(),
+            GGValue::GGDict(d) => self.write_dict(d),
+            GGValue::GGList(l) => self.write_list(l),
+            GGValue::GGString(s) => self.write_typed_string(GGValueType::String, s),
+            GGValue::GGInteger(i) => self.write_typed_string(GGValueType::Integer, &i.to_string()),
+            GGValue::GGFloat(f) => self.write_typed_string(GGValueType::Float, f.text()),
+            GGValue::GGCoordinate(c) => self.write_typed_string(GGValueType::Coordinate, c.text()),
+            GGValue::GGCoordinateList(l) => {
+                self.write_typed_string(GGValueType::CoordinateList, l.text())
+            }
+            GGValue::GGHotspot(h) => self.write_typed_string(GGValueType::Hotspot, h.text()),
+        }
+    }
+
+    fn finish(self) -> Result<Vec<u8>> {
+        const HEADER_SIZE: usize = 12;
+
+        let offset_to_table = HEADER_SIZE + self.data.len();
+        // Table type + one offset per string + terminator, followed by the string data marker
+        let mut string_offset = offset_to_table + 1 + (self.strings.len() + 1) * 4 + 1;
+
+        let mut outp = Vec::new();
+        outp.write_u32::<LittleEndian>(0x04030201)?;
+        outp.write_u32::<LittleEndian>(1)?;
+        outp.write_u32::<LittleEndian>(offset_to_table as u32)?;
+        outp.extend_from_slice(&self.data);
+
+        outp.write_u8(7)?;
+        for str in &self.strings {
+            outp.write_u32::<LittleEndian>(string_offset as u32)?;
+            string_offset += str.len() + 1;
+        }
+        outp.write_u32::<LittleEndian>(0xFF_FF_FF_FF)?;
+
+        outp.write_u8(8)?;
+        for str in &self.strings {
+            outp.extend_from_slice(str.as_bytes());
+            outp.write_u8(0)?;
+        }
+
+        Ok(outp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_bytes_round_trip() {
+        let json = br#"{
+            "name": "Melee",
+            "objects": [
+                { "name": "door", "pos": { "$coordinate": "{10,20}" }, "zsort": 5, "scale": 0.5 },
+                { "name": "sign", "pos": { "$coordinate": "{30,40}" }, "zsort": 5, "parent": null },
+                {
+                    "hotspot": { "$hotspot": "{{-10,-20},{10,20.5}}" },
+                    "polygon": { "$coordinate_list": "{1,2};{3,4};{5,6}" }
+                }
+            ],
+            "offset": 1234567891,
+            "flags": []
+        }"#;
+        let value = GGValue::from_json(json).unwrap();
+
+        let bytes = value.to_bytes().unwrap();
+        assert_eq!(&bytes[0..4], &[0x01, 0x02, 0x03, 0x04]);
+
+        let parsed = GGValue::parse(bytes).unwrap();
+        assert_eq!(parsed, value);
+
+        assert_eq!(
+            *parsed
+                .expect_entry("offset")
+                .unwrap()
+                .expect_integer()
+                .unwrap(),
+            1234567891
+        );
+        let objects = parsed
+            .expect_entry("objects")
+            .unwrap()
+            .expect_list()
+            .unwrap();
+        assert_eq!(
+            objects[0].expect_entry("pos").unwrap(),
+            &GGValue::GGCoordinate(GGCoordinate { x: 10.0, y: 20.0 }.into())
+        );
+        assert_eq!(
+            objects[2].expect_entry("hotspot").unwrap(),
+            &GGValue::GGHotspot(
+                GGHotspot {
+                    min: GGCoordinate { x: -10.0, y: -20.0 },
+                    max: GGCoordinate { x: 10.0, y: 20.5 },
+                }
+                .into()
+            )
+        );
+        assert!(matches!(
+            objects[2].expect_entry("polygon").unwrap(),
+            GGValue::GGCoordinateList(l) if l.value().len() == 3
+        ));
+        assert!(matches!(
+            objects[0].expect_entry("missing"),
+            Err(Error::MissingKey(_))
+        ));
+    }
+
+    #[test]
+    fn json_round_trip_is_lossless() {
+        let coordinate = |x, y| GGCoordinate { x, y };
+        let value = GGValue::GGList(vec![
+            GGValue::GGCoordinateList(vec![coordinate(1.0, 2.0)].into()),
+            GGValue::GGCoordinateList(Vec::new().into()),
+            GGValue::GGCoordinate(coordinate(1.0, 2.0).into()),
+            GGValue::GGString("{1,2}".to_string()),
+            GGValue::GGString(String::new()),
+            GGValue::GGHotspot(
+                GGHotspot {
+                    min: coordinate(-1.5, 0.0),
+                    max: coordinate(3.0, 4.25),
+                }
+                .into(),
+            ),
+            GGValue::GGFloat(1.0.into()),
+            GGValue::GGFloat(0.1.into()),
+            GGValue::GGInteger(1),
+            GGValue::GGDict(IndexMap::from([(
+                "$coordinate".to_string(),
+                GGValue::GGInteger(5),
+            )])),
+        ]);
+        let bytes = value.to_bytes().unwrap();
+
+        let json = serde_json::to_vec(&GGValue::parse(bytes.clone()).unwrap()).unwrap();
+        let from_json = GGValue::from_json(&json).unwrap();
+        assert_eq!(from_json, value);
+        assert_eq!(from_json.to_bytes().unwrap(), bytes);
+
+        assert!(matches!(
+            GGValue::from_json(br#"[{"$hotspot": "{1,2}"}]"#),
+            Err(Error::InvalidValue {
+                kind: "hotspot",
+                ..
+            })
+        ));
+        assert!(matches!(
+            GGValue::from_json(br#"{"offset": 9223372036854775808}"#),
+            Err(Error::InvalidValue {
+                kind: "integer",
+                ..
+            })
+        ));
+        assert!(matches!(
+            GGValue::from_json(br#"{"offset": 1"#),
+            Err(Error::Json(_))
+        ));
+    }
+
+    /// Builds a directory the way the game stores it, with the values as the given strings
+    fn directory_bytes(values: &[(GGValueType, &str)]) -> Vec<u8> {
+        let mut data = vec![GGValueType::List as u8];
+        data.extend_from_slice(&(values.len() as u32).to_le_bytes());
+        for (i, (type_, _)) in values.iter().enumerate() {
+            data.push(*type_ as u8);
+            data.extend_from_slice(&(i as u16).to_le_bytes());
+        }
+        data.push(GGValueType::List as u8);
+
+        let offset_to_table = 12 + data.len();
+        let mut string_offset = offset_to_table + 1 + (values.len() + 1) * 4 + 1;
+        let mut bytes = Vec::new();
+        for value in [0x04030201, 1, offset_to_table as u32] {
+            bytes.extend_from_slice(&u32::to_le_bytes(value));
+        }
+        bytes.extend_from_slice(&data);
+        bytes.push(7);
+        for (_, text) in values {
+            bytes.extend_from_slice(&(string_offset as u32).to_le_bytes());
+            string_offset += text.len() + 1;
+        }
+        bytes.extend_from_slice(&0xFF_FF_FF_FFu32.to_le_bytes());
+        bytes.push(8);
+        for (_, text) in values {
+            bytes.extend_from_slice(text.as_bytes());
+            bytes.push(0);
+        }
+        bytes
+    }
+
+    #[test]
+    fn bytes_round_trip_through_json() {
+        let bytes = directory_bytes(&[
+            (GGValueType::Float, "1"),
+            (GGValueType::Float, "0.50"),
+            (GGValueType::Float, "2.5"),
+            (GGValueType::Float, "1e3"),
+            (GGValueType::Float, "nan"),
+            (GGValueType::Coordinate, "{1.0, 2}"),
+            (GGValueType::CoordinateList, "{1,2};{3.50,4}"),
+            (GGValueType::Hotspot, "{{-0,0},{10.0,20}}"),
+            (GGValueType::Integer, "7"),
+        ]);
+
+        let value = GGValue::parse(bytes.clone()).unwrap();
+        assert_eq!(value.to_bytes().unwrap(), bytes);
+
+        let json = serde_json::to_string(&value).unwrap();
+        assert!(json.starts_with(r#"[{"$float":"1"},{"$float":"0.50"},2.5,"#));
+        assert_eq!(
+            GGValue::from_json(json.as_bytes())
+                .unwrap()
+                .to_bytes()
+                .unwrap(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn parse_rejects_corrupt_data() {
+        assert!(matches!(
+            GGValue::parse(vec![0xFF, 0x02, 0x03, 0x04, 0, 0, 0, 0, 0, 0, 0, 0]),
+            Err(Error::BadMagic { .. })
+        ));
+
+        let mut bytes = GGValue::GGList(vec![GGValue::GGNull()]).to_bytes().unwrap();
+        bytes[12] = 42;
+        assert!(matches!(
+            GGValue::parse(bytes),
+            Err(Error::UnknownValueType(42))
+        ));
+
+        assert!(matches!(
+            GGValue::parse(vec![0x01, 0x02]),
+            Err(Error::Io(_))
+        ));
+    }
+
+    #[test]
+    fn dict_keeps_key_order() {
+        let json = br#"{"zsort": 1, "name": "door", "alpha": {"b": 2, "a": 1}}"#;
+        let mut value = GGValue::from_json(json).unwrap();
+
+        let parsed = GGValue::parse(value.to_bytes().unwrap()).unwrap();
+        assert_eq!(
+            serde_json::to_string(&parsed).unwrap(),
+            r#"{"zsort":1,"name":"door","alpha":{"b":2,"a":1}}"#
+        );
+
+        value.sort_keys();
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"alpha":{"a":1,"b":2},"name":"door","zsort":1}"#
+        );
+    }
+
+    #[test]
+    fn to_bytes_deduplicates_strings() {
+        let value = GGValue::GGList(vec![
+            GGValue::GGString("same".to_string()),
+            GGValue::GGString("same".to_string()),
+            GGValue::GGInteger(5),
+            GGValue::GGString("5".to_string()),
+        ]);
+
+        let bytes = value.to_bytes().unwrap();
+        let occurrences = bytes.windows(5).filter(|w| w == b"same\0").count();
+        assert_eq!(occurrences, 1);
+        assert_eq!(bytes.windows(2).filter(|w| w == b"5\0").count(), 1);
     }
 }
@@ -1,4 +1,4 @@
-use std::io::{BufRead, Seek, SeekFrom};
+use std::io::{BufRead, Read, Seek, SeekFrom};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
@@ -10,9 +10,11 @@ pub trait EasyRead: ReadBytesExt + BufRead + Seek {
 
     fn read_cstring(&mut self) -> Result<String, std::io::Error> {
         let mut buf = Vec::new();
-        let len = self.read_until(0, &mut buf)?;
-        buf.resize(len - 1, 0);
-        String::from_utf8(buf).map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+        self.read_until(0, &mut buf)?;
+        if buf.pop() != Some(0) {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        String::from_utf8(buf).map_err(std::io::Error::other)
     }
 
     #[inline]
@@ -20,20 +22,26 @@ pub trait EasyRead: ReadBytesExt + BufRead + Seek {
         self.read_u16::<LittleEndian>()
     }
 
-    fn read_at<F, T>(&mut self, seek: SeekFrom, f: F) -> Result<T, std::io::Error>
+    fn read_at<F, T, E>(&mut self, seek: SeekFrom, f: F) -> Result<T, E>
     where
-        F: Fn(&mut Self) -> Result<T, std::io::Error>,
+        F: Fn(&mut Self) -> Result<T, E>,
+        E: From<std::io::Error>,
     {
-        let tell = self.seek(SeekFrom::Current(0))?;
+        let tell = self.stream_position()?;
         self.seek(seek)?;
         let res = f(self)?;
         self.seek(SeekFrom::Start(tell))?;
         Ok(res)
     }
 
+    /// Reads `count` bytes. The buffer grows as the data is read, so a corrupt count can't
+    /// allocate more than what is left of the stream.
     fn read_bytes(&mut self, count: usize) -> Result<Vec<u8>, std::io::Error> {
-        let mut buffer = vec![0; count];
-        self.read_exact(&mut buffer)?;
+        let mut buffer = Vec::new();
+        Read::take(&mut *self, count as u64).read_to_end(&mut buffer)?;
+        if buffer.len() != count {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
         Ok(buffer)
     }
 }
@@ -41,3 +49,18 @@ pub trait EasyRead: ReadBytesExt + BufRead + Seek {
 /// All types that implement `Read`, `BufRead` and `Seek` get methods defined in `EasyRead`
 /// for free.
 impl<R: std::io::Read + BufRead + Seek + ?Sized> EasyRead for R {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn read_bytes_stops_at_the_end() {
+        let mut reader = Cursor::new(vec![1, 2, 3, 4]);
+        assert_eq!(reader.read_bytes(3).unwrap(), [1, 2, 3]);
+
+        let error = reader.read_bytes(usize::MAX).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt::Display;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    BadMagic {
+        expected: u32,
+        found: u32,
+    },
+    UnknownValueType(u8),
+    BadEndMarker {
+        expected: u8,
+        found: u8,
+    },
+    BadStringIndex(usize),
+    TooManyStrings,
+    UnexpectedValueType(&'static str),
+    InvalidValue {
+        kind: &'static str,
+        value: String,
+    },
+    /// A dictionary entry that is required is missing
+    MissingKey(String),
+    /// An encryption key could not be found in the executable
+    KeyNotFound(&'static str),
+    KeyFile {
+        path: String,
+        source: std::io::Error,
+    },
+    BadKeyLength {
+        name: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    BadYackOpcode {
+        opcode: u8,
+        reason: &'static str,
+    },
+    /// A yack ran this many instructions without anything to show, so it loops forever
+    YackStepLimit(usize),
+    /// A line of yack text that can't be compiled
+    BadYackLine {
+        line: usize,
+        reason: &'static str,
+    },
+    FileNotFound(String),
+    FileExists(String),
+    /// The pack grew past the 4 GiB its u32 directory offset can address
+    PackTooLarge(u64),
+    BadPattern(String),
+    Texture(String),
+    Audio(String),
+    /// The setup header of a Vorbis sample isn't known, so it can't be rebuilt as Ogg Vorbis
+    VorbisSetupNotFound(u32),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Json(e) => write!(f, "JSON error: {}", e),
+            Error::BadMagic { expected, found } => {
+                write!(f, "Bad magic {:#010X}, expected {:#010X}", found, expected)
+            }
+            Error::UnknownValueType(t) => write!(f, "{} is not a known GGValueType", t),
+            Error::BadEndMarker { expected, found } => {
+                write!(f, "Bad end marker {}, expected {}", found, expected)
+            }
+            Error::BadStringIndex(i) => write!(f, "String index {} is out of range", i),
+            Error::TooManyStrings => write!(f, "Too many unique strings for the string table"),
+            Error::UnexpectedValueType(expected) => write!(f, "Expected {}", expected),
+            Error::InvalidValue { kind, value } => write!(f, "'{}' is not a valid {}", value, kind),
+            Error::MissingKey(key) => write!(f, "{} entry not found", key),
+            Error::KeyNotFound(key) => write!(f, "Could not find {} in the executable", key),
+            Error::KeyFile { path, source } => {
+                write!(
+                    f,
+                    "Failed to read {}: {}. Run extract-keys first",
+                    path, source
+                )
+            }
+            Error::BadKeyLength {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} is {} bytes long, expected {} bytes",
+                name, found, expected
+            ),
+            Error::BadYackOpcode { opcode, reason } => {
+                write!(f, "Bad yack opcode {}: {}", opcode, reason)
+            }
+            Error::YackStepLimit(steps) => write!(
+                f,
+                "The yack ran {} instructions without any dialogue, it loops forever",
+                steps
+            ),
+            Error::BadYackLine { line, reason } => {
+                write!(f, "Bad yack line {}: {}", line, reason)
+            }
+            Error::FileNotFound(filename) => write!(f, "{} does not exist in the ggpack", filename),
+            Error::FileExists(filename) => write!(f, "{} already exists in the ggpack", filename),
+            Error::PackTooLarge(size) => write!(
+                f,
+                "The ggpack would be {} bytes, ggpacks can't be larger than 4 GiB",
+                size
+            ),
+            Error::BadPattern(e) => write!(f, "Invalid pattern: {}", e),
+            Error::Texture(e) => write!(f, "Texture error: {}", e),
+            Error::Audio(e) => write!(f, "Audio error: {}", e),
+            Error::VorbisSetupNotFound(crc32) => write!(
+                f,
+                "The Vorbis setup header {:08X} is not known, the sound can't be rebuilt as Ogg Vorbis",
+                crc32
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Json(e) => Some(e),
+            Error::KeyFile { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
@@ -1,197 +1,802 @@
 use wax::{Glob, Pattern};
 
 use crate::{
-    decoder::{self, decode_data, decode_yack_data},
+    decoder::{self, encode_data, encode_yack_data, DataDecoder, YackDecoder},
     directory::GGValue,
     easy_br::EasyRead,
     keys::Keys,
-    ktx_decompressor::KTXDecompressor,
-    yack::parse_yack,
+    text_table::TextTable,
+    yack::{parse_yack, parse_yack_with_texts},
+    Error, Result,
 };
 
 #[cfg(feature = "decompress_ktx")]
-use crate::ktx_decompress;
+use crate::{
+    ktx_decompress,
+    ktx_decompressor::{build_atlas, decompress_ktx, encode_png, KTXDecompressor},
+    sprite_sheet::SpriteSheet,
+};
 
+use byteorder::{LittleEndian, WriteBytesExt};
+use indexmap::IndexMap;
 use std::{
     fs::File,
-    io::{BufReader, Seek, SeekFrom},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Take, Write},
     path::Path,
+    str::FromStr,
 };
 
-pub struct OpenGGPack {
-    reader: BufReader<File>,
+pub struct OpenGGPack<R: Read + Seek = File> {
+    reader: BufReader<R>,
     directory: GGValue,
     keys: Keys,
-    ktx_decompressor: Option<Box<dyn KTXDecompressor>>,
+    pending_files: Vec<(String, Vec<u8>)>,
+    #[cfg(feature = "decompress_ktx")]
+    ktx_decompressor: Box<dyn KTXDecompressor>,
+    ktx_export: KtxExport,
+    text_table: Option<TextTable>,
+}
+
+pub struct GGPackWriter<W: Write + Seek = BufWriter<File>> {
+    writer: W,
+    files: Vec<GGFile>,
+    /// Top-level directory entries other than `files`, in order
+    directory_entries: IndexMap<String, GGValue>,
+    keys: Keys,
 }
 
-#[derive(Debug)]
+/// Reads and decodes a single file from a ggpack, see `OpenGGPack::open_entry`
+pub struct GGFileReader<'a, R: Read> {
+    reader: Take<&'a mut BufReader<R>>,
+    data_decoder: Option<DataDecoder<'a>>,
+    yack_decoder: Option<YackDecoder<'a>>,
+}
+
+/// Which images of an extracted KTX texture are converted to PNG
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KtxExport {
+    /// Only the first mip level of the first face or array layer
+    #[default]
+    First,
+    /// Every mip level, cubemap face and array layer to a separate PNG
+    All,
+    /// Every mip level, cubemap face and array layer packed into a single PNG, with a row per
+    /// mip level and a column per face or array layer
+    Atlas,
+}
+
+impl FromStr for KtxExport {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "first" => Ok(KtxExport::First),
+            "all" => Ok(KtxExport::All),
+            "atlas" => Ok(KtxExport::Atlas),
+            _ => Err(Error::InvalidValue {
+                kind: "KTX export mode",
+                value: s.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct GGFile {
     pub filename: String,
     pub size: usize,
     pub offset: u64,
 }
 
-fn get_ktx_decompressor() -> Option<Box<dyn KTXDecompressor>> {
-    #[cfg(feature = "decompress_ktx")]
-    return Some(Box::new(
-        ktx_decompress::decompress_gl::OpenGLKTXDecompressor::new(),
-    ));
-    #[cfg(not(feature = "decompress_ktx"))]
-    None
+/// Uses the OpenGL decompressor when the `decompress_ktx_gl` feature is enabled and an OpenGL
+/// context can be created, otherwise textures are decoded on the CPU
+#[cfg(feature = "decompress_ktx")]
+fn get_ktx_decompressor() -> Box<dyn KTXDecompressor> {
+    #[cfg(feature = "decompress_ktx_gl")]
+    match ktx_decompress::decompress_gl::OpenGLKTXDecompressor::new() {
+        Ok(decompressor) => return Box::new(decompressor),
+        Err(e) => eprintln!(
+            "OpenGL KTX decompression is unavailable, using software decoding: {}",
+            e
+        ),
+    }
+
+    Box::new(ktx_decompress::decompress_software::SoftwareKTXDecompressor)
 }
 
-impl OpenGGPack {
-    pub fn from_path(pack_path: &str) -> Result<Self, std::io::Error> {
-        let keys = Keys::from_disk();
+impl OpenGGPack<File> {
+    /// Opens a ggpack using the keys in the `keys` directory, see `Keys::from_disk`
+    pub fn from_path(pack_path: &str) -> Result<Self> {
+        let keys = Keys::from_disk()?;
+        Self::open_with_keys(pack_path, &keys)
+    }
 
-        let file = File::open(&Path::new(pack_path)).unwrap();
-        let mut reader = BufReader::new(file);
+    pub fn open_with_keys(pack_path: &str, keys: &Keys) -> Result<Self> {
+        let file = File::open(Path::new(pack_path))?;
+        Self::from_reader(file, keys)
+    }
+}
 
-        let offset = reader
-            .read_u32_le()
-            .expect("Failed to read directory offset") as u64;
-        let size = reader.read_u32_le().expect("Failed to read directory size") as usize;
+impl<R: Read + Seek> OpenGGPack<R> {
+    /// Opens a ggpack from any seekable source, e.g. an in-memory buffer
+    pub fn from_reader(reader: R, keys: &Keys) -> Result<Self> {
+        let keys = keys.clone();
+        let mut reader = BufReader::new(reader);
+
+        let offset = reader.read_u32_le()? as u64;
+        let size = reader.read_u32_le()? as usize;
 
         reader.seek(SeekFrom::Start(offset))?;
         let mut directory_data = reader.read_bytes(size)?;
-        decoder::decode_data(&mut directory_data, &keys.key1, &keys.key2);
+        decoder::decode_data(&mut directory_data, keys.key1(), keys.key2());
 
         Ok(Self {
             reader,
-            directory: GGValue::parse(directory_data).expect("Failed to parse directory"),
+            directory: GGValue::parse(directory_data)?,
             keys,
+            pending_files: Vec::new(),
+            #[cfg(feature = "decompress_ktx")]
             ktx_decompressor: get_ktx_decompressor(),
+            ktx_export: KtxExport::default(),
+            text_table: None,
         })
     }
 
-    pub fn get_files(&self) -> Vec<GGFile> {
-        let rootdict = self.directory.expect_dict();
+    /// Sets which images of KTX textures are converted to PNG when they are extracted
+    pub fn set_ktx_export(&mut self, ktx_export: KtxExport) {
+        self.ktx_export = ktx_export;
+    }
 
-        rootdict
-            .get("files")
-            .expect("files entry not found!")
-            .expect_list()
+    /// Loads the text table of `language`, e.g. `en`, from the `*Text_<language>.tsv` file in
+    /// the ggpack. Decompiled yacks then get the localized lines of their text ids.
+    pub fn set_text_language(&mut self, language: &str) -> Result<()> {
+        self.set_text_table(&format!("*Text_{}.tsv", language))
+    }
+
+    /// Loads the text table from the first file in the ggpack matching `pattern`, for packs
+    /// where it isn't named after the language as `set_text_language` expects
+    pub fn set_text_table(&mut self, pattern: &str) -> Result<()> {
+        let file = self
+            .get_matching_files(pattern)?
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::FileNotFound(pattern.to_string()))?;
+
+        self.text_table = Some(TextTable::from_tsv(&self.read_file(&file)?));
+        Ok(())
+    }
+
+    pub fn get_files(&self) -> Result<Vec<GGFile>> {
+        self.directory
+            .expect_entry("files")?
+            .expect_list()?
             .iter()
             .map(|entry| {
-                let entry_dict = entry.expect_dict();
-
-                let filename = entry_dict
-                    .get("filename")
-                    .expect("filename entry not found!")
-                    .expect_string();
-
-                let offset = (*entry_dict
-                    .get("offset")
-                    .expect("offset entry not found!")
-                    .expect_number()) as u64;
-                let size = *entry_dict
-                    .get("size")
-                    .expect("size entry not found!")
-                    .expect_number() as usize;
-                GGFile {
+                let filename = entry.expect_entry("filename")?.expect_string()?;
+                let offset = *entry.expect_entry("offset")?.expect_integer()?;
+                let offset = u64::try_from(offset).map_err(|_| Error::InvalidValue {
+                    kind: "file offset",
+                    value: offset.to_string(),
+                })?;
+                let size = *entry.expect_entry("size")?.expect_integer()?;
+                let size = usize::try_from(size).map_err(|_| Error::InvalidValue {
+                    kind: "file size",
+                    value: size.to_string(),
+                })?;
+
+                Ok(GGFile {
                     filename: filename.clone(),
                     offset,
                     size,
-                }
+                })
             })
             .collect()
     }
 
-    pub fn list_files(&self) {
-        let file_list = self.get_files();
+    pub fn get_file(&self, filename: &str) -> Result<GGFile> {
+        self.get_files()?
+            .into_iter()
+            .find(|f| f.filename == filename)
+            .ok_or_else(|| Error::FileNotFound(filename.to_string()))
+    }
+
+    /// Returns the files whose names match the glob-pattern `pattern`
+    pub fn get_matching_files(&self, pattern: &str) -> Result<Vec<GGFile>> {
+        let glob = Glob::new(pattern).map_err(|e| Error::BadPattern(e.to_string()))?;
+
+        Ok(self
+            .get_files()?
+            .into_iter()
+            .filter(|f| glob.is_match(&f.filename[..]))
+            .collect())
+    }
+
+    /// Reads a file without decoding it
+    pub fn read_raw(&mut self, file: &GGFile) -> Result<Vec<u8>> {
+        self.reader.seek(SeekFrom::Start(file.offset))?;
+        Ok(self.reader.read_bytes(file.size)?)
+    }
+
+    /// Reads and decodes a file
+    pub fn read_file(&mut self, file: &GGFile) -> Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(file.size);
+        self.open_entry(file)?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    /// Returns a reader that decodes the file while it is read
+    pub fn open_entry(&mut self, file: &GGFile) -> Result<GGFileReader<'_, R>> {
+        self.reader.seek(SeekFrom::Start(file.offset))?;
+
+        let data_decoder = (!file.filename.ends_with(".bank"))
+            .then(|| DataDecoder::new(file.size, self.keys.key1(), self.keys.key2()));
+        let yack_decoder = file
+            .filename
+            .ends_with(".yack")
+            .then(|| YackDecoder::new(self.keys.key3(), &file.filename));
+
+        Ok(GGFileReader {
+            reader: (&mut self.reader).take(file.size as u64),
+            data_decoder,
+            yack_decoder,
+        })
+    }
+
+    pub fn replace_file(&mut self, filename: &str, data: Vec<u8>) -> Result<()> {
+        if !self.get_files()?.iter().any(|f| f.filename == filename) {
+            return Err(Error::FileNotFound(filename.to_string()));
+        }
+
+        self.set_pending_file(filename, data);
+        Ok(())
+    }
+
+    pub fn add_file(&mut self, filename: &str, data: Vec<u8>) -> Result<()> {
+        if self.get_files()?.iter().any(|f| f.filename == filename) {
+            return Err(Error::FileExists(filename.to_string()));
+        }
+
+        self.set_pending_file(filename, data);
+        Ok(())
+    }
+
+    fn set_pending_file(&mut self, filename: &str, data: Vec<u8>) {
+        match self.pending_files.iter_mut().find(|(f, _)| f == filename) {
+            Some((_, pending)) => *pending = data,
+            None => self.pending_files.push((filename.to_string(), data)),
+        }
+    }
+
+    /// Writes a new ggpack to `pack_path` with all replaced and added files applied.
+    /// Untouched files are copied over without being decoded, and the other entries of the
+    /// directory are kept. `pack_path` can't be the pack
+    /// being read, as it is truncated before the files are copied.
+    pub fn write_pack(&mut self, pack_path: &str) -> Result<()> {
+        let writer = GGPackWriter::create(pack_path, self.keys.clone())?;
+        self.write_pack_to(writer)?;
+        Ok(())
+    }
+
+    /// Same as `write_pack`, but writes to an already created `GGPackWriter`
+    pub fn write_pack_to<W: Write + Seek>(&mut self, mut writer: GGPackWriter<W>) -> Result<W> {
+        for (key, value) in self.directory.expect_dict()? {
+            writer.set_directory_entry(key, value.clone());
+        }
+
+        let existing_files = self.get_files()?;
+
+        for file in &existing_files {
+            match self.pending_files.iter().find(|(f, _)| *f == file.filename) {
+                Some((_, data)) => writer.add_file(&file.filename, data)?,
+                None => {
+                    let data = self.read_raw(file)?;
+                    writer.add_raw_file(&file.filename, &data)?;
+                }
+            }
+        }
+
+        for (filename, data) in &self.pending_files {
+            if !existing_files.iter().any(|f| f.filename == *filename) {
+                writer.add_file(filename, data)?;
+            }
+        }
+
+        writer.finish()
+    }
+
+    pub fn list_files(&self) -> Result<()> {
+        let file_list = self.get_files()?;
         let filenames: Vec<&String> = file_list.iter().map(|f| &f.filename).collect();
 
-        println!("{}", serde_json::to_string_pretty(&filenames).unwrap());
+        println!("{}", serde_json::to_string_pretty(&filenames)?);
+        Ok(())
     }
 
-    pub fn extract_files(&mut self, pattern: &str, outpath: &str, decompile_yacks: bool) {
-        let glob = Glob::new(pattern).unwrap();
-        let file_list = self.get_files();
+    pub fn extract_files(
+        &mut self,
+        pattern: &str,
+        outpath: &str,
+        decompile_yacks: bool,
+        sort_keys: bool,
+    ) -> Result<()> {
+        let file_list = self.get_matching_files(pattern)?;
 
-        let mut matched_any = false;
         for file in &file_list {
-            if glob.is_match(&file.filename[..]) {
-                matched_any = true;
-                self.extract_file(file, outpath, decompile_yacks);
-            }
+            self.extract_file(file, outpath, decompile_yacks, sort_keys)?;
         }
 
-        if !matched_any {
+        if file_list.is_empty() {
             println!("No files extracted. The provided pattern '{}' didn't match any files in the archive.", pattern);
         }
+
+        Ok(())
     }
-    pub fn extract_file(&mut self, file: &GGFile, outpath: &str, decompile_yacks: bool) {
+
+    pub fn extract_file(
+        &mut self,
+        file: &GGFile,
+        outpath: &str,
+        decompile_yacks: bool,
+        sort_keys: bool,
+    ) -> Result<()> {
         println!(
             "Extracting {}. Size = {}, offset = {}",
             file.filename, file.size, file.offset
         );
 
-        self.reader
-            .seek(SeekFrom::Start(file.offset))
-            .expect("Failed to seek to offset");
-
-        let mut data = self
-            .reader
-            .read_bytes(file.size)
-            .expect("Failed to read data");
-
-        if file.filename.ends_with(".bank") == false {
-            decode_data(&mut data, &self.keys.key1, &self.keys.key2);
-        }
-
+        let data = self.read_file(file)?;
         let final_path = format!("{}/{}", outpath, file.filename);
 
         if file.filename.ends_with(".yack") {
-            decode_yack_data(&mut data, &self.keys.key3, &file.filename);
-
             if decompile_yacks {
-                let outp = parse_yack(&data).expect("Failed to decompile yack");
+                let outp = match &self.text_table {
+                    Some(texts) => parse_yack_with_texts(&data, texts)?,
+                    None => parse_yack(&data)?,
+                };
 
-                std::fs::write(format!("{}.txt", final_path), outp)
-                    .expect("Failed to write data to disk");
+                std::fs::write(format!("{}.txt", final_path), outp)?;
             } else {
-                std::fs::write(final_path, data)
-                    .expect("Failed to write data to disk");
+                std::fs::write(final_path, data)?;
             }
         } else if file.filename.ends_with(".json")
             || file.filename.ends_with(".wimpy")
             || file.filename.ends_with(".emitter")
         {
-            let expanded = GGValue::parse(data).expect("Failed to expand file");
+            let mut expanded = GGValue::parse(data)?;
+            if sort_keys {
+                expanded.sort_keys();
+            }
 
-            std::fs::write(final_path, serde_json::to_string_pretty(&expanded).unwrap())
-                .expect("Failed to write data to disk");
+            std::fs::write(final_path, serde_json::to_string_pretty(&expanded)?)?;
         } else if file.filename.ends_with(".ktxbz") || file.filename.ends_with(".ktxaz") {
-            self.handle_ktxbz(&data, &final_path);
+            self.handle_ktxbz(&data, &final_path)?;
         } else {
-            std::fs::write(final_path, data).expect("Failed to write data to disk");
+            std::fs::write(final_path, data)?;
         }
+
+        Ok(())
     }
 
-    fn handle_ktxbz(&self, data: &Vec<u8>, final_path: &str) {
+    fn handle_ktxbz(&self, data: &[u8], final_path: &str) -> Result<()> {
         println!("Inflating...");
-        let decompressed = inflate::inflate_bytes_zlib(&data);
+        let decompressed = inflate::inflate_bytes_zlib(data);
 
         match decompressed {
             Ok(data) => {
-                std::fs::write(&final_path, &data).expect("Failed to write data to disk");
+                std::fs::write(final_path, &data)?;
 
                 #[cfg(feature = "decompress_ktx")]
-                if let Some(decompressor) = &self.ktx_decompressor {
-                    println!("Decompressing BPTC texture...");
-                    let mut output_buffer: Vec<u8> = Vec::new();
+                self.write_ktx_pngs(&data, final_path)?;
+            }
+            Err(e) => {
+                eprintln!("Error when inflating, {e:?}, saving raw data.");
+                std::fs::write(final_path, data)?;
+            }
+        }
+
+        Ok(())
+    }
 
-                    decompressor.decompress_ktx(&data, &mut output_buffer);
+    /// Cuts the texture of a sprite sheet into a PNG per frame, using the frame rectangles of
+    /// the sheet json-file `sheet_name`. Frames are written to `outpath` under their names.
+    #[cfg(feature = "decompress_ktx")]
+    pub fn slice_sheet(&mut self, sheet_name: &str, outpath: &str) -> Result<()> {
+        let sheet_file = self.get_file(sheet_name)?;
+        let sheet = SpriteSheet::from_value(&GGValue::parse(self.read_file(&sheet_file)?)?)?;
+
+        let files = self.get_files()?;
+        let texture_names = sheet.texture_names(sheet_name);
+        let texture_file = texture_names
+            .iter()
+            .find_map(|name| files.iter().find(|f| f.filename == *name))
+            .ok_or_else(|| Error::FileNotFound(texture_names.join(" or ")))?;
 
-                    std::fs::write(format!("{}.png", final_path), output_buffer)
-                        .expect("Failed to write data to disk");
+        println!(
+            "Slicing {} frames from {}",
+            sheet.frames.len(),
+            texture_file.filename
+        );
+        let ktx = inflate::inflate_bytes_zlib(&self.read_file(texture_file)?)
+            .map_err(|e| Error::Texture(format!("Failed to inflate texture: {}", e)))?;
+        let texture = decompress_ktx(self.ktx_decompressor.as_ref(), &ktx, false)?.remove(0);
+
+        for frame in &sheet.frames {
+            let (width, height, pixels) =
+                frame.cut(&texture.pixels, texture.width, texture.height)?;
+
+            let path = format!("{}/{}", outpath, frame.output_path()?);
+            if let Some(parent) = Path::new(&path).parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+
+            let mut output_buffer: Vec<u8> = Vec::new();
+            encode_png(&mut output_buffer, width, height, &pixels)?;
+            std::fs::write(path, output_buffer)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "decompress_ktx")]
+    fn write_ktx_pngs(&self, data: &[u8], final_path: &str) -> Result<()> {
+        println!("Decompressing texture...");
+        let all_images = self.ktx_export != KtxExport::First;
+        let images = decompress_ktx(self.ktx_decompressor.as_ref(), data, all_images)?;
+
+        if self.ktx_export == KtxExport::Atlas {
+            let (width, height, pixels) = build_atlas(&images);
+            let mut output_buffer: Vec<u8> = Vec::new();
+            encode_png(&mut output_buffer, width, height, &pixels)?;
+            std::fs::write(format!("{}.atlas.png", final_path), output_buffer)?;
+            return Ok(());
+        }
+
+        let layers = images.iter().map(|i| i.layer + 1).max().unwrap_or(1);
+        for image in &images {
+            let mut name = final_path.to_string();
+            if self.ktx_export == KtxExport::All {
+                name.push_str(&format!(".mip{}", image.level));
+                if layers > 1 {
+                    name.push_str(&format!(".layer{}", image.layer));
                 }
             }
-            Err(e) => {
-                eprintln!("Error when inflating, {e:?}, saving raw data.");
-                std::fs::write(final_path, data).expect("Failed to write data to disk");
+
+            let mut output_buffer: Vec<u8> = Vec::new();
+            encode_png(&mut output_buffer, image.width, image.height, &image.pixels)?;
+            std::fs::write(format!("{}.png", name), output_buffer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for GGFileReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.reader.read(buf)?;
+
+        if let Some(decoder) = &mut self.data_decoder {
+            decoder.decode(&mut buf[..len]);
+        }
+        if let Some(decoder) = &mut self.yack_decoder {
+            decoder.decode(&mut buf[..len]);
+        }
+
+        Ok(len)
+    }
+}
+
+/// The directory offset and size in the pack header are u32, so everything written to a pack
+/// has to end below 4 GiB
+fn check_pack_size(end: u64) -> Result<()> {
+    u32::try_from(end)
+        .map(|_| ())
+        .map_err(|_| Error::PackTooLarge(end))
+}
+
+impl GGPackWriter {
+    pub fn create(pack_path: &str, keys: Keys) -> Result<Self> {
+        let file = File::create(Path::new(pack_path))?;
+        Self::from_writer(BufWriter::new(file), keys)
+    }
+}
+
+impl<W: Write + Seek> GGPackWriter<W> {
+    pub fn from_writer(mut writer: W, keys: Keys) -> Result<Self> {
+        // Directory offset and size, filled in by finish()
+        writer.write_u32::<LittleEndian>(0)?;
+        writer.write_u32::<LittleEndian>(0)?;
+
+        Ok(Self {
+            writer,
+            files: Vec::new(),
+            directory_entries: IndexMap::new(),
+            keys,
+        })
+    }
+
+    /// Sets a top-level entry of the directory. The `files` entry is always replaced by the
+    /// list of added files.
+    pub fn set_directory_entry(&mut self, key: &str, value: GGValue) {
+        self.directory_entries.insert(key.to_string(), value);
+    }
+
+    pub fn add_file(&mut self, filename: &str, data: &[u8]) -> Result<()> {
+        let mut data = data.to_vec();
+
+        if filename.ends_with(".yack") {
+            encode_yack_data(&mut data, self.keys.key3(), filename);
+        }
+
+        if !filename.ends_with(".bank") {
+            encode_data(&mut data, self.keys.key1(), self.keys.key2());
+        }
+
+        self.add_raw_file(filename, &data)
+    }
+
+    /// Adds a file whose data is already encoded, e.g. when copied from another ggpack.
+    pub fn add_raw_file(&mut self, filename: &str, data: &[u8]) -> Result<()> {
+        let offset = self.writer.stream_position()?;
+        check_pack_size(offset + data.len() as u64)?;
+
+        self.writer.write_all(data)?;
+        self.files.push(GGFile {
+            filename: filename.to_string(),
+            size: data.len(),
+            offset,
+        });
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        let files = self
+            .files
+            .iter()
+            .map(|file| {
+                GGValue::GGDict(IndexMap::from([
+                    (
+                        "filename".to_string(),
+                        GGValue::GGString(file.filename.clone()),
+                    ),
+                    ("offset".to_string(), GGValue::GGInteger(file.offset as i64)),
+                    ("size".to_string(), GGValue::GGInteger(file.size as i64)),
+                ]))
+            })
+            .collect();
+        let mut directory = std::mem::take(&mut self.directory_entries);
+        directory.insert("files".to_string(), GGValue::GGList(files));
+        let directory = GGValue::GGDict(directory);
+
+        let mut directory_data = directory.to_bytes()?;
+        encode_data(&mut directory_data, self.keys.key1(), self.keys.key2());
+
+        let offset = self.writer.stream_position()?;
+        let size = directory_data.len() as u64;
+        check_pack_size(offset + size)?;
+        self.writer.write_all(&directory_data)?;
+
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_u32::<LittleEndian>(offset as u32)?;
+        self.writer.write_u32::<LittleEndian>(size as u32)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn build_pack(keys: &Keys, files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = GGPackWriter::from_writer(Cursor::new(Vec::new()), keys.clone()).unwrap();
+        for (filename, data) in files {
+            writer.add_file(filename, data).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn open_pack_from_memory() {
+        let keys = Keys::for_tests();
+        let data = build_pack(&keys, &[("Hello.txt", b"Hello world"), ("Empty.bin", b"")]);
+
+        let pack = OpenGGPack::from_reader(Cursor::new(data), &keys).unwrap();
+        let files = pack.get_files().unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].filename, "Hello.txt");
+        assert_eq!(files[0].size, 11);
+        assert_eq!(files[0].offset, 8);
+        assert_eq!(files[1].filename, "Empty.bin");
+        assert_eq!(files[1].size, 0);
+    }
+
+    #[test]
+    fn read_files_from_memory() {
+        let keys = Keys::for_tests();
+        let yack = b"\x01\x02\x03 not really a yack".repeat(20);
+        let data = build_pack(
+            &keys,
+            &[
+                ("Hello.txt", b"Hello world"),
+                ("Dialog.yack", &yack),
+                ("Music.bank", b"bank data"),
+            ],
+        );
+
+        let mut pack = OpenGGPack::from_reader(Cursor::new(data), &keys).unwrap();
+
+        let hello = pack.get_file("Hello.txt").unwrap();
+        assert_eq!(pack.read_file(&hello).unwrap(), b"Hello world");
+        assert_ne!(pack.read_raw(&hello).unwrap(), b"Hello world");
+
+        let dialog = pack.get_file("Dialog.yack").unwrap();
+        let mut entry = pack.open_entry(&dialog).unwrap();
+        let mut read = Vec::new();
+        let mut chunk = [0u8; 13];
+        loop {
+            let len = entry.read(&mut chunk).unwrap();
+            if len == 0 {
+                break;
+            }
+            read.extend_from_slice(&chunk[..len]);
+        }
+        assert_eq!(read, yack);
+
+        let bank = pack.get_file("Music.bank").unwrap();
+        assert_eq!(pack.read_raw(&bank).unwrap(), b"bank data");
+        assert_eq!(pack.read_file(&bank).unwrap(), b"bank data");
+
+        assert!(matches!(
+            pack.get_file("Missing.txt"),
+            Err(Error::FileNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn load_text_tables() {
+        let keys = Keys::for_tests();
+        let data = build_pack(
+            &keys,
+            &[
+                ("GameText_de.tsv", b"id\ttext\n1\tHallo\n"),
+                ("Strings_en.tsv", b"id\ttext\n1\tHello\n"),
+            ],
+        );
+        let mut pack = OpenGGPack::from_reader(Cursor::new(data), &keys).unwrap();
+
+        pack.set_text_language("de").unwrap();
+        assert_eq!(pack.text_table.as_ref().unwrap().get("@1"), Some("Hallo"));
+
+        // Text tables named otherwise are loaded by name
+        assert!(matches!(
+            pack.set_text_language("en"),
+            Err(Error::FileNotFound(pattern)) if pattern == "*Text_en.tsv"
+        ));
+        pack.set_text_table("Strings_en.tsv").unwrap();
+        assert_eq!(pack.text_table.as_ref().unwrap().get("@1"), Some("Hello"));
+    }
+
+    #[test]
+    fn replace_and_add_files_in_memory() {
+        let keys = Keys::for_tests();
+        let data = build_pack(&keys, &[("A.txt", b"aaa"), ("B.txt", b"bbb")]);
+
+        let mut pack = OpenGGPack::from_reader(Cursor::new(data), &keys).unwrap();
+        pack.replace_file("A.txt", b"replaced".to_vec()).unwrap();
+        pack.add_file("C.txt", b"added".to_vec()).unwrap();
+        assert!(matches!(
+            pack.replace_file("D.txt", Vec::new()),
+            Err(Error::FileNotFound(_))
+        ));
+        assert!(matches!(
+            pack.add_file("B.txt", Vec::new()),
+            Err(Error::FileExists(_))
+        ));
+
+        let writer = GGPackWriter::from_writer(Cursor::new(Vec::new()), keys.clone()).unwrap();
+        let data = pack.write_pack_to(writer).unwrap().into_inner();
+
+        let pack = OpenGGPack::from_reader(Cursor::new(data), &keys).unwrap();
+        let files = pack.get_files().unwrap();
+        let names: Vec<_> = files.iter().map(|f| f.filename.as_str()).collect();
+        assert_eq!(names, ["A.txt", "B.txt", "C.txt"]);
+        assert_eq!(files[0].size, 8);
+        assert_eq!(files[2].size, 5);
+    }
+
+    #[test]
+    fn reject_negative_offsets_and_sizes() {
+        let keys = Keys::for_tests();
+        for (offset, size, kind) in [(-1, 0, "file offset"), (8, -1, "file size")] {
+            let directory = GGValue::GGDict(IndexMap::from([(
+                "files".to_string(),
+                GGValue::GGList(vec![GGValue::GGDict(IndexMap::from([
+                    (
+                        "filename".to_string(),
+                        GGValue::GGString("A.txt".to_string()),
+                    ),
+                    ("offset".to_string(), GGValue::GGInteger(offset)),
+                    ("size".to_string(), GGValue::GGInteger(size)),
+                ]))]),
+            )]));
+            let mut directory_data = directory.to_bytes().unwrap();
+            encode_data(&mut directory_data, keys.key1(), keys.key2());
+
+            let mut data = 8u32.to_le_bytes().to_vec();
+            data.extend_from_slice(&(directory_data.len() as u32).to_le_bytes());
+            data.extend_from_slice(&directory_data);
+
+            let pack = OpenGGPack::from_reader(Cursor::new(data), &keys).unwrap();
+            assert!(matches!(
+                pack.get_files(),
+                Err(Error::InvalidValue { kind: k, .. }) if k == kind
+            ));
+        }
+    }
+
+    #[test]
+    fn keep_other_directory_entries() {
+        let keys = Keys::for_tests();
+        let mut writer = GGPackWriter::from_writer(Cursor::new(Vec::new()), keys.clone()).unwrap();
+        writer.set_directory_entry("guid", GGValue::GGString("abc".to_string()));
+        writer.set_directory_entry("files", GGValue::GGNull());
+        writer.set_directory_entry("version", GGValue::GGInteger(2));
+        writer.add_file("A.txt", b"aaa").unwrap();
+        let data = writer.finish().unwrap().into_inner();
+
+        let mut pack = OpenGGPack::from_reader(Cursor::new(data), &keys).unwrap();
+        pack.replace_file("A.txt", b"replaced".to_vec()).unwrap();
+        let writer = GGPackWriter::from_writer(Cursor::new(Vec::new()), keys.clone()).unwrap();
+        let data = pack.write_pack_to(writer).unwrap().into_inner();
+
+        let pack = OpenGGPack::from_reader(Cursor::new(data), &keys).unwrap();
+        let directory = pack.directory.expect_dict().unwrap();
+        let keys: Vec<_> = directory.keys().map(|k| k.as_str()).collect();
+        assert_eq!(keys, ["guid", "files", "version"]);
+        assert_eq!(directory["guid"], GGValue::GGString("abc".to_string()));
+        assert_eq!(directory["version"], GGValue::GGInteger(2));
+        assert_eq!(pack.get_file("A.txt").unwrap().size, 8);
+    }
+
+    /// Discards everything written to it, so tests can pretend to write huge packs
+    struct NullWriter {
+        position: u64,
+    }
+
+    impl Write for NullWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.position += buf.len() as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for NullWriter {
+        fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+            if let SeekFrom::Start(position) = pos {
+                self.position = position;
             }
+            Ok(self.position)
         }
     }
+
+    #[test]
+    fn reject_packs_over_4_gib() {
+        let keys = Keys::for_tests();
+        let mut writer = GGPackWriter::from_writer(NullWriter { position: 0 }, keys).unwrap();
+
+        writer.writer.position = u32::MAX as u64 - 4;
+        writer.add_raw_file("Fits.bin", &[0; 4]).unwrap();
+        assert!(matches!(
+            writer.add_raw_file("TooBig.bin", &[0; 2]),
+            Err(Error::PackTooLarge(0x1_0000_0001))
+        ));
+        assert!(matches!(writer.finish(), Err(Error::PackTooLarge(_))));
+    }
 }
@@ -1,14 +1,44 @@
 use std::{fs::File, io::Read, io::Seek, io::SeekFrom, path::Path};
 
+use crate::{Error, Result};
+
+const KEY1_LEN: usize = 65536;
+const KEY2_LEN: usize = 256;
+const KEY3_LEN: usize = 1024;
+
+/// The encryption keys of the ggpacks. The fields are private so the keys always have the
+/// lengths the decoders index them with.
+#[derive(Clone)]
 pub struct Keys {
-    pub key1: Vec<u8>,
-    pub key2: Vec<u8>,
-    pub key3: Vec<u8>,
+    key1: Vec<u8>,
+    key2: Vec<u8>,
+    key3: Vec<u8>,
 }
 
 impl Keys {
-    pub fn extract_from_exe(exe_path: &str) -> Result<Self, std::io::Error> {
-        let mut ef = File::open(&Path::new(exe_path)).unwrap();
+    /// Keys read from somewhere else than the executable or a keys directory
+    pub fn new(key1: Vec<u8>, key2: Vec<u8>, key3: Vec<u8>) -> Result<Self> {
+        check_key_length(&key1, "key1", KEY1_LEN)?;
+        check_key_length(&key2, "key2", KEY2_LEN)?;
+        check_key_length(&key3, "key3", KEY3_LEN)?;
+
+        Ok(Self { key1, key2, key3 })
+    }
+
+    pub fn key1(&self) -> &[u8] {
+        &self.key1
+    }
+
+    pub fn key2(&self) -> &[u8] {
+        &self.key2
+    }
+
+    pub fn key3(&self) -> &[u8] {
+        &self.key3
+    }
+
+    pub fn extract_from_exe(exe_path: &str) -> Result<Self> {
+        let mut ef = File::open(Path::new(exe_path))?;
         let mut exe_data = Vec::new();
         ef.seek(SeekFrom::Start(4000000))?;
         ef.read_to_end(&mut exe_data)?;
@@ -18,7 +48,8 @@ impl Keys {
                 0xF7, 0xEC, 0x7E, 0xB6, 0xE3, 0x42, 0x5C, 0x36, 0x55, 0x5E, 0xA2, 0x97, 0xC0, 0x1E,
                 0xBE, 0x2C,
             ],
-            65536,
+            KEY1_LEN,
+            "key1",
         )?;
         let key2 = read_key(
             &exe_data,
@@ -26,7 +57,8 @@ impl Keys {
                 0xD5, 0x7D, 0xFB, 0x4D, 0x51, 0xF5, 0x5E, 0xF4, 0xAA, 0x0B, 0x8A, 0x7E, 0x00, 0x8D,
                 0xCB, 0x66,
             ],
-            256,
+            KEY2_LEN,
+            "key2",
         )?;
         let key3 = read_key(
             &exe_data,
@@ -34,32 +66,111 @@ impl Keys {
                 0x1F, 0xB5, 0xD6, 0xE7, 0xA2, 0x88, 0x8D, 0x75, 0xC1, 0xC4, 0xB9, 0x6B, 0x03, 0x04,
                 0x56, 0xD3,
             ],
-            1024,
+            KEY3_LEN,
+            "key3",
         )?;
 
         Ok(Self { key1, key2, key3 })
     }
 
-    pub fn from_disk() -> Self {
-        let key1 = std::fs::read("keys/key1.bin")
-            .expect("Failed to read keys/key1.bin. Run extract-keys first");
+    /// Reads the keys from the `keys` directory relative to the current working directory
+    pub fn from_disk() -> Result<Self> {
+        Self::from_dir("keys")
+    }
+
+    /// Reads key1.bin, key2.bin and key3.bin, as written by `save_to_dir`, from `keys_dir`
+    pub fn from_dir(keys_dir: &str) -> Result<Self> {
+        let keys_dir = Path::new(keys_dir);
+        let key1 = read_key_file(&keys_dir.join("key1.bin"), "key1", KEY1_LEN)?;
+        let key2 = read_key_file(&keys_dir.join("key2.bin"), "key2", KEY2_LEN)?;
+        let key3 = read_key_file(&keys_dir.join("key3.bin"), "key3", KEY3_LEN)?;
 
-        let key2 = std::fs::read("keys/key2.bin")
-            .expect("Failed to read keys/key2.bin. Run extract-keys first");
+        Ok(Self { key1, key2, key3 })
+    }
 
-        let key3 = std::fs::read("keys/key3.bin")
-            .expect("Failed to read keys/key3.bin. Run extract-keys first");
+    pub fn save_to_dir(&self, keys_dir: &str) -> Result<()> {
+        let keys_dir = Path::new(keys_dir);
+        std::fs::create_dir_all(keys_dir)?;
+        std::fs::write(keys_dir.join("key1.bin"), &self.key1)?;
+        std::fs::write(keys_dir.join("key2.bin"), &self.key2)?;
+        std::fs::write(keys_dir.join("key3.bin"), &self.key3)?;
 
-        Self { key1, key2, key3 }
+        Ok(())
     }
 }
 
-fn read_key(exe_data: &[u8], key_data: &[u8], len: usize) -> Result<Vec<u8>, std::io::Error> {
-    let pos = find_pos(exe_data, key_data).unwrap();
-    let data = &exe_data[pos..pos + len];
+#[cfg(test)]
+impl Keys {
+    /// Deterministic pseudo-random keys with the right lengths, for tests that need to
+    /// encode and decode data
+    pub(crate) fn for_tests() -> Self {
+        let mut state: u32 = 0x1234_5678;
+        let mut key = |len| {
+            (0..len)
+                .map(|_| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 24) as u8
+                })
+                .collect()
+        };
+
+        Self {
+            key1: key(KEY1_LEN),
+            key2: key(KEY2_LEN),
+            key3: key(KEY3_LEN),
+        }
+    }
+}
+
+fn read_key_file(path: &Path, name: &'static str, len: usize) -> Result<Vec<u8>> {
+    let key = std::fs::read(path).map_err(|source| Error::KeyFile {
+        path: path.display().to_string(),
+        source,
+    })?;
+
+    check_key_length(&key, name, len)?;
+    Ok(key)
+}
+
+fn check_key_length(key: &[u8], name: &'static str, len: usize) -> Result<()> {
+    if key.len() != len {
+        return Err(Error::BadKeyLength {
+            name,
+            expected: len,
+            found: key.len(),
+        });
+    }
+
+    Ok(())
+}
+
+fn read_key(exe_data: &[u8], key_data: &[u8], len: usize, name: &'static str) -> Result<Vec<u8>> {
+    let data = find_pos(exe_data, key_data)
+        .and_then(|pos| exe_data.get(pos..pos + len))
+        .ok_or(Error::KeyNotFound(name))?;
     Ok(data.to_vec())
 }
 
 fn find_pos(data: &[u8], find: &[u8]) -> Option<usize> {
     data.windows(find.len()).position(|data| data == find)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_checks_key_lengths() {
+        let keys = Keys::new(vec![1; KEY1_LEN], vec![2; KEY2_LEN], vec![3; KEY3_LEN]).unwrap();
+        assert_eq!(keys.key2(), [2; KEY2_LEN]);
+
+        assert!(matches!(
+            Keys::new(vec![1; KEY1_LEN], vec![2; 16], vec![3; KEY3_LEN]),
+            Err(Error::BadKeyLength {
+                name: "key2",
+                expected: KEY2_LEN,
+                found: 16,
+            })
+        ));
+    }
+}
@@ -0,0 +1,253 @@
+use crate::ktx_decompress::decompress_bptc::WEIGHTS4;
+
+/// Compresses RGBA8 pixels to BC7. Every block is encoded with mode 6, a single subset with
+/// RGBA endpoints and 4 bit indices. This handles gradients and alpha well, but blocks with
+/// several distinct colors lose more detail than with a full mode search.
+pub fn encode_bc7(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let width = width as usize;
+    let height = height as usize;
+    let blocks_x = width.div_ceil(4);
+    let blocks_y = height.div_ceil(4);
+
+    let mut output = Vec::with_capacity(blocks_x * blocks_y * 16);
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            let mut pixels = [[0; 4]; 16];
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                // Blocks on the right and bottom edges repeat the last column or row
+                let x = (block_x * 4 + i % 4).min(width - 1);
+                let y = (block_y * 4 + i / 4).min(height - 1);
+                let offset = (y * width + x) * 4;
+                pixel.copy_from_slice(&rgba[offset..offset + 4]);
+            }
+
+            output.extend_from_slice(&encode_bc7_block(&pixels));
+        }
+    }
+
+    output
+}
+
+/// Mode 6 endpoints, with the p-bit as the lowest bit of every channel
+struct Mode6Block {
+    endpoints: [[u8; 4]; 2],
+    indices: [u8; 16],
+    error: u32,
+}
+
+fn encode_bc7_block(pixels: &[[u8; 4]; 16]) -> [u8; 16] {
+    let (low, high) = principal_endpoints(pixels);
+    let mut best = quantize_mode6(pixels, low, high);
+
+    // Fitting the endpoints to the chosen indices usually lowers the error a bit further
+    for _ in 0..2 {
+        let Some((low, high)) = fit_endpoints(pixels, &best.indices) else {
+            break;
+        };
+
+        let candidate = quantize_mode6(pixels, low, high);
+        if candidate.error >= best.error {
+            break;
+        }
+        best = candidate;
+    }
+
+    pack_mode6(best)
+}
+
+/// Returns the extremes of the pixels along the axis with the largest variance
+fn principal_endpoints(pixels: &[[u8; 4]; 16]) -> ([f32; 4], [f32; 4]) {
+    let mut mean = [0.0; 4];
+    for pixel in pixels {
+        for channel in 0..4 {
+            mean[channel] += pixel[channel] as f32 / 16.0;
+        }
+    }
+
+    let mut covariance = [[0.0f32; 4]; 4];
+    for pixel in pixels {
+        let d = [0, 1, 2, 3].map(|channel| pixel[channel] as f32 - mean[channel]);
+        for (row, covariance) in covariance.iter_mut().enumerate() {
+            for (column, value) in covariance.iter_mut().enumerate() {
+                *value += d[row] * d[column];
+            }
+        }
+    }
+
+    // Power iteration converges on the eigenvector with the largest eigenvalue
+    let mut axis = [1.0f32; 4];
+    for _ in 0..8 {
+        let next = covariance.map(|row| row.iter().zip(axis).map(|(c, a)| c * a).sum::<f32>());
+        let length = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if length < 1e-6 {
+            return (mean, mean);
+        }
+        axis = next.map(|v| v / length);
+    }
+
+    let (mut min, mut max) = (f32::MAX, f32::MIN);
+    for pixel in pixels {
+        let t: f32 = (0..4)
+            .map(|channel| (pixel[channel] as f32 - mean[channel]) * axis[channel])
+            .sum();
+        min = min.min(t);
+        max = max.max(t);
+    }
+
+    (
+        [0, 1, 2, 3].map(|channel| mean[channel] + axis[channel] * min),
+        [0, 1, 2, 3].map(|channel| mean[channel] + axis[channel] * max),
+    )
+}
+
+/// Least squares fit of the endpoints that best reproduce the pixels with the given indices
+fn fit_endpoints(pixels: &[[u8; 4]; 16], indices: &[u8; 16]) -> Option<([f32; 4], [f32; 4])> {
+    let (mut aa, mut ab, mut bb) = (0.0f32, 0.0f32, 0.0f32);
+    let mut ax = [0.0f32; 4];
+    let mut bx = [0.0f32; 4];
+
+    for (pixel, index) in pixels.iter().zip(indices) {
+        let t = WEIGHTS4[*index as usize] as f32 / 64.0;
+        let s = 1.0 - t;
+        aa += s * s;
+        ab += s * t;
+        bb += t * t;
+        for channel in 0..4 {
+            ax[channel] += s * pixel[channel] as f32;
+            bx[channel] += t * pixel[channel] as f32;
+        }
+    }
+
+    let determinant = aa * bb - ab * ab;
+    if determinant.abs() < 1e-6 {
+        return None;
+    }
+
+    let low = [0, 1, 2, 3].map(|c| ((bb * ax[c] - ab * bx[c]) / determinant).clamp(0.0, 255.0));
+    let high = [0, 1, 2, 3].map(|c| ((aa * bx[c] - ab * ax[c]) / determinant).clamp(0.0, 255.0));
+    Some((low, high))
+}
+
+/// Quantizes the endpoints to 7 bits and a p-bit, trying every p-bit combination
+fn quantize_mode6(pixels: &[[u8; 4]; 16], low: [f32; 4], high: [f32; 4]) -> Mode6Block {
+    [[0, 0], [0, 1], [1, 0], [1, 1]]
+        .into_iter()
+        .map(|p_bits| {
+            let mut endpoints = [[0; 4]; 2];
+            for ((endpoint, value), p_bit) in endpoints.iter_mut().zip([low, high]).zip(p_bits) {
+                for channel in 0..4 {
+                    let quantized = ((value[channel] - p_bit as f32) / 2.0)
+                        .round()
+                        .clamp(0.0, 127.0);
+                    endpoint[channel] = ((quantized as u8) << 1) | p_bit;
+                }
+            }
+
+            let (indices, error) = select_indices(pixels, &endpoints);
+            Mode6Block {
+                endpoints,
+                indices,
+                error,
+            }
+        })
+        .min_by_key(|block| block.error)
+        .unwrap()
+}
+
+fn select_indices(pixels: &[[u8; 4]; 16], endpoints: &[[u8; 4]; 2]) -> ([u8; 16], u32) {
+    let palette = WEIGHTS4.map(|weight| {
+        [0, 1, 2, 3].map(|channel| {
+            let e0 = endpoints[0][channel] as i32;
+            let e1 = endpoints[1][channel] as i32;
+            ((64 - weight) * e0 + weight * e1 + 32) >> 6
+        })
+    });
+
+    let mut indices = [0; 16];
+    let mut total_error = 0;
+    for (pixel, index) in pixels.iter().zip(indices.iter_mut()) {
+        let (best_index, error) = palette
+            .iter()
+            .map(|color| {
+                (0..4)
+                    .map(|channel| (color[channel] - pixel[channel] as i32).pow(2) as u32)
+                    .sum::<u32>()
+            })
+            .enumerate()
+            .min_by_key(|(_, error)| *error)
+            .unwrap();
+
+        *index = best_index as u8;
+        total_error += error;
+    }
+
+    (indices, total_error)
+}
+
+fn pack_mode6(mut block: Mode6Block) -> [u8; 16] {
+    // The most significant index bit of the first pixel is implicitly zero
+    if block.indices[0] >= 8 {
+        block.endpoints.swap(0, 1);
+        block.indices = block.indices.map(|index| 15 - index);
+    }
+
+    let mut bits: u128 = 1 << 6;
+    let mut position = 7;
+    let mut write = |value: u8, count: u32| {
+        bits |= (value as u128) << position;
+        position += count;
+    };
+
+    for channel in 0..4 {
+        for endpoint in &block.endpoints {
+            write(endpoint[channel] >> 1, 7);
+        }
+    }
+    for endpoint in &block.endpoints {
+        write(endpoint[0] & 1, 1);
+    }
+    for (i, index) in block.indices.iter().enumerate() {
+        write(*index, if i == 0 { 3 } else { 4 });
+    }
+
+    bits.to_le_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ktx_decompress::decompress_bptc::{decode_bptc, GL_COMPRESSED_RGBA_BPTC_UNORM};
+
+    fn max_error(rgba: &[u8], width: u32, height: u32) -> u8 {
+        let encoded = encode_bc7(rgba, width, height);
+        assert_eq!(
+            encoded.len(),
+            width.div_ceil(4) as usize * height.div_ceil(4) as usize * 16
+        );
+
+        let decoded = decode_bptc(&encoded, width, height, GL_COMPRESSED_RGBA_BPTC_UNORM).unwrap();
+        rgba.iter()
+            .zip(&decoded)
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn gradient_round_trip() {
+        let (width, height) = (10, 7);
+        let rgba: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| x + 2 * y))
+            .flat_map(|t| [t * 8, 255 - t * 6, t * 3, 255 - t * 2])
+            .map(|v| v as u8)
+            .collect();
+
+        assert!(max_error(&rgba, width, height) <= 6);
+    }
+
+    #[test]
+    fn solid_color_round_trip() {
+        let rgba = [17, 128, 250, 3].repeat(16);
+        assert!(max_error(&rgba, 4, 4) <= 1);
+    }
+}
@@ -0,0 +1 @@
+pub mod compress_bptc;
\ No newline at end of file
@@ -0,0 +1,1043 @@
+use super::decompress_software::{decode_blocks, unsupported_format};
+use crate::Result;
+
+pub const GL_COMPRESSED_RGBA_ASTC_4X4_KHR: u32 = 0x93B0;
+pub const GL_COMPRESSED_RGBA_ASTC_12X12_KHR: u32 = 0x93BD;
+pub const GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4_KHR: u32 = 0x93D0;
+pub const GL_COMPRESSED_SRGB8_ALPHA8_ASTC_12X12_KHR: u32 = 0x93DD;
+
+/// Block footprints in the order of the OpenGL formats
+const FOOTPRINTS: [(usize, usize); 14] = [
+    (4, 4),
+    (5, 4),
+    (5, 5),
+    (6, 5),
+    (6, 6),
+    (8, 5),
+    (8, 6),
+    (8, 8),
+    (10, 5),
+    (10, 6),
+    (10, 8),
+    (10, 10),
+    (12, 10),
+    (12, 12),
+];
+
+/// Color of blocks and pixels that can't be decoded, e.g. HDR ones
+const ERROR_COLOR: [u8; 4] = [255, 0, 255, 255];
+
+/// Returns the block footprint and if the colors are sRGB
+fn astc_format(gl_internal_format: u32) -> Option<((usize, usize), bool)> {
+    match gl_internal_format {
+        GL_COMPRESSED_RGBA_ASTC_4X4_KHR..=GL_COMPRESSED_RGBA_ASTC_12X12_KHR => Some((
+            FOOTPRINTS[(gl_internal_format - GL_COMPRESSED_RGBA_ASTC_4X4_KHR) as usize],
+            false,
+        )),
+        GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4_KHR..=GL_COMPRESSED_SRGB8_ALPHA8_ASTC_12X12_KHR => {
+            Some((
+                FOOTPRINTS[(gl_internal_format - GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4_KHR) as usize],
+                true,
+            ))
+        }
+        _ => None,
+    }
+}
+
+pub fn is_astc(gl_internal_format: u32) -> bool {
+    astc_format(gl_internal_format).is_some()
+}
+
+/// Decodes an ASTC compressed image to RGBA8 pixels. Only the LDR profile is supported,
+/// HDR blocks and partitions decode to magenta.
+pub fn decode_astc(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    gl_internal_format: u32,
+) -> Result<Vec<u8>> {
+    let ((block_width, block_height), srgb) =
+        astc_format(gl_internal_format).ok_or_else(|| unsupported_format(gl_internal_format))?;
+
+    decode_blocks(
+        data,
+        width,
+        height,
+        (block_width, block_height, 16),
+        |block, out| {
+            if decode_astc_block(block, block_width, block_height, srgb, out).is_none() {
+                out.fill(ERROR_COLOR);
+            }
+        },
+    )
+}
+
+/// Integer sequence encoding of values with the given number of levels
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum IseEncoding {
+    Bits(u32),
+    Trits(u32),
+    Quints(u32),
+}
+
+fn ise_encoding(levels: u32) -> IseEncoding {
+    match levels {
+        3 => IseEncoding::Trits(0),
+        5 => IseEncoding::Quints(0),
+        6 => IseEncoding::Trits(1),
+        10 => IseEncoding::Quints(1),
+        12 => IseEncoding::Trits(2),
+        20 => IseEncoding::Quints(2),
+        24 => IseEncoding::Trits(3),
+        40 => IseEncoding::Quints(3),
+        48 => IseEncoding::Trits(4),
+        80 => IseEncoding::Quints(4),
+        96 => IseEncoding::Trits(5),
+        160 => IseEncoding::Quints(5),
+        192 => IseEncoding::Trits(6),
+        _ => IseEncoding::Bits(levels.trailing_zeros()),
+    }
+}
+
+fn ise_bit_count(count: u32, levels: u32) -> u32 {
+    match ise_encoding(levels) {
+        IseEncoding::Bits(bits) => bits * count,
+        IseEncoding::Trits(bits) => bits * count + (8 * count).div_ceil(5),
+        IseEncoding::Quints(bits) => bits * count + (7 * count).div_ceil(3),
+    }
+}
+
+/// Color endpoint levels, the highest that fits the block is used
+const COLOR_LEVELS: [u32; 17] = [
+    256, 192, 160, 128, 96, 80, 64, 48, 40, 32, 24, 20, 16, 12, 10, 8, 6,
+];
+
+/// Weight levels by the precision bits of the block mode
+const WEIGHT_LEVELS: [u32; 12] = [2, 3, 4, 5, 6, 8, 10, 12, 16, 20, 24, 32];
+
+struct AstcBits {
+    bits: u128,
+    position: u32,
+}
+
+impl AstcBits {
+    /// Only the first `len` bits are read, the rest reads as zeros
+    fn new(bits: u128, len: u32) -> Self {
+        let bits = if len >= 128 {
+            bits
+        } else {
+            bits & ((1 << len) - 1)
+        };
+        Self { bits, position: 0 }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = self.bits.checked_shr(self.position).unwrap_or(0) & ((1 << count) - 1);
+        self.position += count;
+        value as u32
+    }
+}
+
+fn decode_trits(t: u32) -> [u32; 5] {
+    let bit = |value: u32, i: u32| (value >> i) & 1;
+
+    let (c, t3, t4) = if (t >> 2) & 7 == 7 {
+        (((t >> 5) & 7) << 2 | (t & 3), 2, 2)
+    } else if (t >> 5) & 3 == 3 {
+        (t & 0x1F, bit(t, 7), 2)
+    } else {
+        (t & 0x1F, (t >> 5) & 3, bit(t, 7))
+    };
+
+    let (t0, t1, t2) = if c & 3 == 3 {
+        (
+            (bit(c, 3) << 1) | (bit(c, 2) & !bit(c, 3) & 1),
+            bit(c, 4),
+            2,
+        )
+    } else if (c >> 2) & 3 == 3 {
+        (c & 3, 2, 2)
+    } else {
+        (
+            (bit(c, 1) << 1) | (bit(c, 0) & !bit(c, 1) & 1),
+            (c >> 2) & 3,
+            bit(c, 4),
+        )
+    };
+
+    [t0, t1, t2, t3, t4]
+}
+
+fn decode_quints(q: u32) -> [u32; 3] {
+    let bit = |value: u32, i: u32| (value >> i) & 1;
+
+    if (q >> 1) & 3 == 3 && (q >> 5) & 3 == 0 {
+        let q2 =
+            (bit(q, 0) << 2) | ((bit(q, 4) & !bit(q, 0) & 1) << 1) | (bit(q, 3) & !bit(q, 0) & 1);
+        return [4, 4, q2];
+    }
+
+    let (c, q2) = if (q >> 1) & 3 == 3 {
+        (((q >> 3) & 3) << 3 | ((!(q >> 5) & 3) << 1) | (q & 1), 4)
+    } else {
+        (q & 0x1F, (q >> 5) & 3)
+    };
+
+    let (q0, q1) = if c & 7 == 5 {
+        ((c >> 3) & 3, 4)
+    } else {
+        (c & 7, (c >> 3) & 3)
+    };
+
+    [q0, q1, q2]
+}
+
+/// Decodes `count` values, each as its trit or quint and its low bits
+fn decode_ise(bits: u128, count: u32, levels: u32) -> Vec<(u32, u32)> {
+    let mut reader = AstcBits::new(bits, ise_bit_count(count, levels));
+    let mut values = Vec::with_capacity(count as usize);
+
+    match ise_encoding(levels) {
+        IseEncoding::Bits(bits) => {
+            for _ in 0..count {
+                values.push((0, reader.read(bits)));
+            }
+        }
+        IseEncoding::Trits(bits) => {
+            while values.len() < count as usize {
+                let mut low = [0; 5];
+                let mut t = 0;
+                for (i, shift, t_bits) in [(0, 0, 2), (1, 2, 2), (2, 4, 1), (3, 5, 2), (4, 7, 1)] {
+                    low[i] = reader.read(bits);
+                    t |= reader.read(t_bits) << shift;
+                }
+                for (trit, low) in decode_trits(t).into_iter().zip(low) {
+                    values.push((trit, low));
+                }
+            }
+        }
+        IseEncoding::Quints(bits) => {
+            while values.len() < count as usize {
+                let mut low = [0; 3];
+                let mut q = 0;
+                for (i, shift, q_bits) in [(0, 0, 3), (1, 3, 2), (2, 5, 2)] {
+                    low[i] = reader.read(bits);
+                    q |= reader.read(q_bits) << shift;
+                }
+                for (quint, low) in decode_quints(q).into_iter().zip(low) {
+                    values.push((quint, low));
+                }
+            }
+        }
+    }
+
+    values.truncate(count as usize);
+    values
+}
+
+/// Repeats the bits of `value` to fill `target` bits
+fn replicate(value: u32, bits: u32, target: u32) -> u32 {
+    if bits == 0 {
+        return 0;
+    }
+
+    let mut result = 0;
+    let mut filled = 0;
+    while filled < target {
+        result = (result << bits) | value;
+        filled += bits;
+    }
+    result >> (filled - target)
+}
+
+/// Scales a color endpoint value to 0-255
+fn unquantize_color((high, low): (u32, u32), levels: u32) -> i32 {
+    let bit = |i: u32| (low >> i) & 1;
+
+    let (b, c) = match ise_encoding(levels) {
+        IseEncoding::Bits(bits) => return replicate(low, bits, 8) as i32,
+        IseEncoding::Trits(1) => (0, 204),
+        IseEncoding::Quints(1) => (0, 113),
+        IseEncoding::Trits(2) => (bit(1) * 0x116, 93),
+        IseEncoding::Quints(2) => (bit(1) * 0x10C, 54),
+        IseEncoding::Trits(3) => (bit(2) * 0x10A + bit(1) * 0x85, 44),
+        IseEncoding::Quints(3) => (bit(2) * 0x105 + bit(1) * 0x82, 26),
+        IseEncoding::Trits(4) => (bit(3) * 0x104 + bit(2) * 0x82 + bit(1) * 0x41, 22),
+        IseEncoding::Quints(4) => (bit(3) * 0x102 + bit(2) * 0x81 + bit(1) * 0x40, 13),
+        IseEncoding::Trits(5) => (
+            bit(4) * 0x102 + bit(3) * 0x81 + bit(2) * 0x40 + bit(1) * 0x20,
+            11,
+        ),
+        IseEncoding::Quints(5) => (
+            bit(4) * 0x101 + bit(3) * 0x80 + bit(2) * 0x40 + bit(1) * 0x20,
+            6,
+        ),
+        _ => (
+            bit(5) * 0x101 + bit(4) * 0x80 + bit(3) * 0x40 + bit(2) * 0x20 + bit(1) * 0x10,
+            5,
+        ),
+    };
+
+    let a = if bit(0) == 1 { 0x1FF } else { 0 };
+    let t = (high * c + b) ^ a;
+    ((a & 0x80) | (t >> 2)) as i32
+}
+
+/// Scales a weight to 0-64
+fn unquantize_weight((high, low): (u32, u32), levels: u32) -> u32 {
+    let bit = |i: u32| (low >> i) & 1;
+
+    let value = match ise_encoding(levels) {
+        IseEncoding::Bits(bits) => replicate(low, bits, 6),
+        IseEncoding::Trits(0) => [0, 32, 63][high as usize],
+        IseEncoding::Quints(0) => [0, 16, 32, 47, 63][high as usize],
+        encoding => {
+            let (b, c) = match encoding {
+                IseEncoding::Trits(1) => (0, 50),
+                IseEncoding::Quints(1) => (0, 28),
+                IseEncoding::Trits(2) => (bit(1) * 0x45, 23),
+                IseEncoding::Quints(2) => (bit(1) * 0x42, 13),
+                _ => (bit(2) * 0x42 + bit(1) * 0x21, 11),
+            };
+
+            let a = if bit(0) == 1 { 0x7F } else { 0 };
+            let t = (high * c + b) ^ a;
+            (a & 0x20) | (t >> 2)
+        }
+    };
+
+    if value > 32 {
+        value + 1
+    } else {
+        value
+    }
+}
+
+struct BlockMode {
+    grid_width: usize,
+    grid_height: usize,
+    dual_plane: bool,
+    weight_levels: u32,
+}
+
+fn decode_block_mode(mode: u32) -> Option<BlockMode> {
+    let a = (mode >> 5) & 3;
+    let mut precision = (mode >> 9) & 1;
+    let mut dual_plane = (mode >> 10) & 1;
+    let range;
+    let (grid_width, grid_height);
+
+    if mode & 3 != 0 {
+        range = ((mode >> 4) & 1) | ((mode & 3) << 1);
+        let b = (mode >> 7) & 3;
+        (grid_width, grid_height) = match (mode >> 2) & 3 {
+            0 => (b + 4, a + 2),
+            1 => (b + 8, a + 2),
+            2 => (a + 2, b + 8),
+            _ if mode & 0x100 != 0 => ((b & 1) + 2, a + 2),
+            _ => (a + 2, (b & 1) + 6),
+        };
+    } else {
+        range = ((mode >> 4) & 1) | (((mode >> 2) & 3) << 1);
+        if (mode >> 2) & 3 == 0 {
+            return None;
+        }
+
+        let b = (mode >> 9) & 3;
+        (grid_width, grid_height) = match (mode >> 7) & 3 {
+            0 => (12, a + 2),
+            1 => (a + 2, 12),
+            2 => {
+                precision = 0;
+                dual_plane = 0;
+                (a + 6, b + 6)
+            }
+            _ => match (mode >> 5) & 3 {
+                0 => (6, 10),
+                1 => (10, 6),
+                _ => return None,
+            },
+        };
+    }
+
+    Some(BlockMode {
+        grid_width: grid_width as usize,
+        grid_height: grid_height as usize,
+        dual_plane: dual_plane == 1,
+        weight_levels: WEIGHT_LEVELS[(range - 2 + 6 * precision) as usize],
+    })
+}
+
+fn hash52(mut p: u32) -> u32 {
+    p ^= p >> 15;
+    p = p.wrapping_sub(p << 17);
+    p = p.wrapping_add(p << 7);
+    p = p.wrapping_add(p << 4);
+    p ^= p >> 5;
+    p = p.wrapping_add(p << 16);
+    p ^= p >> 7;
+    p ^= p >> 3;
+    p ^= p << 6;
+    p ^= p >> 17;
+    p
+}
+
+/// Partition of a texel, generated from the partition index of the block
+fn select_partition(seed: u32, x: u32, y: u32, partition_count: u32, small_block: bool) -> usize {
+    let (x, y) = if small_block {
+        (x << 1, y << 1)
+    } else {
+        (x, y)
+    };
+
+    let seed = seed + (partition_count - 1) * 1024;
+    let rnum = hash52(seed);
+    let mut seeds = [
+        rnum,
+        rnum >> 4,
+        rnum >> 8,
+        rnum >> 12,
+        rnum >> 16,
+        rnum >> 20,
+        rnum >> 24,
+        rnum >> 28,
+    ]
+    .map(|s| s & 0xF);
+    for s in seeds.iter_mut() {
+        *s *= *s;
+    }
+
+    let (sh1, sh2) = if seed & 1 == 1 {
+        (
+            if seed & 2 != 0 { 4 } else { 5 },
+            if partition_count == 3 { 6 } else { 5 },
+        )
+    } else {
+        (
+            if partition_count == 3 { 6 } else { 5 },
+            if seed & 2 != 0 { 4 } else { 5 },
+        )
+    };
+    for (i, s) in seeds.iter_mut().enumerate() {
+        *s >>= if i % 2 == 0 { sh1 } else { sh2 };
+    }
+
+    let a = (seeds[0] * x + seeds[1] * y + (rnum >> 14)) & 0x3F;
+    let b = (seeds[2] * x + seeds[3] * y + (rnum >> 10)) & 0x3F;
+    let c = if partition_count >= 3 {
+        (seeds[4] * x + seeds[5] * y + (rnum >> 6)) & 0x3F
+    } else {
+        0
+    };
+    let d = if partition_count >= 4 {
+        (seeds[6] * x + seeds[7] * y + (rnum >> 2)) & 0x3F
+    } else {
+        0
+    };
+
+    if a >= b && a >= c && a >= d {
+        0
+    } else if b >= c && b >= d {
+        1
+    } else if c >= d {
+        2
+    } else {
+        3
+    }
+}
+
+/// Moves the top bit of `b` into `a`, leaving `b` a signed 6 bit offset
+fn bit_transfer_signed(a: &mut i32, b: &mut i32) {
+    *a = (*a >> 1) | (*b & 0x80);
+    *b = (*b >> 1) & 0x3F;
+    if *b & 0x20 != 0 {
+        *b -= 0x40;
+    }
+}
+
+fn blue_contract([r, g, b, a]: [i32; 4]) -> [i32; 4] {
+    [(r + b) >> 1, (g + b) >> 1, b, a]
+}
+
+/// Decodes the two endpoints of a color endpoint mode, `None` for HDR modes
+fn decode_endpoints(mode: u32, v: &[i32]) -> Option<[[i32; 4]; 2]> {
+    let endpoints = match mode {
+        0 => [[v[0], v[0], v[0], 255], [v[1], v[1], v[1], 255]],
+        1 => {
+            let l0 = (v[0] >> 2) | (v[1] & 0xC0);
+            let l1 = (l0 + (v[1] & 0x3F)).min(255);
+            [[l0, l0, l0, 255], [l1, l1, l1, 255]]
+        }
+        4 => [[v[0], v[0], v[0], v[2]], [v[1], v[1], v[1], v[3]]],
+        5 => {
+            let (mut v0, mut v1, mut v2, mut v3) = (v[0], v[1], v[2], v[3]);
+            bit_transfer_signed(&mut v0, &mut v1);
+            bit_transfer_signed(&mut v2, &mut v3);
+            [[v0, v0, v0, v2], [v0 + v1, v0 + v1, v0 + v1, v2 + v3]]
+        }
+        6 => [
+            [
+                (v[0] * v[3]) >> 8,
+                (v[1] * v[3]) >> 8,
+                (v[2] * v[3]) >> 8,
+                255,
+            ],
+            [v[0], v[1], v[2], 255],
+        ],
+        8 | 12 => {
+            let (a0, a1) = if mode == 12 { (v[6], v[7]) } else { (255, 255) };
+            if v[1] + v[3] + v[5] >= v[0] + v[2] + v[4] {
+                [[v[0], v[2], v[4], a0], [v[1], v[3], v[5], a1]]
+            } else {
+                [
+                    blue_contract([v[1], v[3], v[5], a1]),
+                    blue_contract([v[0], v[2], v[4], a0]),
+                ]
+            }
+        }
+        9 | 13 => {
+            let (mut v0, mut v1, mut v2, mut v3, mut v4, mut v5) =
+                (v[0], v[1], v[2], v[3], v[4], v[5]);
+            bit_transfer_signed(&mut v0, &mut v1);
+            bit_transfer_signed(&mut v2, &mut v3);
+            bit_transfer_signed(&mut v4, &mut v5);
+
+            let (mut a0, mut a1) = (255, 0);
+            if mode == 13 {
+                (a0, a1) = (v[6], v[7]);
+                bit_transfer_signed(&mut a0, &mut a1);
+            }
+
+            if v1 + v3 + v5 >= 0 {
+                [[v0, v2, v4, a0], [v0 + v1, v2 + v3, v4 + v5, a0 + a1]]
+            } else {
+                [
+                    blue_contract([v0 + v1, v2 + v3, v4 + v5, a0 + a1]),
+                    blue_contract([v0, v2, v4, a0]),
+                ]
+            }
+        }
+        10 => [
+            [
+                (v[0] * v[3]) >> 8,
+                (v[1] * v[3]) >> 8,
+                (v[2] * v[3]) >> 8,
+                v[4],
+            ],
+            [v[0], v[1], v[2], v[5]],
+        ],
+        _ => return None,
+    };
+
+    Some(endpoints.map(|endpoint| endpoint.map(|c| c.clamp(0, 255))))
+}
+
+/// Decodes a block into `out`, `None` if the block is invalid or an HDR void extent
+fn decode_astc_block(
+    block: &[u8],
+    block_width: usize,
+    block_height: usize,
+    srgb: bool,
+    out: &mut [[u8; 4]],
+) -> Option<()> {
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&block[..16]);
+    let bits = u128::from_le_bytes(bytes);
+    let read = |start: u32, count: u32| ((bits >> start) & ((1 << count) - 1)) as u32;
+
+    let block_mode = read(0, 11);
+    if block_mode & 0x1FF == 0x1FC {
+        return decode_void_extent(bits, out);
+    }
+
+    let mode = decode_block_mode(block_mode)?;
+    if mode.grid_width > block_width || mode.grid_height > block_height {
+        return None;
+    }
+
+    let planes = if mode.dual_plane { 2 } else { 1 };
+    let weight_count = (mode.grid_width * mode.grid_height * planes) as u32;
+    if weight_count > 64 {
+        return None;
+    }
+    let weight_bits = ise_bit_count(weight_count, mode.weight_levels);
+    if !(24..=96).contains(&weight_bits) {
+        return None;
+    }
+
+    let partition_count = read(11, 2) + 1;
+    if partition_count == 4 && mode.dual_plane {
+        return None;
+    }
+
+    let mut color_modes = [0; 4];
+    let mut extra_mode_bits = 0;
+    let color_start;
+    if partition_count == 1 {
+        color_modes[0] = read(13, 4);
+        color_start = 17;
+    } else {
+        let mode_field = read(23, 6);
+        let selector = mode_field & 3;
+        if selector == 0 {
+            color_modes.fill(mode_field >> 2);
+        } else {
+            // Modes of different classes store their remaining bits below the weights
+            extra_mode_bits = 3 * partition_count - 4;
+            let high = read(128 - weight_bits - extra_mode_bits, extra_mode_bits);
+            let encoded = mode_field | (high << 6);
+            for (i, color_mode) in color_modes
+                .iter_mut()
+                .enumerate()
+                .take(partition_count as usize)
+            {
+                let class = selector - 1 + ((encoded >> (2 + i)) & 1);
+                let low = (encoded >> (2 + partition_count as usize + 2 * i)) & 3;
+                *color_mode = (class << 2) | low;
+            }
+        }
+        color_start = 29;
+    }
+    let partition_index = read(13, 10);
+
+    let below_weights = 128 - weight_bits - extra_mode_bits;
+    let (color_end, dual_plane_component) = if mode.dual_plane {
+        (below_weights - 2, read(below_weights - 2, 2) as usize)
+    } else {
+        (below_weights, 4)
+    };
+    if color_end < color_start {
+        return None;
+    }
+
+    let color_value_count: u32 = color_modes[..partition_count as usize]
+        .iter()
+        .map(|mode| ((mode >> 2) + 1) * 2)
+        .sum();
+    if color_value_count > 18 {
+        return None;
+    }
+    let color_levels = *COLOR_LEVELS
+        .iter()
+        .find(|&&levels| ise_bit_count(color_value_count, levels) <= color_end - color_start)?;
+    let color_values: Vec<i32> = decode_ise(bits >> color_start, color_value_count, color_levels)
+        .into_iter()
+        .map(|value| unquantize_color(value, color_levels))
+        .collect();
+
+    // Partitions with HDR endpoints decode to the error color
+    let mut endpoints = [None; 4];
+    let mut values = &color_values[..];
+    for (color_mode, endpoint) in color_modes
+        .iter()
+        .zip(endpoints.iter_mut())
+        .take(partition_count as usize)
+    {
+        let count = (((color_mode >> 2) + 1) * 2) as usize;
+        *endpoint = decode_endpoints(*color_mode, &values[..count]);
+        values = &values[count..];
+    }
+
+    // Weights are stored bit reversed from the end of the block
+    let weights: Vec<u32> = decode_ise(bits.reverse_bits(), weight_count, mode.weight_levels)
+        .into_iter()
+        .map(|value| unquantize_weight(value, mode.weight_levels))
+        .collect();
+
+    let small_block = block_width * block_height < 31;
+    let scale_x = (1024 + block_width / 2) / (block_width - 1);
+    let scale_y = (1024 + block_height / 2) / (block_height - 1);
+
+    for y in 0..block_height {
+        for x in 0..block_width {
+            let partition = if partition_count > 1 {
+                select_partition(
+                    partition_index,
+                    x as u32,
+                    y as u32,
+                    partition_count,
+                    small_block,
+                )
+            } else {
+                0
+            };
+
+            // Bilinear infill of the weight grid
+            let gx = ((scale_x * x) * (mode.grid_width - 1) + 32) >> 6;
+            let gy = ((scale_y * y) * (mode.grid_height - 1) + 32) >> 6;
+            let (fx, fy) = (gx & 0xF, gy & 0xF);
+            let grid_index = (gx >> 4) + (gy >> 4) * mode.grid_width;
+            let w11 = (fx * fy + 8) >> 4;
+            let factors = [
+                (grid_index, 16 + w11 - fx - fy),
+                (grid_index + 1, fx - w11),
+                (grid_index + mode.grid_width, fy - w11),
+                (grid_index + mode.grid_width + 1, w11),
+            ];
+            let weight = |plane: usize| {
+                let sum: usize = factors
+                    .iter()
+                    .filter(|(_, factor)| *factor > 0)
+                    .map(|(i, factor)| weights[i * planes + plane] as usize * factor)
+                    .sum();
+                ((sum + 8) >> 4) as i32
+            };
+            let plane_weights = [weight(0), if mode.dual_plane { weight(1) } else { 0 }];
+
+            let rgba = &mut out[y * block_width + x];
+            let [e0, e1] = match endpoints[partition] {
+                Some(endpoints) => endpoints,
+                None => {
+                    *rgba = ERROR_COLOR;
+                    continue;
+                }
+            };
+            for channel in 0..4 {
+                let w = plane_weights[(channel == dual_plane_component) as usize];
+                let (c0, c1) = if srgb {
+                    ((e0[channel] << 8) | 0x80, (e1[channel] << 8) | 0x80)
+                } else {
+                    (e0[channel] * 257, e1[channel] * 257)
+                };
+                rgba[channel] = (((c0 * (64 - w) + c1 * w + 32) >> 6) >> 8) as u8;
+            }
+        }
+    }
+
+    Some(())
+}
+
+/// A block with a single color, stored as 16 bit values at the end of the block
+fn decode_void_extent(bits: u128, out: &mut [[u8; 4]]) -> Option<()> {
+    if (bits >> 9) & 1 == 1 {
+        // HDR colors
+        return None;
+    }
+
+    // The extent is ignored, but it has to be all ones or a valid range
+    let coordinate = |index: u32| ((bits >> (12 + 13 * index)) & 0x1FFF) as u32;
+    let all_ones = (0..4).all(|index| coordinate(index) == 0x1FFF);
+    if !all_ones && (coordinate(0) >= coordinate(1) || coordinate(2) >= coordinate(3)) {
+        return None;
+    }
+
+    let color = [0, 1, 2, 3].map(|channel| ((bits >> (64 + 16 * channel + 8)) & 0xFF) as u8);
+    out.fill(color);
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trits_and_quints_decode_every_combination() {
+        let mut trits: Vec<[u32; 5]> = (0..256).map(decode_trits).collect();
+        trits.sort();
+        trits.dedup();
+        assert_eq!(trits.len(), 243);
+        assert!(trits.iter().flatten().all(|&t| t < 3));
+
+        let mut quints: Vec<[u32; 3]> = (0..128).map(decode_quints).collect();
+        quints.sort();
+        quints.dedup();
+        assert_eq!(quints.len(), 125);
+        assert!(quints.iter().flatten().all(|&q| q < 5));
+    }
+
+    fn all_values(levels: u32) -> Vec<(u32, u32)> {
+        let (high_count, bits) = match ise_encoding(levels) {
+            IseEncoding::Bits(bits) => (1, bits),
+            IseEncoding::Trits(bits) => (3, bits),
+            IseEncoding::Quints(bits) => (5, bits),
+        };
+        (0..high_count)
+            .flat_map(|high| (0..1 << bits).map(move |low| (high, low)))
+            .collect()
+    }
+
+    #[test]
+    fn unquantized_values_are_evenly_spaced() {
+        for levels in COLOR_LEVELS {
+            let mut values: Vec<i32> = all_values(levels)
+                .into_iter()
+                .map(|value| unquantize_color(value, levels))
+                .collect();
+            values.sort();
+            for (i, value) in values.iter().enumerate() {
+                let expected = (i as f64 * 255.0 / (levels - 1) as f64).round() as i32;
+                assert!(
+                    (value - expected).abs() <= 1,
+                    "{} levels: {:?}",
+                    levels,
+                    values
+                );
+            }
+        }
+
+        for levels in WEIGHT_LEVELS {
+            let mut values: Vec<u32> = all_values(levels)
+                .into_iter()
+                .map(|value| unquantize_weight(value, levels))
+                .collect();
+            values.sort();
+            for (i, value) in values.iter().enumerate() {
+                let expected = (i as f64 * 64.0 / (levels - 1) as f64).round() as i32;
+                assert!(
+                    (*value as i32 - expected).abs() <= 1,
+                    "{} levels: {:?}",
+                    levels,
+                    values
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn void_extent_block() {
+        let mut bits: u128 = 0x1FC | (0b11 << 10) | (0x1FFF << 12) | (0x1FFF << 25);
+        bits |= (0x1FFF << 38) | (0x1FFF << 51);
+        for (channel, value) in [0x1234u128, 0x5678, 0x9ABC, 0xFFFF].iter().enumerate() {
+            bits |= value << (64 + 16 * channel);
+        }
+
+        let rgba = decode_astc(
+            &bits.to_le_bytes(),
+            5,
+            4,
+            GL_COMPRESSED_RGBA_ASTC_4X4_KHR + 1,
+        )
+        .unwrap();
+        assert_eq!(rgba.len(), 5 * 4 * 4);
+        assert!(rgba.chunks(4).all(|c| c == [0x12, 0x56, 0x9A, 0xFF]));
+    }
+
+    #[test]
+    fn single_partition_rgb_block() {
+        // 4x4 weight grid with 4 weight levels, one partition, RGB direct endpoints
+        let block_mode: u128 = 0b10 | (2 << 5);
+        let mut bits = block_mode | (8 << 13);
+        let colors = [10u128, 250, 20, 240, 30, 230];
+        for (i, value) in colors.iter().enumerate() {
+            bits |= value << (17 + 8 * i);
+        }
+        let weights: Vec<u128> = (0..16).map(|i| i % 4).collect();
+        let weight_bits = weights
+            .iter()
+            .enumerate()
+            .fold(0u128, |acc, (i, w)| acc | (w << (2 * i)));
+        bits |= weight_bits.reverse_bits();
+
+        let mut pixels = [[0; 4]; 16];
+        decode_astc_block(&bits.to_le_bytes(), 4, 4, false, &mut pixels).unwrap();
+
+        for (i, rgba) in pixels.iter().enumerate() {
+            let w = [0, 21, 43, 64][i % 4];
+            let interpolate = |e0: i32, e1: i32| {
+                ((((e0 * 257) * (64 - w) + (e1 * 257) * w + 32) >> 6) >> 8) as u8
+            };
+            assert_eq!(
+                *rgba,
+                [
+                    interpolate(10, 250),
+                    interpolate(20, 240),
+                    interpolate(30, 230),
+                    255
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_blocks_decode_to_error_color() {
+        // Reserved block mode
+        let rgba = decode_astc(&[0; 16], 4, 4, GL_COMPRESSED_RGBA_ASTC_4X4_KHR).unwrap();
+        assert!(rgba.chunks(4).all(|c| c == ERROR_COLOR));
+    }
+
+    #[test]
+    fn partitions_use_every_subset() {
+        for partition_count in 2..=4 {
+            let mut used = [false; 4];
+            for seed in 0..1024 {
+                for (x, y) in [(0, 0), (3, 0), (0, 3), (3, 3), (1, 2), (2, 1)] {
+                    used[select_partition(seed, x, y, partition_count, true)] = true;
+                }
+            }
+            assert_eq!(
+                used.iter().filter(|used| **used).count(),
+                partition_count as usize
+            );
+        }
+    }
+
+    /// Blocks and their pixels, row by row, as decoded by Mesa's llvmpipe through OpenGL
+    const REFERENCE_BLOCKS: [(&str, u32, &[u8], [u8; 64]); 11] = [
+        (
+            "one partition",
+            GL_COMPRESSED_RGBA_ASTC_4X4_KHR,
+            &[
+                0x9F, 0x43, 0x2B, 0x3B, 0xF4, 0x68, 0xA2, 0xFB, 0xE4, 0xD6, 0x6E, 0x8B, 0x47, 0x0B,
+                0x57, 0x72,
+            ],
+            [
+                82, 15, 67, 172, 95, 18, 77, 163, 110, 21, 90, 153, 130, 25, 107, 138, 67, 12, 54,
+                183, 84, 15, 68, 171, 102, 19, 84, 158, 119, 23, 97, 146, 48, 8, 39, 196, 74, 14,
+                60, 178, 93, 17, 76, 165, 108, 20, 88, 154, 33, 5, 27, 207, 65, 12, 53, 184, 84,
+                15, 68, 171, 97, 18, 79, 162,
+            ],
+        ),
+        (
+            "dual plane",
+            GL_COMPRESSED_RGBA_ASTC_4X4_KHR,
+            &[
+                0xBF, 0x85, 0xBF, 0x21, 0x28, 0xD8, 0xD8, 0x8A, 0x62, 0x28, 0x71, 0x4B, 0x9D, 0x8F,
+                0x6F, 0x14,
+            ],
+            [
+                218, 64, 207, 234, 193, 64, 207, 234, 182, 122, 171, 164, 182, 250, 91, 10, 242,
+                137, 162, 147, 236, 96, 187, 196, 211, 110, 178, 178, 158, 157, 149, 122, 242, 180,
+                135, 94, 240, 128, 167, 157, 213, 116, 175, 171, 152, 134, 164, 150, 218, 198, 124,
+                73, 207, 160, 147, 118, 189, 151, 153, 129, 164, 172, 140, 104,
+            ],
+        ),
+        (
+            "two partitions",
+            GL_COMPRESSED_RGBA_ASTC_4X4_KHR,
+            &[
+                0x3F, 0xEB, 0x6C, 0xB8, 0x92, 0x71, 0x15, 0xEC, 0x8D, 0xBD, 0xAC, 0xDF, 0x93, 0xF3,
+                0xB2, 0x02,
+            ],
+            [
+                161, 153, 170, 102, 108, 168, 100, 252, 156, 162, 174, 80, 153, 166, 175, 70, 150,
+                172, 178, 57, 138, 156, 93, 237, 145, 181, 181, 35, 142, 185, 183, 24, 143, 183,
+                182, 28, 149, 152, 90, 232, 143, 184, 183, 27, 143, 183, 182, 30, 138, 191, 186, 9,
+                147, 153, 91, 233, 152, 169, 177, 64, 158, 158, 172, 89,
+            ],
+        ),
+        (
+            "two partitions, dual plane",
+            GL_COMPRESSED_RGBA_ASTC_4X4_KHR,
+            &[
+                0x0E, 0xAF, 0xF8, 0x11, 0x97, 0xA2, 0xA7, 0x7E, 0x9D, 0x49, 0xAC, 0x92, 0xE4, 0x05,
+                0xD5, 0x3F,
+            ],
+            [
+                192, 41, 39, 203, 191, 40, 39, 202, 111, 217, 89, 109, 109, 206, 88, 113, 184, 40,
+                38, 189, 185, 39, 38, 191, 103, 201, 82, 127, 104, 189, 84, 124, 173, 39, 35, 171,
+                176, 38, 36, 175, 95, 182, 76, 144, 99, 168, 80, 135, 165, 38, 34, 156, 170, 37,
+                35, 164, 87, 168, 70, 162, 94, 151, 76, 146,
+            ],
+        ),
+        (
+            "three partitions",
+            GL_COMPRESSED_RGBA_ASTC_4X4_KHR,
+            &[
+                0x4D, 0x33, 0x4A, 0x25, 0xCB, 0x77, 0x3B, 0x2F, 0x23, 0xC7, 0x7D, 0x7C, 0xCD, 0x40,
+                0x03, 0x74,
+            ],
+            [
+                158, 29, 91, 255, 152, 152, 152, 93, 128, 121, 81, 54, 128, 121, 81, 54, 152, 152,
+                152, 93, 140, 25, 80, 255, 84, 80, 53, 25, 63, 60, 40, 11, 166, 166, 166, 86, 223,
+                42, 128, 255, 207, 196, 131, 107, 211, 200, 134, 110, 139, 139, 139, 100, 137, 25,
+                78, 255, 153, 145, 97, 71, 195, 185, 124, 99,
+            ],
+        ),
+        (
+            "four partitions",
+            GL_COMPRESSED_RGBA_ASTC_4X4_KHR,
+            &[
+                0xBF, 0xD9, 0x40, 0xEC, 0x55, 0x00, 0x3B, 0xBB, 0xB2, 0x08, 0x71, 0xAA, 0x24, 0x31,
+                0x1D, 0x81,
+            ],
+            [
+                15, 36, 2, 255, 12, 113, 60, 255, 13, 22, 24, 255, 41, 68, 72, 255, 33, 79, 6, 255,
+                54, 120, 64, 255, 51, 113, 61, 255, 48, 107, 57, 255, 39, 93, 7, 255, 71, 117, 124,
+                255, 57, 126, 68, 255, 52, 115, 61, 255, 62, 102, 108, 255, 78, 129, 135, 255, 85,
+                140, 147, 255, 63, 140, 75, 255,
+            ],
+        ),
+        (
+            "HDR endpoints in one partition",
+            GL_COMPRESSED_RGBA_ASTC_4X4_KHR,
+            &[
+                0x13, 0x68, 0x58, 0x3D, 0x59, 0xD0, 0x14, 0xD5, 0xFD, 0xC9, 0xC7, 0xE5, 0xBD, 0xC2,
+                0x58, 0x90,
+            ],
+            [
+                255, 0, 255, 255, 74, 87, 13, 106, 51, 60, 9, 92, 168, 198, 29, 162, 255, 0, 255,
+                255, 109, 129, 19, 127, 51, 60, 9, 92, 145, 171, 25, 148, 255, 0, 255, 255, 155,
+                183, 27, 154, 51, 60, 9, 92, 119, 141, 21, 133, 74, 87, 13, 106, 190, 225, 34, 176,
+                51, 60, 9, 92, 255, 0, 255, 255,
+            ],
+        ),
+        (
+            "void extent",
+            GL_COMPRESSED_RGBA_ASTC_4X4_KHR,
+            &[
+                0xFC, 0xE5, 0xC7, 0x26, 0x59, 0x39, 0x05, 0xFC, 0x48, 0x2F, 0x1B, 0xF1, 0x62, 0x2B,
+                0x22, 0xA7,
+            ],
+            [
+                47, 241, 43, 167, 47, 241, 43, 167, 47, 241, 43, 167, 47, 241, 43, 167, 47, 241,
+                43, 167, 47, 241, 43, 167, 47, 241, 43, 167, 47, 241, 43, 167, 47, 241, 43, 167,
+                47, 241, 43, 167, 47, 241, 43, 167, 47, 241, 43, 167, 47, 241, 43, 167, 47, 241,
+                43, 167, 47, 241, 43, 167, 47, 241, 43, 167,
+            ],
+        ),
+        (
+            "sRGB one partition",
+            GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4_KHR,
+            &[
+                0xDF, 0x83, 0x29, 0x53, 0x37, 0xD3, 0x7C, 0xFA, 0xCB, 0xE6, 0x2B, 0x26, 0x5B, 0xB3,
+                0x6D, 0x57,
+            ],
+            [
+                98, 178, 95, 72, 157, 201, 107, 97, 164, 203, 108, 99, 117, 185, 99, 80, 211, 221,
+                117, 119, 142, 195, 104, 90, 114, 184, 98, 78, 117, 185, 99, 80, 161, 202, 107, 98,
+                126, 189, 101, 84, 129, 190, 101, 85, 167, 204, 109, 101, 230, 228, 121, 127, 208,
+                219, 117, 118, 161, 202, 107, 98, 73, 169, 90, 61,
+            ],
+        ),
+        (
+            "sRGB dual plane",
+            GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4_KHR,
+            &[
+                0x13, 0x86, 0xA3, 0xA6, 0x49, 0xA9, 0xC6, 0xF2, 0xC6, 0x01, 0xB1, 0xB6, 0x1A, 0x8C,
+                0x68, 0x64,
+            ],
+            [
+                227, 173, 235, 201, 225, 171, 212, 207, 223, 167, 226, 217, 244, 202, 228, 120,
+                229, 177, 228, 191, 224, 168, 214, 214, 230, 179, 224, 184, 241, 196, 227, 135,
+                232, 182, 221, 176, 222, 165, 217, 224, 239, 193, 221, 145, 238, 191, 227, 151,
+                234, 186, 215, 166, 220, 162, 219, 232, 245, 204, 219, 115, 234, 186, 226, 166,
+            ],
+        ),
+        (
+            "sRGB two partitions",
+            GL_COMPRESSED_SRGB8_ALPHA8_ASTC_4X4_KHR,
+            &[
+                0x4F, 0xAB, 0x52, 0xB0, 0xD9, 0x8C, 0x1C, 0x9F, 0xA2, 0x90, 0xBB, 0x7B, 0x35, 0x19,
+                0x43, 0x34,
+            ],
+            [
+                129, 164, 149, 255, 144, 136, 173, 255, 158, 118, 198, 255, 168, 116, 192, 255,
+                107, 206, 115, 255, 128, 167, 148, 255, 151, 120, 202, 255, 165, 117, 193, 255,
+                172, 84, 217, 255, 174, 79, 220, 255, 169, 116, 191, 255, 179, 70, 227, 255, 120,
+                182, 135, 255, 128, 167, 148, 255, 141, 122, 208, 255, 147, 121, 204, 255,
+            ],
+        ),
+    ];
+
+    #[test]
+    fn matches_reference_decoder() {
+        for (mode, format, block, expected) in REFERENCE_BLOCKS {
+            assert_eq!(
+                decode_astc(block, 4, 4, format).unwrap(),
+                expected,
+                "{}",
+                mode
+            );
+        }
+    }
+}
@@ -0,0 +1,1162 @@
+use super::decompress_software::{decode_blocks, unsupported_format};
+use crate::Result;
+
+pub const GL_COMPRESSED_RGBA_BPTC_UNORM: u32 = 0x8E8C;
+pub const GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM: u32 = 0x8E8D;
+pub const GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT: u32 = 0x8E8E;
+pub const GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT: u32 = 0x8E8F;
+
+/// Width, height and size in bytes of a block
+const BLOCK: (usize, usize, usize) = (4, 4, 16);
+
+/// Decodes a BPTC compressed image to RGBA8 pixels.
+/// BC6H colors are clamped to 0.0-1.0, like OpenGL does when reading them back as bytes.
+pub fn decode_bptc(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    gl_internal_format: u32,
+) -> Result<Vec<u8>> {
+    match gl_internal_format {
+        GL_COMPRESSED_RGBA_BPTC_UNORM | GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM => {
+            decode_blocks(data, width, height, BLOCK, decode_bc7_block)
+        }
+        GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT => {
+            decode_blocks(data, width, height, BLOCK, |block, out| {
+                decode_bc6h_block_rgba(block, true, out)
+            })
+        }
+        GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT => {
+            decode_blocks(data, width, height, BLOCK, |block, out| {
+                decode_bc6h_block_rgba(block, false, out)
+            })
+        }
+        _ => Err(unsupported_format(gl_internal_format)),
+    }
+}
+
+/// Reads the bits of a block, starting with the least significant bit of the first byte
+struct BitReader {
+    bits: u128,
+}
+
+impl BitReader {
+    fn new(block: &[u8]) -> Self {
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&block[..16]);
+        Self {
+            bits: u128::from_le_bytes(bytes),
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let value = (self.bits & ((1 << count) - 1)) as u32;
+        self.bits >>= count;
+        value
+    }
+}
+
+const WEIGHTS2: [i32; 4] = [0, 21, 43, 64];
+const WEIGHTS3: [i32; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+pub(crate) const WEIGHTS4: [i32; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn interpolate(e0: i32, e1: i32, index: u32, index_bits: u32) -> i32 {
+    let weight = match index_bits {
+        2 => WEIGHTS2[index as usize],
+        3 => WEIGHTS3[index as usize],
+        _ => WEIGHTS4[index as usize],
+    };
+
+    ((64 - weight) * e0 + weight * e1 + 32) >> 6
+}
+
+/// Subset of each pixel for the two subset partitions, one bit per pixel
+const PARTITIONS2: [u16; 64] = [
+    0xCCCC, 0x8888, 0xEEEE, 0xECC8, 0xC880, 0xFEEC, 0xFEC8, 0xEC80, 0xC800, 0xFFEC, 0xFE80, 0xE800,
+    0xFFE8, 0xFF00, 0xFFF0, 0xF000, 0xF710, 0x008E, 0x7100, 0x08CE, 0x008C, 0x7310, 0x3100, 0x8CCE,
+    0x088C, 0x3110, 0x6666, 0x366C, 0x17E8, 0x0FF0, 0x718E, 0x399C, 0xAAAA, 0xF0F0, 0x5A5A, 0x33CC,
+    0x3C3C, 0x55AA, 0x9696, 0xA55A, 0x73CE, 0x13C8, 0x324C, 0x3BDC, 0x6996, 0xC33C, 0x9966, 0x0660,
+    0x0272, 0x04E4, 0x4E40, 0x2720, 0xC936, 0x936C, 0x39C6, 0x639C, 0x9336, 0x9CC6, 0x817E, 0xE718,
+    0xCCF0, 0x0FCC, 0x7744, 0xEE22,
+];
+
+/// Subset of each pixel for the three subset partitions
+const PARTITIONS3: [[u8; 16]; 64] = [
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 1, 2, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 2, 0, 0, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 1, 0, 1, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1],
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2],
+    [0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2, 0, 1, 1, 2],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0, 2, 2, 2, 0],
+    [0, 0, 0, 1, 0, 0, 1, 1, 0, 1, 1, 2, 1, 1, 2, 2],
+    [0, 1, 1, 1, 0, 0, 1, 1, 2, 0, 0, 1, 2, 2, 0, 0],
+    [0, 0, 0, 0, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 2, 2, 0, 0, 2, 2, 1, 1, 1, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2, 0, 2, 2, 2],
+    [0, 0, 0, 1, 0, 0, 0, 1, 2, 2, 2, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2],
+    [0, 0, 0, 0, 1, 1, 0, 0, 2, 2, 1, 0, 2, 2, 1, 0],
+    [0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1, 0, 0, 0, 0],
+    [0, 0, 1, 2, 0, 0, 1, 2, 1, 1, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1, 0, 1, 1, 0],
+    [0, 0, 0, 0, 0, 1, 1, 0, 1, 2, 2, 1, 1, 2, 2, 1],
+    [0, 0, 2, 2, 1, 1, 0, 2, 1, 1, 0, 2, 0, 0, 2, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 0, 0, 2, 2, 2, 2, 2],
+    [0, 0, 1, 1, 0, 1, 2, 2, 0, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 0, 0, 2, 0, 0, 0, 2, 2, 1, 1, 2, 2, 2, 1],
+    [0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 2, 2, 2],
+    [0, 2, 2, 2, 0, 0, 2, 2, 0, 0, 1, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 0, 0, 1, 2, 0, 0, 2, 2, 0, 2, 2, 2],
+    [0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0, 0, 1, 2, 0],
+    [0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0],
+    [0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0],
+    [0, 1, 2, 0, 2, 0, 1, 2, 1, 2, 0, 1, 0, 1, 2, 0],
+    [0, 0, 1, 1, 2, 2, 0, 0, 1, 1, 2, 2, 0, 0, 1, 1],
+    [0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 0, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2, 1, 1, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 2, 2, 0, 0, 1, 1],
+    [0, 2, 2, 0, 1, 2, 2, 1, 0, 2, 2, 0, 1, 2, 2, 1],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 0, 1, 0, 1],
+    [0, 0, 0, 0, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1, 2, 1],
+    [0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 2, 2, 2, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 2, 2, 2, 0, 1, 1, 1],
+    [0, 0, 0, 2, 1, 1, 1, 2, 0, 0, 0, 2, 1, 1, 1, 2],
+    [0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 2, 2, 2, 0, 1, 1, 1, 0, 1, 1, 1, 0, 2, 2, 2],
+    [0, 0, 0, 2, 1, 1, 1, 2, 1, 1, 1, 2, 0, 0, 0, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2, 2, 1, 1, 2],
+    [0, 1, 1, 0, 0, 1, 1, 0, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 0, 2, 2, 0, 0, 1, 1, 0, 0, 1, 1, 0, 0, 2, 2],
+    [0, 0, 2, 2, 1, 1, 2, 2, 1, 1, 2, 2, 0, 0, 2, 2],
+    [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 1, 1, 2],
+    [0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+    [0, 2, 2, 2, 1, 2, 2, 2, 0, 2, 2, 2, 1, 2, 2, 2],
+    [0, 1, 0, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2],
+    [0, 1, 1, 1, 2, 0, 1, 1, 2, 2, 0, 1, 2, 2, 2, 0],
+];
+
+/// Pixel holding the anchor index of the second subset of the two subset partitions
+const ANCHORS2: [usize; 64] = [
+    15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 15, 2, 8, 2, 2, 8, 8, 15, 2, 8,
+    2, 2, 8, 8, 2, 2, 15, 15, 6, 8, 2, 8, 15, 15, 2, 8, 2, 2, 2, 15, 15, 6, 6, 2, 6, 8, 15, 15, 2,
+    2, 15, 15, 15, 15, 15, 2, 2, 15,
+];
+
+/// Pixel holding the anchor index of the second subset of the three subset partitions
+const ANCHORS3_SECOND: [usize; 64] = [
+    3, 3, 15, 15, 8, 3, 15, 15, 8, 8, 6, 6, 6, 5, 3, 3, 3, 3, 8, 15, 3, 3, 6, 10, 5, 8, 8, 6, 8, 5,
+    15, 15, 8, 15, 3, 5, 6, 10, 8, 15, 15, 3, 15, 5, 15, 15, 15, 15, 3, 15, 5, 5, 5, 8, 5, 10, 5,
+    10, 8, 13, 15, 12, 3, 3,
+];
+
+/// Pixel holding the anchor index of the third subset of the three subset partitions
+const ANCHORS3_THIRD: [usize; 64] = [
+    15, 8, 8, 3, 15, 15, 3, 8, 15, 15, 15, 15, 15, 15, 15, 8, 15, 8, 15, 3, 15, 8, 15, 8, 3, 15, 6,
+    10, 15, 15, 10, 8, 15, 3, 15, 10, 10, 8, 9, 10, 6, 15, 8, 15, 3, 6, 6, 8, 15, 3, 15, 15, 15,
+    15, 15, 15, 15, 15, 15, 15, 3, 15, 15, 8,
+];
+
+fn subset_of(subsets: usize, partition: usize, pixel: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => ((PARTITIONS2[partition] >> pixel) & 1) as usize,
+        _ => PARTITIONS3[partition][pixel] as usize,
+    }
+}
+
+fn is_anchor(subsets: usize, partition: usize, pixel: usize) -> bool {
+    pixel == 0
+        || match subsets {
+            1 => false,
+            2 => pixel == ANCHORS2[partition],
+            _ => pixel == ANCHORS3_SECOND[partition] || pixel == ANCHORS3_THIRD[partition],
+        }
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index2_bits: u32,
+}
+
+const BC7_MODES: [Bc7Mode; 8] = [
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 4,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 4,
+        alpha_bits: 0,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 3,
+        index2_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 6,
+        alpha_bits: 0,
+        endpoint_pbits: false,
+        shared_pbits: true,
+        index_bits: 3,
+        index2_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 3,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 0,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 0,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 1,
+        color_bits: 5,
+        alpha_bits: 6,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 3,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 2,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 8,
+        endpoint_pbits: false,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 2,
+    },
+    Bc7Mode {
+        subsets: 1,
+        partition_bits: 0,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 7,
+        alpha_bits: 7,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 4,
+        index2_bits: 0,
+    },
+    Bc7Mode {
+        subsets: 2,
+        partition_bits: 6,
+        rotation_bits: 0,
+        index_selection_bits: 0,
+        color_bits: 5,
+        alpha_bits: 5,
+        endpoint_pbits: true,
+        shared_pbits: false,
+        index_bits: 2,
+        index2_bits: 0,
+    },
+];
+
+/// Expands a quantized endpoint to 8 bits by replicating its high bits
+fn unquantize_bc7(value: u32, bits: u32) -> i32 {
+    let value = value << (8 - bits);
+    (value | (value >> bits)) as i32
+}
+
+fn decode_bc7_block(block: &[u8], out: &mut [[u8; 4]]) {
+    let mode_index = block[0].trailing_zeros() as usize;
+    if mode_index >= BC7_MODES.len() {
+        // Reserved mode, decoders must output transparent black
+        out.fill([0; 4]);
+        return;
+    }
+
+    let mode = &BC7_MODES[mode_index];
+    let mut bits = BitReader::new(block);
+    bits.read(mode_index as u32 + 1);
+
+    let partition = bits.read(mode.partition_bits) as usize;
+    let rotation = bits.read(mode.rotation_bits);
+    let index_selection = bits.read(mode.index_selection_bits);
+
+    let num_endpoints = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for channel in 0..3 {
+        for endpoint in endpoints.iter_mut().take(num_endpoints) {
+            endpoint[channel] = bits.read(mode.color_bits);
+        }
+    }
+    for endpoint in endpoints.iter_mut().take(num_endpoints) {
+        endpoint[3] = bits.read(mode.alpha_bits);
+    }
+
+    let mut color_bits = mode.color_bits;
+    let mut alpha_bits = mode.alpha_bits;
+    if mode.endpoint_pbits || mode.shared_pbits {
+        let mut pbits = [0; 6];
+        if mode.endpoint_pbits {
+            for pbit in pbits.iter_mut().take(num_endpoints) {
+                *pbit = bits.read(1);
+            }
+        } else {
+            for subset in 0..mode.subsets {
+                let pbit = bits.read(1);
+                pbits[subset * 2] = pbit;
+                pbits[subset * 2 + 1] = pbit;
+            }
+        }
+
+        for (endpoint, pbit) in endpoints.iter_mut().zip(pbits) {
+            for value in endpoint.iter_mut() {
+                *value = (*value << 1) | pbit;
+            }
+        }
+        color_bits += 1;
+        if alpha_bits > 0 {
+            alpha_bits += 1;
+        }
+    }
+
+    let mut unquantized = [[0i32; 4]; 6];
+    for (endpoint, result) in endpoints.iter().zip(unquantized.iter_mut()) {
+        for channel in 0..3 {
+            result[channel] = unquantize_bc7(endpoint[channel], color_bits);
+        }
+        result[3] = if alpha_bits > 0 {
+            unquantize_bc7(endpoint[3], alpha_bits)
+        } else {
+            255
+        };
+    }
+
+    let mut indices = [0; 16];
+    for (pixel, index) in indices.iter_mut().enumerate() {
+        let anchor = is_anchor(mode.subsets, partition, pixel);
+        *index = bits.read(mode.index_bits - anchor as u32);
+    }
+
+    let mut indices2 = [0; 16];
+    if mode.index2_bits > 0 {
+        for (pixel, index) in indices2.iter_mut().enumerate() {
+            *index = bits.read(mode.index2_bits - (pixel == 0) as u32);
+        }
+    }
+
+    for (pixel, rgba) in out.iter_mut().enumerate() {
+        let subset = subset_of(mode.subsets, partition, pixel);
+        let e0 = &unquantized[subset * 2];
+        let e1 = &unquantized[subset * 2 + 1];
+
+        let (color_index, color_index_bits, alpha_index, alpha_index_bits) =
+            if mode.index2_bits == 0 {
+                (
+                    indices[pixel],
+                    mode.index_bits,
+                    indices[pixel],
+                    mode.index_bits,
+                )
+            } else if index_selection == 0 {
+                (
+                    indices[pixel],
+                    mode.index_bits,
+                    indices2[pixel],
+                    mode.index2_bits,
+                )
+            } else {
+                (
+                    indices2[pixel],
+                    mode.index2_bits,
+                    indices[pixel],
+                    mode.index_bits,
+                )
+            };
+
+        for channel in 0..3 {
+            rgba[channel] =
+                interpolate(e0[channel], e1[channel], color_index, color_index_bits) as u8;
+        }
+        rgba[3] = interpolate(e0[3], e1[3], alpha_index, alpha_index_bits) as u8;
+
+        match rotation {
+            1 => rgba.swap(0, 3),
+            2 => rgba.swap(1, 3),
+            3 => rgba.swap(2, 3),
+            _ => {}
+        }
+    }
+}
+
+/// Endpoint component of a BC6H block, as (channel, endpoint)
+type Bc6hField = (usize, usize);
+
+const RW: Bc6hField = (0, 0);
+const RX: Bc6hField = (0, 1);
+const RY: Bc6hField = (0, 2);
+const RZ: Bc6hField = (0, 3);
+const GW: Bc6hField = (1, 0);
+const GX: Bc6hField = (1, 1);
+const GY: Bc6hField = (1, 2);
+const GZ: Bc6hField = (1, 3);
+const BW: Bc6hField = (2, 0);
+const BX: Bc6hField = (2, 1);
+const BY: Bc6hField = (2, 2);
+const BZ: Bc6hField = (2, 3);
+
+struct Bc6hMode {
+    mode: u32,
+    regions: usize,
+    transformed: bool,
+    endpoint_bits: u32,
+    delta_bits: [u32; 3],
+    /// Endpoint bits in the order they are stored, as (field, first bit, last bit)
+    layout: &'static [(Bc6hField, u32, u32)],
+}
+
+#[rustfmt::skip]
+const BC6H_MODES: [Bc6hMode; 14] = [
+    Bc6hMode {
+        mode: 0b00,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 10,
+        delta_bits: [5, 5, 5],
+        layout: &[
+            (GY, 4, 4), (BY, 4, 4), (BZ, 4, 4), (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4),
+            (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1),
+            (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 7,
+        delta_bits: [6, 6, 6],
+        layout: &[
+            (GY, 5, 5), (GZ, 4, 5), (RW, 0, 6), (BZ, 0, 1), (BY, 4, 4), (GW, 0, 6), (BY, 5, 5),
+            (BZ, 2, 2), (GY, 4, 4), (BW, 0, 6), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 5),
+            (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b00010,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [5, 4, 4],
+        layout: &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 4), (RW, 10, 10), (GY, 0, 3), (GX, 0, 3),
+            (GW, 10, 10), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3),
+            (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b00110,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 5, 4],
+        layout: &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (GZ, 4, 4), (GY, 0, 3),
+            (GX, 0, 4), (GW, 10, 10), (GZ, 0, 3), (BX, 0, 3), (BW, 10, 10), (BZ, 1, 1), (BY, 0, 3),
+            (RY, 0, 3), (BZ, 0, 0), (BZ, 2, 2), (RZ, 0, 3), (GY, 4, 4), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01010,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [4, 4, 5],
+        layout: &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 10, 10), (BY, 4, 4), (GY, 0, 3),
+            (GX, 0, 3), (GW, 10, 10), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BW, 10, 10), (BY, 0, 3),
+            (RY, 0, 3), (BZ, 1, 2), (RZ, 0, 3), (BZ, 4, 4), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01110,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 9,
+        delta_bits: [5, 5, 5],
+        layout: &[
+            (RW, 0, 8), (BY, 4, 4), (GW, 0, 8), (GY, 4, 4), (BW, 0, 8), (BZ, 4, 4), (RX, 0, 4),
+            (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4), (BZ, 1, 1),
+            (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b10010,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [6, 5, 5],
+        layout: &[
+            (RW, 0, 7), (GZ, 4, 4), (BY, 4, 4), (GW, 0, 7), (BZ, 2, 2), (GY, 4, 4), (BW, 0, 7),
+            (BZ, 3, 4), (RX, 0, 5), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0), (GZ, 0, 3), (BX, 0, 4),
+            (BZ, 1, 1), (BY, 0, 3), (RY, 0, 5), (RZ, 0, 5),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b10110,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 6, 5],
+        layout: &[
+            (RW, 0, 7), (BZ, 0, 0), (BY, 4, 4), (GW, 0, 7), (GY, 5, 5), (GY, 4, 4), (BW, 0, 7),
+            (GZ, 5, 5), (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3),
+            (BX, 0, 4), (BZ, 1, 1), (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b11010,
+        regions: 2,
+        transformed: true,
+        endpoint_bits: 8,
+        delta_bits: [5, 5, 6],
+        layout: &[
+            (RW, 0, 7), (BZ, 1, 1), (BY, 4, 4), (GW, 0, 7), (BY, 5, 5), (GY, 4, 4), (BW, 0, 7),
+            (BZ, 5, 5), (BZ, 4, 4), (RX, 0, 4), (GZ, 4, 4), (GY, 0, 3), (GX, 0, 4), (BZ, 0, 0),
+            (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 4), (BZ, 2, 2), (RZ, 0, 4), (BZ, 3, 3),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b11110,
+        regions: 2,
+        transformed: false,
+        endpoint_bits: 6,
+        delta_bits: [6, 6, 6],
+        layout: &[
+            (RW, 0, 5), (GZ, 4, 4), (BZ, 0, 1), (BY, 4, 4), (GW, 0, 5), (GY, 5, 5), (BY, 5, 5),
+            (BZ, 2, 2), (GY, 4, 4), (BW, 0, 5), (GZ, 5, 5), (BZ, 3, 3), (BZ, 5, 5), (BZ, 4, 4),
+            (RX, 0, 5), (GY, 0, 3), (GX, 0, 5), (GZ, 0, 3), (BX, 0, 5), (BY, 0, 3), (RY, 0, 5),
+            (RZ, 0, 5),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b00011,
+        regions: 1,
+        transformed: false,
+        endpoint_bits: 10,
+        delta_bits: [10, 10, 10],
+        layout: &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 9), (GX, 0, 9), (BX, 0, 9),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b00111,
+        regions: 1,
+        transformed: true,
+        endpoint_bits: 11,
+        delta_bits: [9, 9, 9],
+        layout: &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 8), (RW, 10, 10), (GX, 0, 8), (GW, 10, 10),
+            (BX, 0, 8), (BW, 10, 10),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01011,
+        regions: 1,
+        transformed: true,
+        endpoint_bits: 12,
+        delta_bits: [8, 8, 8],
+        layout: &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 7), (RW, 11, 10), (GX, 0, 7), (GW, 11, 10),
+            (BX, 0, 7), (BW, 11, 10),
+        ],
+    },
+    Bc6hMode {
+        mode: 0b01111,
+        regions: 1,
+        transformed: true,
+        endpoint_bits: 16,
+        delta_bits: [4, 4, 4],
+        layout: &[
+            (RW, 0, 9), (GW, 0, 9), (BW, 0, 9), (RX, 0, 3), (RW, 15, 10), (GX, 0, 3), (GW, 15, 10),
+            (BX, 0, 3), (BW, 15, 10),
+        ],
+    },
+];
+
+fn sign_extend(value: i32, bits: u32) -> i32 {
+    let shift = 32 - bits;
+    (value << shift) >> shift
+}
+
+/// Scales an endpoint to the 16 bit range used for interpolation
+fn unquantize_bc6h(value: i32, bits: u32, signed: bool) -> i32 {
+    if signed {
+        if bits >= 16 {
+            return value;
+        }
+
+        let magnitude = value.abs();
+        let unquantized = if magnitude == 0 {
+            0
+        } else if magnitude >= (1 << (bits - 1)) - 1 {
+            0x7FFF
+        } else {
+            ((magnitude << 15) + 0x4000) >> (bits - 1)
+        };
+
+        if value < 0 {
+            -unquantized
+        } else {
+            unquantized
+        }
+    } else if bits >= 15 {
+        value
+    } else if value == 0 {
+        0
+    } else if value == (1 << bits) - 1 {
+        0xFFFF
+    } else {
+        ((value << 16) + 0x8000) >> bits
+    }
+}
+
+/// Scales an interpolated value to the bits of a half float
+fn finish_unquantize_bc6h(value: i32, signed: bool) -> u16 {
+    if !signed {
+        ((value * 31) >> 6) as u16
+    } else if value < 0 {
+        ((((-value) * 31) >> 5) as u16) | 0x8000
+    } else {
+        ((value * 31) >> 5) as u16
+    }
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1F) as i32;
+    let mantissa = (half & 0x3FF) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn decode_bc6h_block(block: &[u8], signed: bool, out: &mut [[f32; 3]; 16]) {
+    let mut bits = BitReader::new(block);
+    let mut mode_value = bits.read(2);
+    if mode_value > 1 {
+        mode_value |= bits.read(3) << 2;
+    }
+
+    let mode = match BC6H_MODES.iter().find(|m| m.mode == mode_value) {
+        Some(mode) => mode,
+        None => {
+            // Reserved mode, decoders must output black
+            *out = [[0.0; 3]; 16];
+            return;
+        }
+    };
+
+    // Endpoint components per channel, in the order w, x, y, z
+    let mut endpoints = [[0i32; 4]; 3];
+    for &((channel, endpoint), first, last) in mode.layout {
+        let mut bit = first;
+        loop {
+            endpoints[channel][endpoint] |= (bits.read(1) as i32) << bit;
+            if bit == last {
+                break;
+            }
+            if first < last {
+                bit += 1;
+            } else {
+                bit -= 1;
+            }
+        }
+    }
+
+    let partition = if mode.regions == 2 {
+        bits.read(5) as usize
+    } else {
+        0
+    };
+
+    let num_endpoints = mode.regions * 2;
+    let endpoint_mask = (1 << mode.endpoint_bits) - 1;
+    for (channel, values) in endpoints.iter_mut().enumerate() {
+        if signed {
+            values[0] = sign_extend(values[0], mode.endpoint_bits);
+        }
+
+        let base = values[0];
+        for value in values.iter_mut().take(num_endpoints).skip(1) {
+            if signed || mode.transformed {
+                *value = sign_extend(*value, mode.delta_bits[channel]);
+            }
+            if mode.transformed {
+                *value = (base + *value) & endpoint_mask;
+                if signed {
+                    *value = sign_extend(*value, mode.endpoint_bits);
+                }
+            }
+        }
+
+        for value in values.iter_mut().take(num_endpoints) {
+            *value = unquantize_bc6h(*value, mode.endpoint_bits, signed);
+        }
+    }
+
+    let index_bits = if mode.regions == 2 { 3 } else { 4 };
+    for (pixel, rgb) in out.iter_mut().enumerate() {
+        let anchor = is_anchor(mode.regions, partition, pixel);
+        let index = bits.read(index_bits - anchor as u32);
+        let region = subset_of(mode.regions, partition, pixel);
+
+        for (channel, values) in endpoints.iter().enumerate() {
+            let value = interpolate(
+                values[region * 2],
+                values[region * 2 + 1],
+                index,
+                index_bits,
+            );
+            rgb[channel] = half_to_f32(finish_unquantize_bc6h(value, signed));
+        }
+    }
+}
+
+fn decode_bc6h_block_rgba(block: &[u8], signed: bool, out: &mut [[u8; 4]]) {
+    let mut colors = [[0.0; 3]; 16];
+    decode_bc6h_block(block, signed, &mut colors);
+
+    for (rgb, rgba) in colors.iter().zip(out.iter_mut()) {
+        for channel in 0..3 {
+            rgba[channel] = (rgb[channel].clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        rgba[3] = 255;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct BitWriter {
+        bits: u128,
+        position: u32,
+    }
+
+    impl BitWriter {
+        fn new() -> Self {
+            Self {
+                bits: 0,
+                position: 0,
+            }
+        }
+
+        fn write(&mut self, value: u32, count: u32) {
+            self.bits |= (value as u128) << self.position;
+            self.position += count;
+        }
+
+        fn finish(self) -> [u8; 16] {
+            assert_eq!(self.position, 128);
+            self.bits.to_le_bytes()
+        }
+    }
+
+    #[test]
+    fn anchors_belong_to_their_subset() {
+        for partition in 0..64 {
+            assert_eq!(subset_of(2, partition, ANCHORS2[partition]), 1);
+            assert_eq!(subset_of(3, partition, ANCHORS3_SECOND[partition]), 1);
+            assert_eq!(subset_of(3, partition, ANCHORS3_THIRD[partition]), 2);
+            assert_eq!(subset_of(3, partition, 0), 0);
+        }
+    }
+
+    #[test]
+    fn bc7_mode6_gradient() {
+        let mut writer = BitWriter::new();
+        writer.write(1 << 6, 7);
+        for _ in 0..3 {
+            writer.write(0, 7);
+            writer.write(127, 7);
+        }
+        writer.write(127, 7);
+        writer.write(127, 7);
+        writer.write(0, 1);
+        writer.write(1, 1);
+        writer.write(0, 3);
+        for index in 1..16 {
+            writer.write(index, 4);
+        }
+        let block = writer.finish();
+
+        let mut pixels = [[0; 4]; 16];
+        decode_bc7_block(&block, &mut pixels);
+
+        for (pixel, rgba) in pixels.iter().enumerate() {
+            let weight = WEIGHTS4[pixel];
+            let color = ((weight * 255 + 32) >> 6) as u8;
+            let alpha = (((64 - weight) * 254 + weight * 255 + 32) >> 6) as u8;
+            assert_eq!(*rgba, [color, color, color, alpha]);
+        }
+    }
+
+    #[test]
+    fn bc6h_layouts_cover_every_endpoint_bit() {
+        for mode in &BC6H_MODES {
+            let mut seen = [[0u32; 4]; 3];
+            let mut count = if mode.mode > 1 { 5 } else { 2 };
+
+            for &((channel, endpoint), first, last) in mode.layout {
+                for bit in first.min(last)..=first.max(last) {
+                    assert_eq!(seen[channel][endpoint] & (1 << bit), 0);
+                    seen[channel][endpoint] |= 1 << bit;
+                    count += 1;
+                }
+            }
+
+            let expected_count = if mode.regions == 2 { 77 } else { 65 };
+            assert_eq!(count, expected_count, "mode {:#07b}", mode.mode);
+
+            for (channel, endpoints) in seen.iter().enumerate() {
+                assert_eq!(endpoints[0], (1 << mode.endpoint_bits) - 1);
+                for endpoint in &endpoints[1..mode.regions * 2] {
+                    assert_eq!(*endpoint, (1 << mode.delta_bits[channel]) - 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bc6h_solid_block() {
+        // 495 unquantizes to the half float 1.0
+        let mut writer = BitWriter::new();
+        writer.write(0b00011, 5);
+        for _ in 0..6 {
+            writer.write(495, 10);
+        }
+        writer.write(0, 63);
+        let block = writer.finish();
+
+        let mut colors = [[0.0; 3]; 16];
+        decode_bc6h_block(&block, false, &mut colors);
+        assert!(colors.iter().all(|rgb| *rgb == [1.0, 1.0, 1.0]));
+
+        let rgba = decode_bptc(&block, 3, 2, GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT).unwrap();
+        assert_eq!(rgba, vec![255; 3 * 2 * 4]);
+    }
+
+    #[test]
+    fn decode_bptc_rejects_truncated_data() {
+        assert!(decode_bptc(&[0; 16], 8, 4, GL_COMPRESSED_RGBA_BPTC_UNORM).is_err());
+        assert!(decode_bptc(&[0; 32], 8, 4, 0x1234).is_err());
+    }
+
+    /// Blocks and their pixels, row by row, as decoded by Mesa's llvmpipe through OpenGL
+    const REFERENCE_BLOCKS: [(&str, u32, &[u8], [u8; 64]); 18] = [
+        (
+            "BC7 mode 0",
+            GL_COMPRESSED_RGBA_BPTC_UNORM,
+            &[
+                0x39, 0x98, 0x69, 0x21, 0x39, 0x49, 0xC5, 0x1F, 0xBD, 0xFD, 0x2A, 0xA8, 0x86, 0xA8,
+                0x63, 0xE5,
+            ],
+            [
+                50, 163, 241, 255, 186, 143, 153, 255, 206, 156, 140, 255, 53, 72, 228, 255, 75,
+                170, 244, 255, 147, 118, 178, 255, 206, 156, 140, 255, 136, 134, 225, 255, 129,
+                185, 248, 255, 167, 131, 166, 255, 66, 66, 231, 255, 189, 173, 222, 255, 101, 177,
+                246, 255, 105, 91, 205, 255, 125, 104, 193, 255, 109, 114, 226, 255,
+            ],
+        ),
+        (
+            "BC7 mode 1",
+            GL_COMPRESSED_RGBA_BPTC_UNORM,
+            &[
+                0xDE, 0xC2, 0x74, 0x3C, 0x3E, 0xCE, 0x3B, 0x2D, 0x5C, 0x52, 0xD5, 0x30, 0x61, 0xD5,
+                0xCC, 0xF8,
+            ],
+            [
+                20, 248, 185, 255, 59, 234, 192, 255, 33, 215, 139, 255, 28, 241, 149, 255, 42,
+                163, 120, 255, 29, 244, 186, 255, 10, 251, 183, 255, 42, 163, 120, 255, 51, 108,
+                99, 255, 37, 189, 130, 255, 39, 241, 188, 255, 68, 230, 193, 255, 49, 237, 190,
+                255, 33, 215, 139, 255, 56, 82, 90, 255, 78, 227, 195, 255,
+            ],
+        ),
+        (
+            "BC7 mode 2",
+            GL_COMPRESSED_RGBA_BPTC_UNORM,
+            &[
+                0x54, 0x26, 0x11, 0xBD, 0x0D, 0x2C, 0x1D, 0x76, 0x10, 0xB7, 0xD7, 0x98, 0x91, 0x6F,
+                0x86, 0x10,
+            ],
+            [
+                156, 198, 198, 255, 116, 182, 168, 255, 73, 164, 137, 255, 33, 148, 107, 255, 239,
+                198, 107, 255, 166, 171, 112, 255, 89, 142, 118, 255, 16, 115, 123, 255, 24, 132,
+                49, 255, 107, 115, 99, 255, 107, 115, 99, 255, 80, 121, 83, 255, 107, 115, 99, 255,
+                51, 126, 65, 255, 107, 115, 99, 255, 107, 115, 99, 255,
+            ],
+        ),
+        (
+            "BC7 mode 3",
+            GL_COMPRESSED_RGBA_BPTC_UNORM,
+            &[
+                0xB8, 0x25, 0x2B, 0xF9, 0xFA, 0x8E, 0xD8, 0x0A, 0x04, 0x12, 0x07, 0x7D, 0xB1, 0xF7,
+                0xCA, 0x98,
+            ],
+            [
+                147, 119, 3, 255, 76, 130, 13, 255, 240, 62, 90, 255, 237, 31, 169, 255, 42, 136,
+                18, 255, 240, 62, 90, 255, 234, 2, 244, 255, 42, 136, 18, 255, 76, 130, 13, 255,
+                237, 31, 169, 255, 243, 91, 15, 255, 42, 136, 18, 255, 243, 91, 15, 255, 237, 31,
+                169, 255, 113, 125, 8, 255, 76, 130, 13, 255,
+            ],
+        ),
+        (
+            "BC7 mode 4",
+            GL_COMPRESSED_RGBA_BPTC_UNORM,
+            &[
+                0x90, 0x86, 0xD3, 0xD9, 0x91, 0x11, 0x0F, 0xC5, 0x4E, 0xC6, 0x77, 0xB4, 0xE2, 0xA7,
+                0xDE, 0x6B,
+            ],
+            [
+                126, 161, 166, 81, 205, 157, 90, 81, 75, 164, 215, 24, 100, 162, 190, 142, 126,
+                161, 166, 142, 180, 159, 115, 24, 49, 165, 239, 142, 231, 156, 66, 81, 231, 156,
+                66, 199, 154, 160, 139, 81, 100, 162, 190, 142, 231, 156, 66, 24, 180, 159, 115,
+                199, 231, 156, 66, 24, 100, 162, 190, 142, 126, 161, 166, 199,
+            ],
+        ),
+        (
+            "BC7 mode 5",
+            GL_COMPRESSED_RGBA_BPTC_UNORM,
+            &[
+                0xA0, 0x7B, 0x9A, 0x6D, 0x5E, 0xB5, 0xC8, 0x05, 0xF8, 0x12, 0x84, 0x53, 0xD0, 0xA4,
+                0xCB, 0x48,
+            ],
+            [
+                247, 114, 171, 108, 104, 114, 44, 231, 104, 77, 44, 231, 200, 1, 129, 148, 200,
+                114, 129, 148, 151, 77, 86, 191, 247, 38, 171, 108, 247, 38, 171, 108, 151, 1, 86,
+                191, 247, 38, 171, 108, 247, 114, 171, 108, 104, 1, 44, 231, 200, 114, 129, 148,
+                151, 38, 86, 191, 151, 114, 86, 191, 247, 77, 171, 108,
+            ],
+        ),
+        (
+            "BC7 mode 6",
+            GL_COMPRESSED_RGBA_BPTC_UNORM,
+            &[
+                0xC0, 0x78, 0x2D, 0xD5, 0xDD, 0xEE, 0x48, 0x17, 0x9B, 0xD9, 0x1A, 0x81, 0x6B, 0x2C,
+                0xE6, 0xCD,
+            ],
+            [
+                187, 116, 161, 64, 155, 144, 145, 57, 155, 144, 145, 57, 124, 172, 128, 51, 146,
+                153, 140, 55, 219, 89, 178, 70, 219, 89, 178, 70, 163, 138, 149, 59, 139, 159, 136,
+                54, 178, 125, 156, 62, 131, 166, 132, 52, 209, 97, 173, 68, 178, 125, 156, 62, 114,
+                180, 123, 49, 124, 172, 128, 51, 131, 166, 132, 52,
+            ],
+        ),
+        (
+            "BC7 mode 7",
+            GL_COMPRESSED_RGBA_BPTC_UNORM,
+            &[
+                0x80, 0xFB, 0x35, 0x2C, 0x49, 0x8B, 0xC9, 0x8C, 0xB4, 0x0E, 0x67, 0x0B, 0x12, 0xE3,
+                0x2D, 0xE3,
+            ],
+            [
+                186, 146, 154, 24, 93, 168, 143, 84, 186, 146, 154, 24, 84, 88, 170, 88, 90, 143,
+                166, 134, 186, 146, 154, 24, 48, 178, 138, 113, 48, 178, 138, 113, 84, 88, 170, 88,
+                90, 143, 166, 134, 90, 143, 166, 134, 93, 168, 143, 84, 141, 157, 149, 53, 97, 195,
+                162, 178, 77, 36, 174, 44, 90, 143, 166, 134,
+            ],
+        ),
+        (
+            "BC7 reserved mode",
+            GL_COMPRESSED_RGBA_BPTC_UNORM,
+            &[
+                0x00, 0xDC, 0x94, 0xDF, 0xED, 0xBC, 0xC2, 0x44, 0xF8, 0x40, 0x93, 0xD1, 0x85, 0x56,
+                0x8C, 0x32,
+            ],
+            [
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+        ),
+        (
+            "BC6H unsigned mode 0b00",
+            GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            &[
+                0x58, 0xB1, 0xCE, 0xCC, 0xEB, 0x90, 0xA6, 0xDE, 0x3C, 0x63, 0x1A, 0x66, 0x31, 0x8C,
+                0x35, 0x07,
+            ],
+            [
+                31, 45, 217, 255, 31, 56, 180, 255, 30, 56, 182, 255, 32, 54, 177, 255, 30, 38,
+                210, 255, 31, 45, 217, 255, 33, 54, 175, 255, 31, 56, 180, 255, 30, 42, 214, 255,
+                31, 47, 219, 255, 30, 38, 210, 255, 31, 55, 178, 255, 30, 43, 215, 255, 30, 38,
+                210, 255, 31, 47, 219, 255, 31, 48, 220, 255,
+            ],
+        ),
+        (
+            "BC6H unsigned mode 0b01",
+            GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            &[
+                0x11, 0x27, 0x52, 0x42, 0x61, 0x12, 0x22, 0xEA, 0x77, 0x03, 0x59, 0x3E, 0x45, 0x4D,
+                0xD3, 0x1B,
+            ],
+            [
+                191, 8, 6, 255, 255, 26, 25, 255, 255, 17, 15, 255, 255, 55, 62, 255, 139, 6, 4,
+                255, 255, 26, 25, 255, 107, 4, 2, 255, 255, 26, 25, 255, 255, 37, 41, 255, 255, 17,
+                15, 255, 255, 37, 41, 255, 129, 0, 2, 255, 255, 37, 41, 255, 243, 12, 9, 255, 102,
+                1, 1, 255, 47, 16, 0, 255,
+            ],
+        ),
+        (
+            "BC6H unsigned mode 0b00011",
+            GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            &[
+                0x23, 0x38, 0xDD, 0xCA, 0x8B, 0x70, 0x1F, 0xA3, 0x42, 0xF2, 0x6D, 0x22, 0x30, 0x73,
+                0xA7, 0xDC,
+            ],
+            [
+                112, 66, 178, 255, 159, 30, 90, 255, 124, 50, 130, 255, 255, 2, 8, 255, 255, 3, 12,
+                255, 203, 17, 56, 255, 124, 50, 130, 255, 124, 50, 130, 255, 102, 89, 216, 255,
+                140, 39, 110, 255, 140, 39, 110, 255, 222, 13, 46, 255, 222, 13, 46, 255, 255, 6,
+                23, 255, 255, 4, 15, 255, 255, 3, 12, 255,
+            ],
+        ),
+        (
+            "BC6H unsigned mode 0b00111",
+            GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            &[
+                0x07, 0x75, 0x3D, 0xC9, 0xC5, 0x01, 0xB4, 0x9E, 0xBA, 0x24, 0xCA, 0x4A, 0x69, 0x33,
+                0x40, 0xF5,
+            ],
+            [
+                185, 4, 10, 255, 229, 3, 4, 255, 179, 5, 11, 255, 165, 6, 14, 255, 222, 3, 5, 255,
+                236, 3, 4, 255, 222, 3, 5, 255, 179, 5, 11, 255, 214, 3, 6, 255, 194, 4, 8, 255,
+                172, 5, 13, 255, 172, 5, 13, 255, 150, 6, 19, 255, 179, 5, 11, 255, 185, 4, 10,
+                255, 255, 2, 3, 255,
+            ],
+        ),
+        (
+            "BC6H unsigned mode 0b01110",
+            GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            &[
+                0xAE, 0x10, 0x77, 0x99, 0xC9, 0x94, 0xFC, 0x42, 0x5F, 0xB3, 0xA7, 0x08, 0xE8, 0x8B,
+                0xBE, 0x1F,
+            ],
+            [
+                2, 188, 45, 255, 2, 192, 47, 255, 2, 188, 45, 255, 2, 192, 47, 255, 4, 137, 63,
+                255, 4, 137, 63, 255, 3, 254, 71, 255, 3, 255, 75, 255, 4, 160, 65, 255, 4, 160,
+                65, 255, 4, 183, 66, 255, 3, 255, 75, 255, 2, 197, 48, 255, 2, 215, 54, 255, 2,
+                215, 54, 255, 2, 184, 44, 255,
+            ],
+        ),
+        (
+            "BC6H unsigned mode 0b11110",
+            GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT,
+            &[
+                0x7E, 0x37, 0x8E, 0xA7, 0xEC, 0xDE, 0x0A, 0x6F, 0xC7, 0x53, 0x8A, 0xFB, 0x0D, 0x38,
+                0x91, 0x09,
+            ],
+            [
+                255, 63, 15, 255, 255, 115, 6, 255, 164, 15, 226, 255, 255, 21, 130, 255, 164, 15,
+                226, 255, 255, 27, 81, 255, 255, 89, 10, 255, 255, 115, 6, 255, 255, 255, 0, 255,
+                164, 15, 226, 255, 255, 36, 49, 255, 255, 115, 6, 255, 255, 255, 1, 255, 255, 255,
+                2, 255, 255, 255, 1, 255, 255, 115, 6, 255,
+            ],
+        ),
+        (
+            "BC6H signed mode 0b00",
+            GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+            &[
+                0x34, 0x9A, 0x06, 0xDE, 0x79, 0x99, 0x70, 0x3E, 0x58, 0x96, 0xA3, 0xA0, 0x08, 0xBC,
+                0x17, 0x9F,
+            ],
+            [
+                54, 0, 191, 255, 62, 0, 183, 255, 58, 0, 187, 255, 90, 0, 199, 255, 85, 0, 169,
+                255, 90, 0, 199, 255, 90, 0, 196, 255, 90, 0, 199, 255, 90, 0, 192, 255, 90, 0,
+                176, 255, 90, 0, 179, 255, 68, 0, 178, 255, 90, 0, 196, 255, 93, 0, 165, 255, 102,
+                0, 160, 255, 77, 0, 173, 255,
+            ],
+        ),
+        (
+            "BC6H signed mode 0b00011",
+            GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+            &[
+                0x23, 0x2C, 0x43, 0xA2, 0x1E, 0x00, 0x97, 0xB8, 0x5E, 0x3D, 0x82, 0x86, 0xB8, 0x23,
+                0xC7, 0x16,
+            ],
+            [
+                23, 6, 0, 255, 185, 5, 0, 255, 0, 14, 255, 255, 255, 4, 0, 255, 255, 3, 0, 255, 9,
+                7, 1, 255, 57, 5, 0, 255, 9, 7, 1, 255, 9, 7, 1, 255, 0, 11, 102, 255, 255, 4, 0,
+                255, 255, 3, 0, 255, 23, 6, 0, 255, 0, 12, 255, 255, 57, 5, 0, 255, 255, 3, 0, 255,
+            ],
+        ),
+        (
+            "BC6H signed mode 0b10110",
+            GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+            &[
+                0xD6, 0x46, 0x2D, 0x76, 0xC8, 0x4A, 0xC3, 0xAA, 0x33, 0x03, 0x27, 0xE6, 0x9D, 0xF4,
+                0xFB, 0xA5,
+            ],
+            [
+                62, 255, 132, 255, 55, 255, 106, 255, 24, 255, 107, 255, 58, 255, 127, 255, 28,
+                255, 36, 255, 47, 255, 82, 255, 24, 255, 28, 255, 84, 255, 141, 255, 39, 255, 60,
+                255, 28, 255, 36, 255, 24, 255, 28, 255, 113, 255, 154, 255, 24, 255, 28, 255, 47,
+                255, 82, 255, 62, 255, 132, 255, 32, 255, 48, 255,
+            ],
+        ),
+    ];
+
+    #[test]
+    fn matches_reference_decoder() {
+        for (mode, format, block, expected) in REFERENCE_BLOCKS {
+            assert_eq!(
+                decode_bptc(block, 4, 4, format).unwrap(),
+                expected,
+                "{}",
+                mode
+            );
+        }
+    }
+}
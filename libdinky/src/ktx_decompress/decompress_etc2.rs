@@ -0,0 +1,522 @@
+use super::decompress_software::{decode_blocks, unsupported_format};
+use crate::Result;
+
+pub const GL_ETC1_RGB8_OES: u32 = 0x8D64;
+pub const GL_COMPRESSED_RGB8_ETC2: u32 = 0x9274;
+pub const GL_COMPRESSED_SRGB8_ETC2: u32 = 0x9275;
+pub const GL_COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2: u32 = 0x9276;
+pub const GL_COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2: u32 = 0x9277;
+pub const GL_COMPRESSED_RGBA8_ETC2_EAC: u32 = 0x9278;
+pub const GL_COMPRESSED_SRGB8_ALPHA8_ETC2_EAC: u32 = 0x9279;
+
+/// Decodes an ETC1 or ETC2 compressed image to RGBA8 pixels
+pub fn decode_etc2(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    gl_internal_format: u32,
+) -> Result<Vec<u8>> {
+    match gl_internal_format {
+        GL_ETC1_RGB8_OES | GL_COMPRESSED_RGB8_ETC2 | GL_COMPRESSED_SRGB8_ETC2 => {
+            decode_blocks(data, width, height, (4, 4, 8), |block, out| {
+                decode_etc2_block(block, false, out)
+            })
+        }
+        GL_COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2
+        | GL_COMPRESSED_SRGB8_PUNCHTHROUGH_ALPHA1_ETC2 => {
+            decode_blocks(data, width, height, (4, 4, 8), |block, out| {
+                decode_etc2_block(block, true, out)
+            })
+        }
+        GL_COMPRESSED_RGBA8_ETC2_EAC | GL_COMPRESSED_SRGB8_ALPHA8_ETC2_EAC => {
+            decode_blocks(data, width, height, (4, 4, 16), |block, out| {
+                decode_etc2_block(&block[8..], false, out);
+                decode_eac_alpha_block(&block[..8], out);
+            })
+        }
+        _ => Err(unsupported_format(gl_internal_format)),
+    }
+}
+
+const INTENSITY_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+const DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+fn read_u64(block: &[u8]) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&block[..8]);
+    u64::from_be_bytes(bytes)
+}
+
+fn field(bits: u64, shift: u32, count: u32) -> i32 {
+    ((bits >> shift) & ((1 << count) - 1)) as i32
+}
+
+/// Expands a color component to 8 bits by replicating its high bits
+fn extend(value: i32, bits: u32) -> i32 {
+    let value = value << (8 - bits);
+    value | (value >> bits)
+}
+
+/// Index of a pixel, the pixels are stored column by column
+fn pixel_index(bits: u64, x: usize, y: usize) -> usize {
+    let position = x * 4 + y;
+    ((((bits >> (position + 16)) & 1) << 1) | ((bits >> position) & 1)) as usize
+}
+
+fn to_rgba(color: [i32; 3]) -> [u8; 4] {
+    [
+        color[0].clamp(0, 255) as u8,
+        color[1].clamp(0, 255) as u8,
+        color[2].clamp(0, 255) as u8,
+        255,
+    ]
+}
+
+/// Decodes the RGB part of a block. With `punchthrough` the differential bit tells if the block
+/// is opaque, and transparent blocks use index 2 for transparent black.
+fn decode_etc2_block(block: &[u8], punchthrough: bool, out: &mut [[u8; 4]]) {
+    let bits = read_u64(block);
+    let differential = punchthrough || (bits >> 33) & 1 == 1;
+    let transparent = punchthrough && (bits >> 33) & 1 == 0;
+
+    if !differential {
+        let color1 = [
+            extend(field(bits, 60, 4), 4),
+            extend(field(bits, 52, 4), 4),
+            extend(field(bits, 44, 4), 4),
+        ];
+        let color2 = [
+            extend(field(bits, 56, 4), 4),
+            extend(field(bits, 48, 4), 4),
+            extend(field(bits, 40, 4), 4),
+        ];
+        decode_subblocks(bits, [color1, color2], false, out);
+        return;
+    }
+
+    let base = [field(bits, 59, 5), field(bits, 51, 5), field(bits, 43, 5)];
+    let delta =
+        [field(bits, 56, 3), field(bits, 48, 3), field(bits, 40, 3)].map(|d| (d << 29) >> 29);
+    let second = [base[0] + delta[0], base[1] + delta[1], base[2] + delta[2]];
+
+    if !(0..32).contains(&second[0]) {
+        decode_t_mode(bits, transparent, out);
+    } else if !(0..32).contains(&second[1]) {
+        decode_h_mode(bits, transparent, out);
+    } else if !(0..32).contains(&second[2]) {
+        decode_planar_mode(bits, out);
+    } else {
+        let color1 = base.map(|c| extend(c, 5));
+        let color2 = second.map(|c| extend(c, 5));
+        decode_subblocks(bits, [color1, color2], transparent, out);
+    }
+}
+
+/// Individual and differential modes, two subblocks with a base color and modifier table each
+fn decode_subblocks(bits: u64, colors: [[i32; 3]; 2], transparent: bool, out: &mut [[u8; 4]]) {
+    let flip = (bits >> 32) & 1 == 1;
+    let tables = [field(bits, 37, 3) as usize, field(bits, 34, 3) as usize];
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let subblock = if flip { y / 2 } else { x / 2 };
+            let index = pixel_index(bits, x, y);
+            let [small, large] = INTENSITY_MODIFIERS[tables[subblock]];
+
+            let modifier = match index {
+                0 if transparent => 0,
+                0 => small,
+                1 => large,
+                2 if transparent => {
+                    out[y * 4 + x] = [0; 4];
+                    continue;
+                }
+                2 => -small,
+                _ => -large,
+            };
+
+            out[y * 4 + x] = to_rgba(colors[subblock].map(|c| c + modifier));
+        }
+    }
+}
+
+fn decode_paint_colors(bits: u64, paint: [[i32; 3]; 4], transparent: bool, out: &mut [[u8; 4]]) {
+    for y in 0..4 {
+        for x in 0..4 {
+            let index = pixel_index(bits, x, y);
+            out[y * 4 + x] = if transparent && index == 2 {
+                [0; 4]
+            } else {
+                to_rgba(paint[index])
+            };
+        }
+    }
+}
+
+fn decode_t_mode(bits: u64, transparent: bool, out: &mut [[u8; 4]]) {
+    let color1 = [
+        extend((field(bits, 59, 2) << 2) | field(bits, 56, 2), 4),
+        extend(field(bits, 52, 4), 4),
+        extend(field(bits, 48, 4), 4),
+    ];
+    let color2 = [
+        extend(field(bits, 44, 4), 4),
+        extend(field(bits, 40, 4), 4),
+        extend(field(bits, 36, 4), 4),
+    ];
+    let distance = DISTANCES[((field(bits, 34, 2) << 1) | field(bits, 32, 1)) as usize];
+
+    let paint = [
+        color1,
+        color2.map(|c| c + distance),
+        color2,
+        color2.map(|c| c - distance),
+    ];
+    decode_paint_colors(bits, paint, transparent, out);
+}
+
+fn decode_h_mode(bits: u64, transparent: bool, out: &mut [[u8; 4]]) {
+    let color1 = [
+        field(bits, 59, 4),
+        (field(bits, 56, 3) << 1) | field(bits, 52, 1),
+        (field(bits, 51, 1) << 3) | field(bits, 47, 3),
+    ];
+    let color2 = [field(bits, 43, 4), field(bits, 39, 4), field(bits, 35, 4)];
+
+    // The order of the colors stores the lowest bit of the distance index
+    let value1 = (color1[0] << 8) | (color1[1] << 4) | color1[2];
+    let value2 = (color2[0] << 8) | (color2[1] << 4) | color2[2];
+    let distance_index =
+        (field(bits, 34, 1) << 2) | (field(bits, 32, 1) << 1) | (value1 >= value2) as i32;
+    let distance = DISTANCES[distance_index as usize];
+
+    let color1 = color1.map(|c| extend(c, 4));
+    let color2 = color2.map(|c| extend(c, 4));
+    let paint = [
+        color1.map(|c| c + distance),
+        color1.map(|c| c - distance),
+        color2.map(|c| c + distance),
+        color2.map(|c| c - distance),
+    ];
+    decode_paint_colors(bits, paint, transparent, out);
+}
+
+/// Planar mode, the colors are interpolated from the origin, horizontal and vertical colors
+fn decode_planar_mode(bits: u64, out: &mut [[u8; 4]]) {
+    let origin = [
+        extend(field(bits, 57, 6), 6),
+        extend((field(bits, 56, 1) << 6) | field(bits, 49, 6), 7),
+        extend(
+            (field(bits, 48, 1) << 5) | (field(bits, 43, 2) << 3) | field(bits, 39, 3),
+            6,
+        ),
+    ];
+    let horizontal = [
+        extend((field(bits, 34, 5) << 1) | field(bits, 32, 1), 6),
+        extend(field(bits, 25, 7), 7),
+        extend(field(bits, 19, 6), 6),
+    ];
+    let vertical = [
+        extend(field(bits, 13, 6), 6),
+        extend(field(bits, 6, 7), 7),
+        extend(field(bits, 0, 6), 6),
+    ];
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let color = [0, 1, 2].map(|c| {
+                (x * (horizontal[c] - origin[c])
+                    + y * (vertical[c] - origin[c])
+                    + 4 * origin[c]
+                    + 2)
+                    >> 2
+            });
+            out[(y * 4 + x) as usize] = to_rgba(color);
+        }
+    }
+}
+
+/// Decodes the alpha of an EAC block into the alpha channel of `out`
+fn decode_eac_alpha_block(block: &[u8], out: &mut [[u8; 4]]) {
+    let bits = read_u64(block);
+    let base = field(bits, 56, 8);
+    let multiplier = field(bits, 52, 4);
+    let modifiers = EAC_MODIFIERS[field(bits, 48, 4) as usize];
+
+    for y in 0..4 {
+        for x in 0..4 {
+            let index = field(bits, 45 - 3 * (x * 4 + y) as u32, 3) as usize;
+            let alpha = base + modifiers[index] * multiplier;
+            out[y * 4 + x][3] = alpha.clamp(0, 255) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds the pixel index part of a block from row by row indices
+    fn pixel_indices(indices: [u64; 16]) -> u64 {
+        let mut bits = 0;
+        for (pixel, index) in indices.iter().enumerate() {
+            let position = (pixel % 4) * 4 + pixel / 4;
+            bits |= ((index >> 1) << (position + 16)) | ((index & 1) << position);
+        }
+        bits
+    }
+
+    #[test]
+    fn individual_mode_subblocks() {
+        // Left subblock is 0x11 gray with table 0, right is 0xEE gray with table 7
+        let header: u64 = (0x1E << 56) | (0x1E << 48) | (0x1E << 40) | (7 << 34);
+        let indices = [0, 1, 2, 3, 0, 1, 2, 3, 3, 2, 1, 0, 3, 2, 1, 0];
+        let block = (header | pixel_indices(indices)).to_be_bytes();
+
+        let mut pixels = [[0; 4]; 16];
+        decode_etc2_block(&block, false, &mut pixels);
+
+        let expected = |x: usize, index: u64| {
+            let (color, [small, large]) = if x < 2 {
+                (0x11, INTENSITY_MODIFIERS[0])
+            } else {
+                (0xEE, INTENSITY_MODIFIERS[7])
+            };
+            let modifier = [small, large, -small, -large][index as usize];
+            (color + modifier).clamp(0, 255) as u8
+        };
+        for (pixel, rgba) in pixels.iter().enumerate() {
+            let value = expected(pixel % 4, indices[pixel]);
+            assert_eq!(*rgba, [value, value, value, 255]);
+        }
+    }
+
+    #[test]
+    fn punchthrough_transparent_pixels() {
+        // Differential mode without the opaque bit
+        let header: u64 = (16 << 59) | (16 << 51) | (16 << 43);
+        let indices = [2, 0, 1, 3, 2, 2, 2, 2, 0, 0, 0, 0, 1, 1, 1, 1];
+        let block = (header | pixel_indices(indices)).to_be_bytes();
+
+        let mut pixels = [[0; 4]; 16];
+        decode_etc2_block(&block, true, &mut pixels);
+
+        assert_eq!(pixels[0], [0, 0, 0, 0]);
+        assert_eq!(pixels[1], [132, 132, 132, 255]);
+        assert_eq!(pixels[2], [140, 140, 140, 255]);
+        assert_eq!(pixels[3], [124, 124, 124, 255]);
+    }
+
+    #[test]
+    fn planar_mode_gradient() {
+        let origin = [10u64, 20, 30];
+        let horizontal = [40u64, 80, 50];
+        let vertical = [60u64, 100, 5];
+        let mut bits: u64 = 1 << 33;
+        bits |= origin[0] << 57;
+        bits |= (origin[1] >> 6) << 56 | (origin[1] & 0x3F) << 49;
+        bits |= (origin[2] >> 5) << 48 | ((origin[2] >> 3) & 3) << 43 | (origin[2] & 7) << 39;
+        bits |= (horizontal[0] >> 1) << 34 | (horizontal[0] & 1) << 32;
+        bits |= horizontal[1] << 25 | horizontal[2] << 19;
+        bits |= vertical[0] << 13 | vertical[1] << 6 | vertical[2];
+        // Unused bits keep red from overflowing and make blue overflow
+        bits |= 1 << 63 | 0b111 << 45;
+
+        let mut pixels = [[0; 4]; 16];
+        decode_etc2_block(&bits.to_be_bytes(), false, &mut pixels);
+
+        let origin_rgb = [
+            extend(origin[0] as i32, 6),
+            extend(origin[1] as i32, 7),
+            extend(origin[2] as i32, 6),
+        ];
+        assert_eq!(pixels[0], to_rgba(origin_rgb));
+        // As decoded by Mesa's llvmpipe
+        assert_eq!(
+            pixels,
+            [
+                [40, 40, 121, 255],
+                [71, 70, 142, 255],
+                [101, 101, 162, 255],
+                [132, 131, 183, 255],
+                [91, 80, 96, 255],
+                [121, 111, 116, 255],
+                [152, 141, 137, 255],
+                [182, 171, 157, 255],
+                [142, 121, 71, 255],
+                [172, 151, 91, 255],
+                [203, 181, 112, 255],
+                [233, 211, 132, 255],
+                [192, 161, 45, 255],
+                [223, 191, 66, 255],
+                [253, 221, 86, 255],
+                [255, 252, 107, 255],
+            ]
+        );
+    }
+
+    #[test]
+    fn eac_alpha() {
+        let indices: u64 = (0..16).fold(0, |bits, pixel| (bits << 3) | (pixel % 8));
+        // Base 100, multiplier 3, table 0
+        let bits: u64 = (100 << 56) | (3 << 52) | indices;
+
+        let mut pixels = [[0; 4]; 16];
+        decode_eac_alpha_block(&bits.to_be_bytes(), &mut pixels);
+
+        for y in 0..4 {
+            for x in 0..4 {
+                let index = (x * 4 + y) % 8;
+                let expected = 100 + EAC_MODIFIERS[0][index] * 3;
+                assert_eq!(pixels[y * 4 + x][3], expected as u8);
+            }
+        }
+    }
+
+    /// Blocks and their pixels, row by row, as decoded by Mesa's llvmpipe through OpenGL
+    const REFERENCE_BLOCKS: [(&str, u32, &[u8], [u8; 64]); 9] = [
+        (
+            "individual",
+            GL_COMPRESSED_RGB8_ETC2,
+            &[0x45, 0x6A, 0xD9, 0x44, 0x06, 0xA2, 0xF4, 0x7B],
+            [
+                97, 131, 250, 255, 97, 131, 250, 255, 90, 175, 158, 255, 102, 187, 170, 255, 39,
+                73, 192, 255, 39, 73, 192, 255, 80, 165, 148, 255, 102, 187, 170, 255, 77, 111,
+                230, 255, 97, 131, 250, 255, 68, 153, 136, 255, 102, 187, 170, 255, 97, 131, 250,
+                255, 59, 93, 212, 255, 90, 175, 158, 255, 102, 187, 170, 255,
+            ],
+        ),
+        (
+            "differential",
+            GL_COMPRESSED_RGB8_ETC2,
+            &[0x76, 0x8B, 0x83, 0x96, 0x58, 0x8F, 0x1B, 0x68],
+            [
+                97, 122, 114, 255, 133, 158, 150, 255, 179, 245, 236, 255, 19, 85, 76, 255, 97,
+                122, 114, 255, 175, 200, 192, 255, 179, 245, 236, 255, 123, 189, 180, 255, 97, 122,
+                114, 255, 175, 200, 192, 255, 123, 189, 180, 255, 75, 141, 132, 255, 55, 80, 72,
+                255, 97, 122, 114, 255, 19, 85, 76, 255, 123, 189, 180, 255,
+            ],
+        ),
+        (
+            "T mode",
+            GL_COMPRESSED_RGB8_ETC2,
+            &[0x06, 0x46, 0x1D, 0x82, 0x94, 0x0E, 0x99, 0xEA],
+            [
+                34, 68, 102, 255, 34, 68, 102, 255, 20, 224, 139, 255, 14, 218, 133, 255, 14, 218,
+                133, 255, 20, 224, 139, 255, 34, 68, 102, 255, 34, 68, 102, 255, 17, 221, 136, 255,
+                20, 224, 139, 255, 17, 221, 136, 255, 34, 68, 102, 255, 14, 218, 133, 255, 20, 224,
+                139, 255, 20, 224, 139, 255, 14, 218, 133, 255,
+            ],
+        ),
+        (
+            "H mode",
+            GL_COMPRESSED_RGB8_ETC2,
+            &[0x58, 0xF3, 0x4A, 0xEB, 0x88, 0x57, 0x40, 0x3C],
+            [
+                169, 101, 237, 255, 137, 69, 205, 255, 203, 33, 118, 255, 203, 33, 118, 255, 169,
+                101, 237, 255, 171, 1, 86, 255, 203, 33, 118, 255, 203, 33, 118, 255, 137, 69, 205,
+                255, 169, 101, 237, 255, 203, 33, 118, 255, 171, 1, 86, 255, 171, 1, 86, 255, 203,
+                33, 118, 255, 169, 101, 237, 255, 169, 101, 237, 255,
+            ],
+        ),
+        (
+            "planar",
+            GL_COMPRESSED_RGB8_ETC2,
+            &[0xAE, 0x7E, 0x15, 0xF2, 0x3A, 0xF4, 0x24, 0x8B],
+            [
+                93, 126, 77, 255, 127, 109, 88, 255, 160, 92, 99, 255, 194, 75, 110, 255, 103, 104,
+                69, 255, 137, 87, 80, 255, 170, 70, 91, 255, 204, 53, 102, 255, 114, 81, 61, 255,
+                147, 64, 72, 255, 181, 47, 83, 255, 214, 30, 94, 255, 124, 59, 52, 255, 157, 42,
+                63, 255, 191, 25, 74, 255, 224, 8, 85, 255,
+            ],
+        ),
+        (
+            "punchthrough differential",
+            GL_COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            &[0x3F, 0x57, 0x62, 0x4C, 0x59, 0x58, 0x1C, 0x2F],
+            [
+                86, 111, 128, 255, 0, 0, 0, 0, 0, 0, 0, 0, 7, 32, 73, 255, 86, 111, 128, 255, 86,
+                111, 128, 255, 49, 74, 115, 255, 49, 74, 115, 255, 86, 111, 128, 255, 0, 0, 0, 0,
+                91, 116, 157, 255, 0, 0, 0, 0, 28, 53, 70, 255, 57, 82, 99, 255, 7, 32, 73, 255,
+                49, 74, 115, 255,
+            ],
+        ),
+        (
+            "punchthrough T mode",
+            GL_COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            &[0xF3, 0xE8, 0x41, 0x35, 0x3A, 0x49, 0xEF, 0xA3],
+            [
+                52, 1, 35, 255, 187, 238, 136, 255, 84, 33, 67, 255, 0, 0, 0, 0, 84, 33, 67, 255,
+                84, 33, 67, 255, 52, 1, 35, 255, 52, 1, 35, 255, 187, 238, 136, 255, 0, 0, 0, 0,
+                84, 33, 67, 255, 84, 33, 67, 255, 0, 0, 0, 0, 84, 33, 67, 255, 52, 1, 35, 255, 84,
+                33, 67, 255,
+            ],
+        ),
+        (
+            "punchthrough H mode",
+            GL_COMPRESSED_RGB8_PUNCHTHROUGH_ALPHA1_ETC2,
+            &[0xD4, 0xFA, 0xFC, 0x29, 0xB1, 0xD7, 0x26, 0x66],
+            [
+                0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 244, 125, 74, 255, 159, 142, 210,
+                255, 159, 142, 210, 255, 244, 125, 74, 255, 244, 125, 74, 255, 244, 125, 74, 255,
+                159, 142, 210, 255, 181, 164, 232, 255, 181, 164, 232, 255, 0, 0, 0, 0, 181, 164,
+                232, 255, 0, 0, 0, 0,
+            ],
+        ),
+        (
+            "EAC alpha",
+            GL_COMPRESSED_RGBA8_ETC2_EAC,
+            &[
+                0x6A, 0x75, 0x0E, 0x05, 0x71, 0xE4, 0x8E, 0x94, 0x42, 0x94, 0xB7, 0x0F, 0xD2, 0x2D,
+                0xC5, 0x45,
+            ],
+            [
+                58, 140, 173, 85, 68, 150, 183, 43, 74, 156, 189, 176, 64, 146, 179, 176, 68, 150,
+                183, 29, 64, 146, 179, 148, 64, 146, 179, 57, 68, 150, 183, 43, 40, 73, 131, 120,
+                124, 157, 215, 162, 124, 157, 215, 57, 40, 73, 131, 43, 69, 102, 160, 85, 95, 128,
+                186, 57, 95, 128, 186, 85, 40, 73, 131, 120,
+            ],
+        ),
+    ];
+
+    #[test]
+    fn matches_reference_decoder() {
+        for (mode, format, block, expected) in REFERENCE_BLOCKS {
+            assert_eq!(
+                decode_etc2(block, 4, 4, format).unwrap(),
+                expected,
+                "{}",
+                mode
+            );
+        }
+    }
+}
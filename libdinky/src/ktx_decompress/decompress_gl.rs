@@ -1,9 +1,12 @@
 use glutin::PossiblyCurrent;
-use ktx::KtxInfo;
 use std::ffi::c_void;
 
-use crate::ktx_decompressor::KTXDecompressor;
+use crate::{
+    ktx_decompress::decompress_software::decode_texture, ktx_decompressor::KTXDecompressor, Error,
+    Result,
+};
 
+#[allow(clippy::all)]
 mod gl {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
@@ -11,11 +14,84 @@ mod gl {
 pub struct OpenGLKTXDecompressor {
     _event_loop: glutin::event_loop::EventLoop<()>,
     _context: glutin::Context<PossiblyCurrent>,
+    /// Compressed formats the driver can decode, others are decoded in software
+    supported_formats: Vec<u32>,
+}
+
+/// Returns an error if an OpenGL call since the last check failed
+unsafe fn check_gl_error(action: &str) -> Result<()> {
+    match gl::GetError() {
+        gl::NO_ERROR => Ok(()),
+        error => Err(Error::Texture(format!(
+            "OpenGL error {:#06X} while {}",
+            error, action
+        ))),
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+fn create_event_loop() -> Result<glutin::event_loop::EventLoop<()>> {
+    use glutin::platform::unix::EventLoopBuilderExtUnix;
+
+    // winit panics instead of returning an error when it can't connect to the display server,
+    // so check that it will be able to first, the same way it picks one
+    let wayland = || wayland_client::Display::connect_to_env().is_ok();
+    let x11 = || {
+        x11_dl::xlib::Xlib::open().is_ok_and(|xlib| unsafe {
+            let display = (xlib.XOpenDisplay)(std::ptr::null());
+            if !display.is_null() {
+                (xlib.XCloseDisplay)(display);
+            }
+            !display.is_null()
+        })
+    };
+    let available = match std::env::var("WINIT_UNIX_BACKEND").as_deref() {
+        Ok("x11") => x11(),
+        Ok("wayland") => wayland(),
+        Ok(_) => false,
+        Err(_) => wayland() || x11(),
+    };
+    if !available {
+        return Err(Error::Texture(
+            "No display to create an OpenGL context on".to_string(),
+        ));
+    }
+
+    Ok(glutin::event_loop::EventLoopBuilder::new()
+        .with_any_thread(true)
+        .build())
+}
+
+#[cfg(target_os = "windows")]
+fn create_event_loop() -> Result<glutin::event_loop::EventLoop<()>> {
+    use glutin::platform::windows::EventLoopBuilderExtWindows;
+
+    Ok(glutin::event_loop::EventLoopBuilder::new()
+        .with_any_thread(true)
+        .build())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "windows",
+)))]
+fn create_event_loop() -> Result<glutin::event_loop::EventLoop<()>> {
+    Ok(glutin::event_loop::EventLoop::new())
 }
 
 impl OpenGLKTXDecompressor {
-    pub fn new() -> Self {
-        let event_loop = glutin::event_loop::EventLoop::new();
+    pub fn new() -> Result<Self> {
+        let event_loop = create_event_loop()?;
         let contextbuilder = glutin::ContextBuilder::new();
         let context = contextbuilder
             .build_headless(
@@ -25,35 +101,45 @@ impl OpenGLKTXDecompressor {
                     height: 1,
                 },
             )
-            .expect("Failed to create OpenGL Context");
+            .map_err(|e| Error::Texture(format!("Failed to create OpenGL context: {}", e)))?;
 
         unsafe {
-            let current_context = context
-                .make_current()
-                .expect("Failed to make OpenGL context current");
+            let current_context = context.make_current().map_err(|(_, e)| {
+                Error::Texture(format!("Failed to make OpenGL context current: {}", e))
+            })?;
 
             gl::load_with(|s| current_context.get_proc_address(s) as *const _);
-            
+
             let mut gl_texture: u32 = 0;
             gl::Enable(gl::TEXTURE_2D);
             gl::GenTextures(1, &mut gl_texture);
             gl::BindTexture(gl::TEXTURE_2D, gl_texture);
 
-            return Self {
+            let mut format_count = 0;
+            gl::GetIntegerv(gl::NUM_COMPRESSED_TEXTURE_FORMATS, &mut format_count);
+            let mut supported_formats = vec![0; format_count.max(0) as usize];
+            gl::GetIntegerv(
+                gl::COMPRESSED_TEXTURE_FORMATS,
+                supported_formats.as_mut_ptr(),
+            );
+            check_gl_error("setting up the texture")?;
+
+            Ok(Self {
                 _event_loop: event_loop,
                 _context: current_context,
-            };
+                supported_formats: supported_formats.into_iter().map(|f| f as u32).collect(),
+            })
         }
     }
 
     fn gpu_decompress_texture(
         &self,
-        ktx_texture_data: &Vec<u8>,
+        ktx_texture_data: &[u8],
         target_texture_data: &mut Vec<u8>,
         width: u32,
         height: u32,
         gl_internal_format: u32,
-    ) {
+    ) -> Result<()> {
         unsafe {
             gl::CompressedTexImage2D(
                 gl::TEXTURE_2D,
@@ -65,6 +151,7 @@ impl OpenGLKTXDecompressor {
                 ktx_texture_data.len() as gl::types::GLsizei,
                 ktx_texture_data.as_ptr() as *const c_void,
             );
+            check_gl_error("uploading the compressed texture")?;
 
             gl::GetTexImage(
                 gl::TEXTURE_2D,
@@ -73,39 +160,39 @@ impl OpenGLKTXDecompressor {
                 gl::UNSIGNED_BYTE,
                 target_texture_data.as_mut_ptr() as *mut c_void,
             );
+            check_gl_error("reading back the decompressed texture")
         }
     }
 }
 
 impl KTXDecompressor for OpenGLKTXDecompressor {
-    fn decompress_ktx(&self, data: &Vec<u8>, output_texture_data: &mut Vec<u8>) -> () {
-        let cursor = std::io::Cursor::new(&data);
-        let decoder = ktx::Decoder::new(cursor).expect("Failed to create KTX decoder");
-
-        let width = decoder.pixel_width();
-        let height = decoder.pixel_height();
-        let gl_internal_format = decoder.gl_internal_format();
-
-        let textures: Vec<Vec<u8>> = decoder.read_textures().take(1).collect();
-        let ktx_texture_data = &textures[0];
+    fn decompress_image(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        gl_internal_format: u32,
+    ) -> Result<Vec<u8>> {
+        if !self.supported_formats.contains(&gl_internal_format) {
+            return decode_texture(data, width, height, gl_internal_format);
+        }
 
-        let mut target_texture_data: Vec<u8> = Vec::new();
-        target_texture_data.resize((width * height * 4) as usize, 0);
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(|| {
+                Error::Texture(format!("A {}x{} texture is too large", width, height))
+            })?;
+        let mut target_texture_data: Vec<u8> = vec![0; size];
 
         self.gpu_decompress_texture(
-            ktx_texture_data,
+            data,
             &mut target_texture_data,
             width,
             height,
             gl_internal_format,
-        );
-
-        let mut encoder = png::Encoder::new(output_texture_data, width, height);
-        encoder.set_color(png::ColorType::Rgba);
-        encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = encoder.write_header().expect("Failed to write PNG header");
-        writer
-            .write_image_data(&target_texture_data)
-            .expect("Failed to write PNG data");
+        )?;
+
+        Ok(target_texture_data)
     }
 }
@@ -0,0 +1,106 @@
+use super::{
+    decompress_astc::{decode_astc, is_astc},
+    decompress_bptc::{
+        decode_bptc, GL_COMPRESSED_RGBA_BPTC_UNORM, GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT,
+        GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT, GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+    },
+    decompress_etc2::{
+        decode_etc2, GL_COMPRESSED_RGB8_ETC2, GL_COMPRESSED_SRGB8_ALPHA8_ETC2_EAC, GL_ETC1_RGB8_OES,
+    },
+};
+use crate::{ktx_decompressor::KTXDecompressor, Error, Result};
+
+/// Decodes BPTC, ETC2 and ASTC textures on the CPU, without an OpenGL context
+pub struct SoftwareKTXDecompressor;
+
+impl KTXDecompressor for SoftwareKTXDecompressor {
+    fn decompress_image(
+        &self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        gl_internal_format: u32,
+    ) -> Result<Vec<u8>> {
+        decode_texture(data, width, height, gl_internal_format)
+    }
+}
+
+/// Decodes a compressed image to RGBA8 pixels, picking the decoder from the OpenGL internal
+/// format of the texture
+pub fn decode_texture(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    gl_internal_format: u32,
+) -> Result<Vec<u8>> {
+    match gl_internal_format {
+        GL_COMPRESSED_RGBA_BPTC_UNORM
+        | GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM
+        | GL_COMPRESSED_RGB_BPTC_SIGNED_FLOAT
+        | GL_COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT => {
+            decode_bptc(data, width, height, gl_internal_format)
+        }
+        GL_ETC1_RGB8_OES | GL_COMPRESSED_RGB8_ETC2..=GL_COMPRESSED_SRGB8_ALPHA8_ETC2_EAC => {
+            decode_etc2(data, width, height, gl_internal_format)
+        }
+        format if is_astc(format) => decode_astc(data, width, height, gl_internal_format),
+        _ => Err(unsupported_format(gl_internal_format)),
+    }
+}
+
+pub(crate) fn unsupported_format(gl_internal_format: u32) -> Error {
+    Error::Texture(format!(
+        "Unsupported texture format {:#06X}",
+        gl_internal_format
+    ))
+}
+
+/// Decodes an image made of fixed size blocks. `decode_block` gets the bytes of a block and
+/// writes its pixels row by row.
+pub(crate) fn decode_blocks<F>(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    (block_width, block_height, block_bytes): (usize, usize, usize),
+    mut decode_block: F,
+) -> Result<Vec<u8>>
+where
+    F: FnMut(&[u8], &mut [[u8; 4]]),
+{
+    let width = width as usize;
+    let height = height as usize;
+    let blocks_x = width.div_ceil(block_width);
+    let blocks_y = height.div_ceil(block_height);
+
+    if data.len() < blocks_x * blocks_y * block_bytes {
+        return Err(Error::Texture(format!(
+            "Texture data is {} bytes long, expected {} bytes",
+            data.len(),
+            blocks_x * blocks_y * block_bytes
+        )));
+    }
+
+    let mut output = vec![0; width * height * 4];
+    let mut pixels = vec![[0; 4]; block_width * block_height];
+
+    for (i, block) in data
+        .chunks_exact(block_bytes)
+        .take(blocks_x * blocks_y)
+        .enumerate()
+    {
+        decode_block(block, &mut pixels);
+
+        let block_x = (i % blocks_x) * block_width;
+        let block_y = (i / blocks_x) * block_height;
+        for (pixel, rgba) in pixels.iter().enumerate() {
+            let x = block_x + pixel % block_width;
+            let y = block_y + pixel / block_width;
+            if x < width && y < height {
+                let offset = (y * width + x) * 4;
+                output[offset..offset + 4].copy_from_slice(rgba);
+            }
+        }
+    }
+
+    Ok(output)
+}
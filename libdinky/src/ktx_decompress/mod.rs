@@ -1 +1,6 @@
-pub mod decompress_gl;
\ No newline at end of file
+pub mod decompress_astc;
+pub mod decompress_bptc;
+pub mod decompress_etc2;
+#[cfg(feature = "decompress_ktx_gl")]
+pub mod decompress_gl;
+pub mod decompress_software;
\ No newline at end of file
@@ -1,7 +1,169 @@
+use ktx::KtxInfo;
+
+use crate::{Error, Result};
+
 pub trait KTXDecompressor {
-    fn decompress_ktx(
+    /// Decodes a single compressed image to RGBA8 pixels
+    fn decompress_image(
         &self,
-        data: &Vec<u8>,
-        output_texture_data: &mut Vec<u8>,
-    ) -> ();
+        data: &[u8],
+        width: u32,
+        height: u32,
+        gl_internal_format: u32,
+    ) -> Result<Vec<u8>>;
+}
+
+/// A decoded image of a KTX texture
+pub(crate) struct KtxImage {
+    pub level: u32,
+    /// Index of the face or array layer, faces of each array layer are stored together
+    pub layer: u32,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// Decodes the images of a KTX texture. Only the first image is decoded unless `all_images`
+/// is set, in which case every mip level, cubemap face and array layer is decoded.
+pub(crate) fn decompress_ktx(
+    decompressor: &dyn KTXDecompressor,
+    data: &[u8],
+    all_images: bool,
+) -> Result<Vec<KtxImage>> {
+    let cursor = std::io::Cursor::new(&data);
+    let decoder = ktx::Decoder::new(cursor)?;
+
+    let width = decoder.pixel_width();
+    let height = decoder.pixel_height().max(1);
+    let gl_internal_format = decoder.gl_internal_format();
+    let layers = decoder.faces().max(1) * decoder.array_elements().max(1);
+
+    let mut images = Vec::new();
+    for (level, level_data) in decoder.read_textures().enumerate() {
+        let level = level as u32;
+        let level_width = (width >> level).max(1);
+        let level_height = (height >> level).max(1);
+        let layer_size = level_data.len() / layers as usize;
+
+        for (layer, layer_data) in level_data.chunks_exact(layer_size.max(1)).enumerate() {
+            images.push(KtxImage {
+                level,
+                layer: layer as u32,
+                width: level_width,
+                height: level_height,
+                pixels: decompressor.decompress_image(
+                    layer_data,
+                    level_width,
+                    level_height,
+                    gl_internal_format,
+                )?,
+            });
+
+            if !all_images {
+                return Ok(images);
+            }
+        }
+    }
+
+    if images.is_empty() {
+        return Err(Error::Texture("KTX file contains no textures".to_string()));
+    }
+
+    Ok(images)
+}
+
+/// Packs images into a single RGBA8 image with a row per mip level and a column per face or
+/// array layer. Returns the width, height and pixels of the atlas.
+pub(crate) fn build_atlas(images: &[KtxImage]) -> (u32, u32, Vec<u8>) {
+    let cell_width = images.iter().map(|i| i.width).max().unwrap_or(0) as usize;
+    let columns = images.iter().map(|i| i.layer + 1).max().unwrap_or(0) as usize;
+    let levels = images.iter().map(|i| i.level + 1).max().unwrap_or(0);
+
+    let row_heights: Vec<usize> = (0..levels)
+        .map(|level| {
+            images
+                .iter()
+                .filter(|i| i.level == level)
+                .map(|i| i.height as usize)
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let width = cell_width * columns;
+    let height: usize = row_heights.iter().sum();
+    let mut pixels = vec![0; width * height * 4];
+
+    for image in images {
+        let x = image.layer as usize * cell_width;
+        let y: usize = row_heights[..image.level as usize].iter().sum();
+        let row_bytes = image.width as usize * 4;
+
+        for (row, source) in image.pixels.chunks_exact(row_bytes).enumerate() {
+            let offset = ((y + row) * width + x) * 4;
+            pixels[offset..offset + row_bytes].copy_from_slice(source);
+        }
+    }
+
+    (width as u32, height as u32, pixels)
+}
+
+/// Encodes RGBA8 pixels as a PNG image
+pub(crate) fn encode_png(
+    output_texture_data: &mut Vec<u8>,
+    width: u32,
+    height: u32,
+    texture_data: &[u8],
+) -> Result<()> {
+    let mut encoder = png::Encoder::new(output_texture_data, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| Error::Texture(e.to_string()))?;
+    writer
+        .write_image_data(texture_data)
+        .map_err(|e| Error::Texture(e.to_string()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(level: u32, layer: u32, size: u32, value: u8) -> KtxImage {
+        KtxImage {
+            level,
+            layer,
+            width: size,
+            height: size,
+            pixels: vec![value; (size * size * 4) as usize],
+        }
+    }
+
+    #[test]
+    fn atlas_has_a_row_per_level() {
+        let images = [
+            image(0, 0, 4, 1),
+            image(0, 1, 4, 2),
+            image(1, 0, 2, 3),
+            image(1, 1, 2, 4),
+            image(2, 0, 1, 5),
+            image(2, 1, 1, 6),
+        ];
+
+        let (width, height, pixels) = build_atlas(&images);
+        assert_eq!((width, height), (8, 7));
+
+        let pixel = |x: usize, y: usize| pixels[(y * 8 + x) * 4];
+        assert_eq!(pixel(3, 3), 1);
+        assert_eq!(pixel(4, 0), 2);
+        assert_eq!(pixel(1, 5), 3);
+        assert_eq!(pixel(2, 4), 0);
+        assert_eq!(pixel(5, 5), 4);
+        assert_eq!(pixel(0, 6), 5);
+        assert_eq!(pixel(4, 6), 6);
+        assert_eq!(pixel(5, 6), 0);
+    }
 }
@@ -0,0 +1,257 @@
+use byteorder::{LittleEndian, WriteBytesExt};
+use flate2::{write::ZlibEncoder, Compression};
+use ktx::KtxInfo;
+use std::io::Write;
+
+use crate::{
+    ktx_compress::compress_bptc::encode_bc7,
+    ktx_decompress::decompress_bptc::{
+        GL_COMPRESSED_RGBA_BPTC_UNORM, GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+    },
+    Error, Result,
+};
+
+const KTX_IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'1', b'1', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+const GL_RGBA: u32 = 0x1908;
+
+/// Internal format and number of mip levels of an encoded texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KtxFormat {
+    pub gl_internal_format: u32,
+    pub mipmap_levels: u32,
+}
+
+impl Default for KtxFormat {
+    /// BC7 without mip levels
+    fn default() -> Self {
+        Self {
+            gl_internal_format: GL_COMPRESSED_RGBA_BPTC_UNORM,
+            mipmap_levels: 1,
+        }
+    }
+}
+
+impl KtxFormat {
+    /// Reads the format of a .ktxbz texture, e.g. to encode a replacement the same way
+    pub fn from_ktxbz(data: &[u8]) -> Result<Self> {
+        let ktx = inflate::inflate_bytes_zlib(data)
+            .map_err(|e| Error::Texture(format!("Failed to inflate texture: {}", e)))?;
+        let decoder = ktx::Decoder::new(std::io::Cursor::new(ktx))?;
+
+        Ok(Self {
+            gl_internal_format: decoder.gl_internal_format(),
+            mipmap_levels: decoder.mipmap_levels().max(1),
+        })
+    }
+}
+
+/// Encodes a PNG image to a zlib compressed KTX texture, as stored in .ktxbz files
+pub fn png_to_ktxbz(png_data: &[u8], format: KtxFormat) -> Result<Vec<u8>> {
+    let (width, height, rgba) = read_png(png_data)?;
+    let ktx = encode_ktx(&rgba, width, height, format)?;
+    ktx_to_ktxbz(&ktx)
+}
+
+/// Whether `data` is an uncompressed KTX texture, as written by extract-files for .ktxbz files
+pub fn is_ktx(data: &[u8]) -> bool {
+    data.starts_with(&KTX_IDENTIFIER)
+}
+
+/// Compresses a KTX texture with zlib, as stored in .ktxbz files
+pub fn ktx_to_ktxbz(ktx: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(ktx)?;
+    Ok(encoder.finish()?)
+}
+
+/// Encodes RGBA8 pixels to a KTX texture. Mip levels are generated by halving the image until
+/// either the requested number of levels or a size of 1x1 is reached.
+pub fn encode_ktx(rgba: &[u8], width: u32, height: u32, format: KtxFormat) -> Result<Vec<u8>> {
+    let compress = match format.gl_internal_format {
+        GL_COMPRESSED_RGBA_BPTC_UNORM | GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM => encode_bc7,
+        gl_internal_format => {
+            return Err(Error::Texture(format!(
+                "Encoding to texture format {:#06X} is not supported",
+                gl_internal_format
+            )))
+        }
+    };
+
+    let size = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|n| n.checked_mul(4));
+    if width == 0 || height == 0 || size != Some(rgba.len()) {
+        return Err(Error::Texture(format!(
+            "{} bytes is not a valid {}x{} RGBA image",
+            rgba.len(),
+            width,
+            height
+        )));
+    }
+
+    let max_levels = 32 - width.max(height).leading_zeros();
+    let levels = format.mipmap_levels.clamp(1, max_levels);
+
+    let mut output = KTX_IDENTIFIER.to_vec();
+    for value in [
+        0x04030201, // Endianness
+        0,          // glType, 0 for compressed textures
+        1,          // glTypeSize
+        0,          // glFormat, 0 for compressed textures
+        format.gl_internal_format,
+        GL_RGBA,
+        width,
+        height,
+        0, // pixelDepth
+        0, // numberOfArrayElements
+        1, // numberOfFaces
+        levels,
+        0, // bytesOfKeyValueData
+    ] {
+        output.write_u32::<LittleEndian>(value)?;
+    }
+
+    let mut level = (width, height, rgba.to_vec());
+    for i in 0..levels {
+        if i > 0 {
+            level = downsample(&level.2, level.0, level.1);
+        }
+
+        let data = compress(&level.2, level.0, level.1);
+        output.write_u32::<LittleEndian>(data.len() as u32)?;
+        output.extend_from_slice(&data);
+    }
+
+    Ok(output)
+}
+
+/// Reads a PNG image as RGBA8 pixels, returning the width, height and pixels
+fn read_png(data: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| Error::Texture(e.to_string()))?;
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| Error::Texture(e.to_string()))?;
+    let pixels = &buffer[..info.buffer_size()];
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+        png::ColorType::Indexed => {
+            return Err(Error::Texture("Unexpected indexed PNG image".to_string()))
+        }
+    };
+
+    Ok((info.width, info.height, rgba))
+}
+
+/// Halves an image with a box filter. A side of odd length repeats its last row or column.
+fn downsample(rgba: &[u8], width: u32, height: u32) -> (u32, u32, Vec<u8>) {
+    let (width, height) = (width as usize, height as usize);
+    let half_width = (width / 2).max(1);
+    let half_height = (height / 2).max(1);
+
+    let mut output = Vec::with_capacity(half_width * half_height * 4);
+    for y in 0..half_height {
+        for x in 0..half_width {
+            for channel in 0..4 {
+                let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                    .iter()
+                    .map(|(dx, dy)| {
+                        let sx = (x * 2 + dx).min(width - 1);
+                        let sy = (y * 2 + dy).min(height - 1);
+                        rgba[(sy * width + sx) * 4 + channel] as u32
+                    })
+                    .sum();
+                output.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+
+    (half_width as u32, half_height as u32, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ktx_decompress::decompress_software::decode_texture;
+
+    #[test]
+    fn png_round_trip() {
+        let (width, height) = (16, 8);
+        let rgba: Vec<u8> = (0..width * height)
+            .flat_map(|i| [(i % width * 16) as u8, 128, 64, 255])
+            .collect();
+
+        let mut png_data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png_data, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&rgba)
+            .unwrap();
+
+        let format = KtxFormat {
+            gl_internal_format: GL_COMPRESSED_SRGB_ALPHA_BPTC_UNORM,
+            mipmap_levels: 10,
+        };
+        let ktxbz = png_to_ktxbz(&png_data, format).unwrap();
+
+        let read_format = KtxFormat::from_ktxbz(&ktxbz).unwrap();
+        assert_eq!(read_format.gl_internal_format, format.gl_internal_format);
+        assert_eq!(read_format.mipmap_levels, 5);
+
+        assert!(!is_ktx(&ktxbz));
+        let ktx = inflate::inflate_bytes_zlib(&ktxbz).unwrap();
+        assert!(is_ktx(&ktx));
+        assert_eq!(ktx_to_ktxbz(&ktx).unwrap(), ktxbz);
+        let decoder = ktx::Decoder::new(std::io::Cursor::new(ktx)).unwrap();
+        let levels: Vec<Vec<u8>> = decoder.read_textures().collect();
+        assert_eq!(
+            levels.iter().map(|l| l.len()).collect::<Vec<_>>(),
+            [128, 32, 16, 16, 16]
+        );
+
+        let decoded = decode_texture(&levels[0], width, height, format.gl_internal_format).unwrap();
+        assert!(rgba.iter().zip(&decoded).all(|(a, b)| a.abs_diff(*b) <= 4));
+    }
+
+    #[test]
+    fn unsupported_format() {
+        let format = KtxFormat {
+            gl_internal_format: 0x9274,
+            mipmap_levels: 1,
+        };
+        assert!(matches!(
+            encode_ktx(&[0; 64], 4, 4, format),
+            Err(Error::Texture(_))
+        ));
+    }
+
+    #[test]
+    fn reject_bad_sizes() {
+        for (width, height) in [(u32::MAX, u32::MAX), (0, 4), (4, 3)] {
+            assert!(matches!(
+                encode_ktx(&[0; 64], width, height, KtxFormat::default()),
+                Err(Error::Texture(_))
+            ));
+        }
+    }
+}
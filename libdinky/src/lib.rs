@@ -1,14 +1,27 @@
 mod easy_br;
+mod error;
+#[cfg(feature = "decompress_ktx")]
 mod ktx_decompressor;
 
+pub mod bank;
 pub mod decoder;
 pub mod dink;
 pub mod directory;
 pub mod keys;
 pub mod yack;
+pub mod yack_graph;
+pub mod yack_player;
 pub mod ggpack;
+pub mod sprite_sheet;
+pub mod text_table;
+pub mod vorbis;
 
+pub use error::{Error, Result};
 pub use keys::Keys;
 
 #[cfg(feature = "decompress_ktx")]
-mod ktx_decompress;
\ No newline at end of file
+mod ktx_decompress;
+#[cfg(feature = "encode_ktx")]
+mod ktx_compress;
+#[cfg(feature = "encode_ktx")]
+pub mod ktx_encoder;
\ No newline at end of file
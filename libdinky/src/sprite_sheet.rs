@@ -0,0 +1,392 @@
+use crate::{directory::GGValue, Error, Result};
+
+/// A rectangle in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SheetRect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+/// A frame of a sprite sheet, in the format written by TexturePacker
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SheetFrame {
+    pub name: String,
+    /// Area of the frame in the sheet texture. Width and height are those of the frame before
+    /// it was rotated.
+    pub frame: SheetRect,
+    /// The frame is stored rotated 90 degrees clockwise in the sheet texture
+    pub rotated: bool,
+    /// Position and size of the trimmed frame within the original image
+    pub sprite_source_size: SheetRect,
+    /// Width and height of the original image, before transparent borders were trimmed
+    pub source_size: (u32, u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpriteSheet {
+    /// Image file name from the `meta` entry
+    pub image: Option<String>,
+    pub frames: Vec<SheetFrame>,
+}
+
+/// Reads a number that may be stored as either an integer or a float
+fn expect_size(value: &GGValue) -> Result<u32> {
+    let number = match value {
+        GGValue::GGFloat(f) => f.value().round() as i64,
+        value => *value.expect_integer()?,
+    };
+
+    u32::try_from(number).map_err(|_| Error::InvalidValue {
+        kind: "size",
+        value: number.to_string(),
+    })
+}
+
+/// Reads a flag such as `rotated`. The directory format has no booleans, so JSON booleans are
+/// stored as integers, but flags may also be stored as `true` or `false` strings.
+fn expect_flag(value: &GGValue) -> Result<bool> {
+    match value {
+        GGValue::GGInteger(i) => Ok(*i != 0),
+        GGValue::GGString(s) if s == "true" => Ok(true),
+        GGValue::GGString(s) if s == "false" => Ok(false),
+        _ => Err(Error::UnexpectedValueType("bool")),
+    }
+}
+
+fn expect_rect(value: &GGValue) -> Result<SheetRect> {
+    Ok(SheetRect {
+        x: expect_size(value.expect_entry("x")?)?,
+        y: expect_size(value.expect_entry("y")?)?,
+        w: expect_size(value.expect_entry("w")?)?,
+        h: expect_size(value.expect_entry("h")?)?,
+    })
+}
+
+fn invalid_frame(name: &str) -> Error {
+    Error::InvalidValue {
+        kind: "frame rectangle",
+        value: name.to_string(),
+    }
+}
+
+/// Whether `start + size` doesn't overflow and ends within `limit`
+fn fits(start: u32, size: u32, limit: u32) -> bool {
+    start.checked_add(size).is_some_and(|end| end <= limit)
+}
+
+impl SheetFrame {
+    fn from_value(name: &str, value: &GGValue) -> Result<Self> {
+        let frame = expect_rect(value.expect_entry("frame")?)?;
+        let entries = value.expect_dict()?;
+
+        let rotated = match entries.get("rotated") {
+            Some(rotated) => expect_flag(rotated)?,
+            None => false,
+        };
+
+        // Untrimmed frames may leave out the source sizes
+        let sprite_source_size = match entries.get("spriteSourceSize") {
+            Some(size) => expect_rect(size)?,
+            None => SheetRect {
+                x: 0,
+                y: 0,
+                ..frame
+            },
+        };
+        let source_size = match entries.get("sourceSize") {
+            Some(size) => (
+                expect_size(size.expect_entry("w")?)?,
+                expect_size(size.expect_entry("h")?)?,
+            ),
+            None => (
+                sprite_source_size
+                    .x
+                    .checked_add(sprite_source_size.w)
+                    .ok_or_else(|| invalid_frame(name))?,
+                sprite_source_size
+                    .y
+                    .checked_add(sprite_source_size.h)
+                    .ok_or_else(|| invalid_frame(name))?,
+            ),
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            frame,
+            rotated,
+            sprite_source_size,
+            source_size,
+        })
+    }
+
+    /// Cuts the frame out of the RGBA8 pixels of the sheet texture and places it at its
+    /// position in the original image, so trimmed frames get back their transparent borders.
+    /// Returns the width, height and pixels of the frame.
+    pub fn cut(
+        &self,
+        sheet: &[u8],
+        sheet_width: u32,
+        sheet_height: u32,
+    ) -> Result<(u32, u32, Vec<u8>)> {
+        let SheetRect { x, y, w, h } = self.frame;
+        let (stored_width, stored_height) = if self.rotated { (h, w) } else { (w, h) };
+        let offset = self.sprite_source_size;
+        let (width, height) = self.source_size;
+
+        let sheet_size = (sheet_width as usize)
+            .checked_mul(sheet_height as usize)
+            .and_then(|size| size.checked_mul(4));
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|size| size.checked_mul(4));
+
+        let (sheet_size, size) = match (sheet_size, size) {
+            (Some(sheet_size), Some(size)) => (sheet_size, size),
+            _ => return Err(invalid_frame(&self.name)),
+        };
+        if !fits(x, stored_width, sheet_width)
+            || !fits(y, stored_height, sheet_height)
+            || !fits(offset.x, w, width)
+            || !fits(offset.y, h, height)
+            || sheet.len() < sheet_size
+        {
+            return Err(invalid_frame(&self.name));
+        }
+
+        let mut pixels = vec![0; size];
+        for frame_y in 0..h {
+            for frame_x in 0..w {
+                // Rotating a frame clockwise moves its pixel (x, y) to (h - 1 - y, x)
+                let (sheet_x, sheet_y) = if self.rotated {
+                    (x + h - 1 - frame_y, y + frame_x)
+                } else {
+                    (x + frame_x, y + frame_y)
+                };
+
+                let source = (sheet_y as usize * sheet_width as usize + sheet_x as usize) * 4;
+                let target = ((offset.y + frame_y) as usize * width as usize
+                    + (offset.x + frame_x) as usize)
+                    * 4;
+                pixels[target..target + 4].copy_from_slice(&sheet[source..source + 4]);
+            }
+        }
+
+        Ok((width, height, pixels))
+    }
+
+    /// Path to write the frame to, relative to the output directory. Frame names are used as
+    /// paths, so names that would escape the output directory are rejected.
+    pub fn output_path(&self) -> Result<String> {
+        let name = &self.name;
+        let escapes = name.is_empty()
+            || name.starts_with(['/', '\\'])
+            || name.contains(':')
+            || name.split(['/', '\\']).any(|part| part == "..");
+        if escapes {
+            return Err(Error::InvalidValue {
+                kind: "frame name",
+                value: name.clone(),
+            });
+        }
+
+        if name.ends_with(".png") {
+            Ok(name.clone())
+        } else {
+            Ok(format!("{}.png", name))
+        }
+    }
+}
+
+impl SpriteSheet {
+    /// Reads the frames of an expanded sheet json-file. `frames` may be either a dictionary
+    /// keyed by frame name or a list of frames with a `filename` entry.
+    pub fn from_value(value: &GGValue) -> Result<Self> {
+        let frames = match value.expect_entry("frames")? {
+            GGValue::GGDict(frames) => frames
+                .iter()
+                .map(|(name, frame)| SheetFrame::from_value(name, frame))
+                .collect::<Result<Vec<_>>>()?,
+            frames => frames
+                .expect_list()?
+                .iter()
+                .map(|frame| {
+                    SheetFrame::from_value(frame.expect_entry("filename")?.expect_string()?, frame)
+                })
+                .collect::<Result<Vec<_>>>()?,
+        };
+
+        let image = value
+            .expect_dict()?
+            .get("meta")
+            .and_then(|meta| meta.expect_entry("image").ok())
+            .and_then(|image| image.expect_string().ok())
+            .cloned();
+
+        Ok(Self { image, frames })
+    }
+
+    /// Names the sheet texture may have in the ggpack, based on the image in `meta` and the
+    /// name of the sheet json-file
+    pub fn texture_names(&self, sheet_name: &str) -> Vec<String> {
+        let stems = self
+            .image
+            .iter()
+            .map(|image| image.rsplit_once('.').map_or(&image[..], |(stem, _)| stem))
+            .chain(sheet_name.strip_suffix(".json"));
+
+        let mut names: Vec<String> = Vec::new();
+        for name in stems.flat_map(|stem| [format!("{}.ktxbz", stem), format!("{}.ktxaz", stem)]) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet() -> SpriteSheet {
+        let json = br#"{
+            "frames": {
+                "plain": {"frame": {"x": 0, "y": 0, "w": 2, "h": 1}},
+                "trimmed": {
+                    "frame": {"x": 0, "y": 1, "w": 2, "h": 2},
+                    "rotated": false,
+                    "trimmed": true,
+                    "spriteSourceSize": {"x": 1, "y": 0, "w": 2, "h": 2},
+                    "sourceSize": {"w": 4, "h": 3}
+                },
+                "rotated": {
+                    "frame": {"x": 2, "y": 0, "w": 3, "h": 2},
+                    "rotated": true,
+                    "spriteSourceSize": {"x": 0, "y": 0, "w": 3, "h": 2},
+                    "sourceSize": {"w": 3, "h": 2}
+                }
+            },
+            "meta": {"image": "Sheet.png"}
+        }"#;
+
+        SpriteSheet::from_value(&GGValue::from_json(json).unwrap()).unwrap()
+    }
+
+    /// A 4x4 sheet where the red channel of each pixel is its index
+    fn sheet_pixels() -> Vec<u8> {
+        (0..16).flat_map(|i| [i, 0, 0, 255]).collect()
+    }
+
+    fn red(pixels: &[u8]) -> Vec<u8> {
+        pixels
+            .chunks(4)
+            .map(|p| if p[3] == 0 { 99 } else { p[0] })
+            .collect()
+    }
+
+    #[test]
+    fn parse_frames() {
+        let sheet = sheet();
+        assert_eq!(sheet.image.as_deref(), Some("Sheet.png"));
+        assert_eq!(sheet.frames.len(), 3);
+
+        let plain = &sheet.frames[0];
+        assert_eq!(plain.name, "plain");
+        assert!(!plain.rotated);
+        assert_eq!(plain.source_size, (2, 1));
+        assert!(sheet.frames[2].rotated);
+
+        assert_eq!(
+            sheet.texture_names("Other.json"),
+            ["Sheet.ktxbz", "Sheet.ktxaz", "Other.ktxbz", "Other.ktxaz"]
+        );
+        assert_eq!(
+            sheet.texture_names("Sheet.json"),
+            ["Sheet.ktxbz", "Sheet.ktxaz"]
+        );
+    }
+
+    #[test]
+    fn parse_rotated_flags() {
+        let json = br#"{"frames": {
+            "false": {"frame": {"x": 0, "y": 0, "w": 1, "h": 1}, "rotated": false},
+            "true": {"frame": {"x": 0, "y": 0, "w": 1, "h": 1}, "rotated": true},
+            "one": {"frame": {"x": 0, "y": 0, "w": 1, "h": 1}, "rotated": 1},
+            "text": {"frame": {"x": 0, "y": 0, "w": 1, "h": 1}, "rotated": "true"}
+        }}"#;
+
+        // Sheets in a ggpack are stored in the directory format, not as JSON
+        let value = GGValue::from_json(json).unwrap();
+        let stored = GGValue::parse(value.to_bytes().unwrap()).unwrap();
+        for value in [value, stored] {
+            let sheet = SpriteSheet::from_value(&value).unwrap();
+            let rotated: Vec<_> = sheet.frames.iter().map(|f| f.rotated).collect();
+            assert_eq!(rotated, [false, true, true, true]);
+        }
+
+        let json =
+            br#"{"frames": {"a": {"frame": {"x": 0, "y": 0, "w": 1, "h": 1}, "rotated": "no"}}}"#;
+        assert!(matches!(
+            SpriteSheet::from_value(&GGValue::from_json(json).unwrap()),
+            Err(Error::UnexpectedValueType("bool"))
+        ));
+    }
+
+    #[test]
+    fn cut_trimmed_frame() {
+        let (width, height, pixels) = sheet().frames[1].cut(&sheet_pixels(), 4, 4).unwrap();
+        assert_eq!((width, height), (4, 3));
+        assert_eq!(red(&pixels), [99, 4, 5, 99, 99, 8, 9, 99, 99, 99, 99, 99]);
+    }
+
+    #[test]
+    fn cut_rotated_frame() {
+        // Stored as a 2x3 area at (2, 0), which is the 3x2 frame turned clockwise
+        let (width, height, pixels) = sheet().frames[2].cut(&sheet_pixels(), 4, 4).unwrap();
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(red(&pixels), [3, 7, 11, 2, 6, 10]);
+    }
+
+    #[test]
+    fn reject_frames_outside_the_sheet() {
+        assert!(matches!(
+            sheet().frames[2].cut(&sheet_pixels(), 3, 3),
+            Err(Error::InvalidValue { .. })
+        ));
+
+        let mut frame = sheet().frames[0].clone();
+        frame.frame.x = u32::MAX;
+        assert!(matches!(
+            frame.cut(&sheet_pixels(), 4, 4),
+            Err(Error::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            sheet().frames[0].cut(&sheet_pixels()[..8], 4, 4),
+            Err(Error::InvalidValue { .. })
+        ));
+    }
+
+    #[test]
+    fn frame_output_paths() {
+        let mut frame = sheet().frames[0].clone();
+        let mut output_path = |name: &str| {
+            frame.name = name.to_string();
+            frame.output_path()
+        };
+
+        assert_eq!(output_path("plain").unwrap(), "plain.png");
+        assert_eq!(output_path("dir/walk_1.png").unwrap(), "dir/walk_1.png");
+        for name in [
+            "../evil",
+            "dir/../../evil",
+            "/etc/evil",
+            "\\evil",
+            "C:evil",
+            "",
+        ] {
+            assert!(matches!(output_path(name), Err(Error::InvalidValue { .. })));
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+/// Localized lines of a language, read from a tab separated text table in the ggpack.
+/// Scripts refer to the lines by id, written as `@12345`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextTable {
+    texts: HashMap<u32, String>,
+}
+
+impl TextTable {
+    /// Reads a table of lines of an id and a text separated by a tab. Lines without a
+    /// numeric id, such as the header, are skipped.
+    pub fn from_tsv(data: &[u8]) -> Self {
+        let texts = String::from_utf8_lossy(data)
+            .lines()
+            .filter_map(|line| {
+                let (id, text) = line.split_once('\t')?;
+                Some((id.trim().parse().ok()?, text.to_string()))
+            })
+            .collect();
+
+        Self { texts }
+    }
+
+    /// Looks up a text id such as `@12345`
+    pub fn get(&self, id: &str) -> Option<&str> {
+        let id = id.strip_prefix('@')?.parse().ok()?;
+        self.texts.get(&id).map(|text| text.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.texts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_tsv() {
+        let table = TextTable::from_tsv(b"id\ten\n12345\tHello there!\r\n3\tTabs\tinside\n");
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get("@12345"), Some("Hello there!"));
+        assert_eq!(table.get("@3"), Some("Tabs\tinside"));
+        assert_eq!(table.get("12345"), None);
+        assert_eq!(table.get("@4"), None);
+    }
+}
@@ -0,0 +1,461 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{Error, Result};
+
+/// Block sizes of the Vorbis samples in FSB5 banks, as exponents of 2. FMOD doesn't store
+/// them, since its encoder always uses the same ones.
+const SHORT_BLOCKSIZE_EXPONENT: u8 = 8;
+const LONG_BLOCKSIZE_EXPONENT: u8 = 11;
+
+/// Packet type and codec name at the start of a setup header
+const SETUP_HEADER_START: &[u8] = b"\x05vorbis";
+
+const VENDOR: &str = "libdinky";
+
+/// Serial number of the only logical stream of a rebuilt file
+const STREAM_SERIAL: u32 = 1;
+
+/// Pages are ended after the packet that makes them at least this long, like libogg does
+const PAGE_BODY_SIZE: usize = 4096;
+
+/// Vorbis setup headers by the CRC32 that FSB5 banks identify them with. FMOD strips the
+/// setup header from Vorbis samples, so the setup headers its encoder uses have to be
+/// supplied to rebuild Ogg Vorbis files.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VorbisSetups {
+    headers: HashMap<u32, Vec<u8>>,
+}
+
+impl VorbisSetups {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a setup header packet. The packet type and `vorbis` at the start of the packet
+    /// may be left out.
+    pub fn insert(&mut self, crc32: u32, header: Vec<u8>) {
+        let header = if header.starts_with(SETUP_HEADER_START) {
+            header
+        } else {
+            [SETUP_HEADER_START, &header].concat()
+        };
+        self.headers.insert(crc32, header);
+    }
+
+    /// Reads the setup headers in `dir` from files named after their CRC32 in hex, such as
+    /// `3A1B2C4D.bin`. Other files are skipped.
+    pub fn from_dir(dir: &str) -> Result<Self> {
+        let mut setups = Self::new();
+        for entry in std::fs::read_dir(Path::new(dir))? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "bin") {
+                continue;
+            }
+
+            let crc32 = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| u32::from_str_radix(stem, 16).ok());
+            if let Some(crc32) = crc32 {
+                setups.insert(crc32, std::fs::read(&path)?);
+            }
+        }
+
+        Ok(setups)
+    }
+
+    pub fn get(&self, crc32: u32) -> Option<&[u8]> {
+        self.headers.get(&crc32).map(|header| header.as_slice())
+    }
+}
+
+/// Rebuilds an Ogg Vorbis file from the data of an FSB5 Vorbis sample, which is the audio
+/// packets, each preceded by its size as a 16 bit integer. `samples` is the length of the
+/// sample per channel.
+pub(crate) fn rebuild_ogg(
+    data: &[u8],
+    channels: u16,
+    frequency: u32,
+    samples: u32,
+    setup_header: &[u8],
+) -> Result<Vec<u8>> {
+    let blockflags = mode_blockflags(setup_header)?;
+    let mode_bits = ilog(blockflags.len() as u32 - 1);
+
+    let packets = read_packets(data)?;
+    if packets.is_empty() {
+        return Err(Error::Audio("The Vorbis sample has no packets".to_string()));
+    }
+
+    // The headers are on pages of their own
+    let mut ogg = OggWriter::new(STREAM_SERIAL);
+    ogg.write_packet(&identification_header(channels, frequency), 0);
+    ogg.write_page(false);
+    ogg.write_packet(&comment_header(), 0);
+    ogg.write_packet(setup_header, 0);
+    ogg.write_page(false);
+
+    let mut granule = 0;
+    let mut previous_blocksize = None;
+    for (i, packet) in packets.iter().enumerate() {
+        let blocksize = audio_packet_blocksize(packet, &blockflags, mode_bits)?;
+        // A packet finishes the overlap of its window with the previous one, the first packet
+        // has nothing to overlap with
+        if let Some(previous_blocksize) = previous_blocksize {
+            granule += (previous_blocksize + blocksize) / 4;
+        }
+        previous_blocksize = Some(blocksize);
+
+        // The last packet is cut off at the end of the sample
+        if i == packets.len() - 1 {
+            granule = samples as u64;
+        }
+        ogg.write_packet(packet, granule);
+    }
+    ogg.write_page(true);
+
+    Ok(ogg.output)
+}
+
+/// Splits the data of a sample into packets. The data ends with zero padding.
+fn read_packets(data: &[u8]) -> Result<Vec<&[u8]>> {
+    let mut packets = Vec::new();
+    let mut rest = data;
+    while rest.len() >= 2 {
+        let size = u16::from_le_bytes([rest[0], rest[1]]) as usize;
+        if size == 0 {
+            break;
+        }
+
+        let packet = rest
+            .get(2..2 + size)
+            .ok_or_else(|| Error::Audio("A Vorbis packet is cut off".to_string()))?;
+        packets.push(packet);
+        rest = &rest[2 + size..];
+    }
+
+    Ok(packets)
+}
+
+/// Number of bits needed to store `value`
+fn ilog(value: u32) -> u32 {
+    u32::BITS - value.leading_zeros()
+}
+
+fn identification_header(channels: u16, frequency: u32) -> Vec<u8> {
+    let mut header = b"\x01vorbis".to_vec();
+    // Version
+    header.extend_from_slice(&0u32.to_le_bytes());
+    header.push(channels as u8);
+    header.extend_from_slice(&frequency.to_le_bytes());
+    // Maximum, nominal and minimum bitrate, unknown
+    header.extend_from_slice(&[0; 12]);
+    header.push(SHORT_BLOCKSIZE_EXPONENT | (LONG_BLOCKSIZE_EXPONENT << 4));
+    // Framing bit
+    header.push(1);
+    header
+}
+
+fn comment_header() -> Vec<u8> {
+    let mut header = b"\x03vorbis".to_vec();
+    header.extend_from_slice(&(VENDOR.len() as u32).to_le_bytes());
+    header.extend_from_slice(VENDOR.as_bytes());
+    // No comments
+    header.extend_from_slice(&0u32.to_le_bytes());
+    // Framing bit
+    header.push(1);
+    header
+}
+
+/// Reads the bits of a packet backwards from its end. Vorbis packs fields starting at the
+/// least significant bit, so fields are read starting at their most significant bit.
+struct ReverseBitReader<'a> {
+    data: &'a [u8],
+    /// Number of bits before the next bit to read
+    position: usize,
+}
+
+impl<'a> ReverseBitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: data.len() * 8,
+        }
+    }
+
+    fn read(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+        for _ in 0..count {
+            self.position -= 1;
+            let bit = (self.data[self.position / 8] >> (self.position % 8)) & 1;
+            value = (value << 1) | bit as u32;
+        }
+        value
+    }
+}
+
+/// Finds the block flags of the modes at the end of a setup header by searching backwards for
+/// mode entries, like ffmpeg's Vorbis parser does. Parsing the header from the start would
+/// take decoding the codebooks, floors and residues before the modes.
+fn mode_blockflags(setup_header: &[u8]) -> Result<Vec<bool>> {
+    let bad_setup_header = || Error::Audio("Invalid Vorbis setup header".to_string());
+    if !setup_header.starts_with(SETUP_HEADER_START) {
+        return Err(bad_setup_header());
+    }
+
+    // The header ends with the framing bit, followed by padding
+    let mut reader = ReverseBitReader::new(setup_header);
+    loop {
+        if reader.position <= 97 {
+            return Err(bad_setup_header());
+        }
+        if reader.read(1) == 1 {
+            break;
+        }
+    }
+    let modes_end = reader.position;
+
+    // Each mode is a block flag, a window and transform type that are always 0 and a mapping
+    // number below 64. The modes are preceded by their count minus one in 6 bits, so the
+    // count is the largest one that matches the bits in front of that many modes.
+    let mut mode_count = 0;
+    let mut last_mode_count = 0;
+    while reader.position >= 97 {
+        if reader.read(8) > 63 || reader.read(16) != 0 || reader.read(16) != 0 {
+            break;
+        }
+        reader.read(1);
+        mode_count += 1;
+        if mode_count > 64 {
+            break;
+        }
+
+        let position = reader.position;
+        if reader.read(6) + 1 == mode_count {
+            last_mode_count = mode_count;
+        }
+        reader.position = position;
+    }
+    if last_mode_count == 0 {
+        return Err(bad_setup_header());
+    }
+
+    reader.position = modes_end;
+    let mut blockflags = vec![false; last_mode_count as usize];
+    for blockflag in blockflags.iter_mut().rev() {
+        reader.read(40);
+        *blockflag = reader.read(1) == 1;
+    }
+
+    Ok(blockflags)
+}
+
+/// Block size of an audio packet, from the block flag of its mode
+fn audio_packet_blocksize(packet: &[u8], blockflags: &[bool], mode_bits: u32) -> Result<u64> {
+    let first = match packet.first() {
+        Some(first) if first & 1 == 0 => *first as u32,
+        _ => return Err(Error::Audio("Invalid Vorbis audio packet".to_string())),
+    };
+
+    let mode = (first >> 1) & ((1 << mode_bits) - 1);
+    let long = blockflags
+        .get(mode as usize)
+        .ok_or_else(|| Error::Audio(format!("Vorbis audio packet uses undefined mode {}", mode)))?;
+
+    let exponent = if *long {
+        LONG_BLOCKSIZE_EXPONENT
+    } else {
+        SHORT_BLOCKSIZE_EXPONENT
+    };
+    Ok(1 << exponent)
+}
+
+/// CRC32 of an Ogg page, with the polynomial 0x04C11DB7 and without reflecting the bits
+fn ogg_crc(data: &[u8]) -> u32 {
+    data.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u32) << 24), |crc, _| {
+            if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Splits the packets of a logical stream into Ogg pages
+struct OggWriter {
+    output: Vec<u8>,
+    serial: u32,
+    sequence: u32,
+    lacing: Vec<u8>,
+    body: Vec<u8>,
+    /// Granule position of the last packet that ends on the page, -1 if none does
+    granule: u64,
+    /// Whether the page starts with the rest of a packet from the previous page
+    continued: bool,
+}
+
+impl OggWriter {
+    fn new(serial: u32) -> Self {
+        Self {
+            output: Vec::new(),
+            serial,
+            sequence: 0,
+            lacing: Vec::new(),
+            body: Vec::new(),
+            granule: u64::MAX,
+            continued: false,
+        }
+    }
+
+    fn write_packet(&mut self, packet: &[u8], granule: u64) {
+        if self.body.len() >= PAGE_BODY_SIZE {
+            self.write_page(false);
+        }
+
+        // Packets are split into segments of 255 bytes, ending with a shorter segment
+        let mut rest = packet;
+        loop {
+            if self.lacing.len() == 255 {
+                let started = rest.len() < packet.len();
+                self.write_page(false);
+                self.continued = started;
+            }
+
+            let size = rest.len().min(255);
+            self.lacing.push(size as u8);
+            self.body.extend_from_slice(&rest[..size]);
+            rest = &rest[size..];
+            if size < 255 {
+                break;
+            }
+        }
+        self.granule = granule;
+    }
+
+    fn write_page(&mut self, last: bool) {
+        let mut flags = 0;
+        if self.continued {
+            flags |= 1;
+        }
+        if self.sequence == 0 {
+            flags |= 2;
+        }
+        if last {
+            flags |= 4;
+        }
+
+        let mut page = b"OggS\0".to_vec();
+        page.push(flags);
+        page.extend_from_slice(&self.granule.to_le_bytes());
+        page.extend_from_slice(&self.serial.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        // The checksum is calculated with the checksum field set to 0
+        page.extend_from_slice(&[0; 4]);
+        page.push(self.lacing.len() as u8);
+        page.extend_from_slice(&self.lacing);
+        page.extend_from_slice(&self.body);
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        self.output.extend_from_slice(&page);
+
+        self.sequence += 1;
+        self.lacing.clear();
+        self.body.clear();
+        self.granule = u64::MAX;
+        self.continued = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, io::Cursor};
+
+    /// A 440 Hz sine in stereo, from the samples of the audrey crate
+    const SINE_OGG: &[u8] = include_bytes!("../testdata/sine_440hz_stereo.ogg");
+
+    /// Reads the packets of an Ogg file, and the granule positions of the pages by the index of
+    /// the packet that ends them
+    fn read_ogg(ogg: &[u8]) -> (Vec<Vec<u8>>, HashMap<usize, u64>) {
+        let mut reader = ogg::PacketReader::new(Cursor::new(ogg));
+        let mut packets = Vec::new();
+        let mut granules = HashMap::new();
+        while let Some(packet) = reader.read_packet().unwrap() {
+            if packet.last_in_page() {
+                granules.insert(packets.len(), packet.absgp_page());
+            }
+            packets.push(packet.data);
+        }
+        (packets, granules)
+    }
+
+    #[test]
+    fn page_checksum() {
+        let segments = SINE_OGG[26] as usize;
+        let body_size: usize = SINE_OGG[27..27 + segments]
+            .iter()
+            .map(|size| *size as usize)
+            .sum();
+        let mut page = SINE_OGG[..27 + segments + body_size].to_vec();
+        page[22..26].fill(0);
+
+        assert_eq!(ogg_crc(&page).to_le_bytes(), SINE_OGG[22..26], "first page");
+    }
+
+    #[test]
+    fn setup_header_modes() {
+        let (packets, _) = read_ogg(SINE_OGG);
+        assert_eq!(mode_blockflags(&packets[2]).unwrap(), [false, true]);
+        assert!(mode_blockflags(&packets[1]).is_err());
+
+        let mut setups = VorbisSetups::new();
+        setups.insert(1, packets[2].clone());
+        setups.insert(2, packets[2][SETUP_HEADER_START.len()..].to_vec());
+        assert_eq!(setups.get(1), Some(&packets[2][..]));
+        assert_eq!(setups.get(2), Some(&packets[2][..]));
+        assert_eq!(setups.get(3), None);
+    }
+
+    #[test]
+    fn rebuilt_granule_positions() {
+        let (packets, granules) = read_ogg(SINE_OGG);
+        let mut data = Vec::new();
+        for packet in &packets[3..] {
+            data.extend_from_slice(&(packet.len() as u16).to_le_bytes());
+            data.extend_from_slice(packet);
+        }
+        let samples = granules[&(packets.len() - 1)] as u32;
+
+        let ogg = rebuild_ogg(&data, 2, 44100, samples, &packets[2]).unwrap();
+        let (rebuilt_packets, rebuilt_granules) = read_ogg(&ogg);
+        assert_eq!(rebuilt_packets[3..], packets[3..]);
+
+        // Pages end after the same packets as the ones libogg wrote, with the same granule
+        // positions
+        assert_eq!(rebuilt_granules, granules);
+    }
+
+    #[test]
+    fn packets_across_pages() {
+        // 300 segments, so the packet continues on a second page
+        let large: Vec<u8> = (0..255 * 300).map(|i| i as u8).collect();
+        let mut writer = OggWriter::new(STREAM_SERIAL);
+        writer.write_packet(b"first", 1);
+        writer.write_packet(&large, 2);
+        writer.write_packet(&[], 3);
+        writer.write_page(true);
+
+        let (packets, granules) = read_ogg(&writer.output);
+        assert_eq!(packets, [b"first".to_vec(), large, Vec::new()]);
+        assert_eq!(granules, HashMap::from([(0, 1), (1, 2), (2, 3)]));
+
+        // The first page is full with the start of the large packet, the second page
+        // continues it
+        let second_page = 27 + 255 + 5 + 254 * 255;
+        assert_eq!(&writer.output[second_page..second_page + 4], b"OggS");
+        assert_eq!(writer.output[5], 2);
+        assert_eq!(writer.output[second_page + 5], 1);
+    }
+}
@@ -1,9 +1,13 @@
-use crate::easy_br::EasyRead;
+use crate::{easy_br::EasyRead, text_table::TextTable, Error, Result};
 use byteorder::{LittleEndian, ReadBytesExt};
-use std::io::Cursor;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    io::Cursor,
+};
 
-#[derive(Debug, PartialEq)]
-enum YackOpcode {
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum YackOpcode {
     ActorSay = 1,
     Assign = 2,
     Pause = 5,
@@ -52,191 +56,1264 @@ impl From<u8> for YackOpcode {
     }
 }
 
-pub fn parse_yack(data: &Vec<u8>) -> Result<String, std::io::Error> {
-    let mut reader = Cursor::new(data);
-    let _code_start_marker = reader.read_u32_le()?;
-    let string_table_offset = reader.read_u32_le()? as u64;
+fn missing_arg(opcode: u8) -> Error {
+    Error::BadYackOpcode {
+        opcode,
+        reason: "missing argument",
+    }
+}
 
-    let string_table = reader.read_at(std::io::SeekFrom::Start(string_table_offset), |reader| {
-        let _string_table_start_marker = reader.read_u32_le()?;
-        let num_strings = reader.read_u32_le()?;
+fn unbalanced_block(opcode: u8) -> Error {
+    Error::BadYackOpcode {
+        opcode,
+        reason: "closes a block that was never opened",
+    }
+}
 
-        let mut strings = Vec::new();
-        for _ in 0..num_strings {
-            let str = reader.read_cstring()?;
-            strings.push(str);
-        }
+/// A single instruction of a compiled yack
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YackInstruction {
+    pub opcode: YackOpcode,
+    /// The opcode as stored in the file, which tells unknown opcodes apart
+    pub raw_opcode: u8,
+    pub sequence_number: u64,
+    /// Conditions of the instruction, resolved from the string table. Conditions starting
+    /// with `?` mean that the instruction is only run once.
+    pub conditions: Vec<String>,
+    /// Arguments resolved from the string table, `None` for unused arguments
+    pub args: [Option<String>; 2],
+}
 
-        Ok(strings)
-    })?;
+impl YackInstruction {
+    fn expect_arg(&self, index: usize) -> Result<&str> {
+        self.args[index]
+            .as_deref()
+            .ok_or_else(|| missing_arg(self.raw_opcode))
+    }
 
-    let mut pending_else = false;
-    let mut if_stack: Vec<String> = Vec::new();
+    /// Conditions as written in the decompiled text, with run-once conditions as `once`
+    pub(crate) fn display_conditions(&self) -> Vec<&str> {
+        self.conditions
+            .iter()
+            .map(|condition| {
+                if condition.starts_with('?') {
+                    "once"
+                } else {
+                    condition
+                }
+            })
+            .collect()
+    }
 
-    let mut outp = String::new();
-    let mut indentation_level: u32 = 0;
+    pub(crate) fn conditions_text(&self) -> String {
+        self.display_conditions().join(" || ")
+    }
 
-    let mut emit = |indentation_level: u32, what: &str| {
-        outp += &format!(
-            "{}{}",
-            (0..indentation_level).map(|_| { '\t' }).collect::<String>(),
-            what
-        );
-    };
+    /// Conditions as compiled by `from_text`, with run-once conditions as `once(id)`
+    fn conditions_source(&self) -> String {
+        let conditions: Vec<String> = self
+            .conditions
+            .iter()
+            .map(|condition| match condition.strip_prefix('?') {
+                Some(id) => format!("once({})", id),
+                None => condition.clone(),
+            })
+            .collect();
+        conditions.join(" || ")
+    }
+}
 
+/// Markers at the start of the code and the string table, as found in the game's yacks.
+/// Their meaning isn't known.
+const CODE_START_MARKER: u32 = 0x002F_7D8A;
+const STRING_TABLE_MARKER: u32 = 0x02;
+
+/// A compiled yack dialogue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YackScript {
+    pub instructions: Vec<YackInstruction>,
+    pub code_start_marker: u32,
+    pub string_table_marker: u32,
+    /// The string table of a read yack, kept so that writing it back keeps the order of the
+    /// strings. Strings that aren't in it are added at the end.
+    pub string_table: Vec<String>,
+}
+
+fn read_string(string_table: &[String], index: usize) -> Result<String> {
+    string_table
+        .get(index)
+        .cloned()
+        .ok_or(Error::BadStringIndex(index))
+}
+
+/// Reads the instructions of a decrypted yack
+pub fn read_yack(data: &[u8]) -> Result<YackScript> {
+    let mut reader = Cursor::new(data);
+    let code_start_marker = reader.read_u32_le()?;
+    let string_table_offset = reader.read_u32_le()? as u64;
+
+    let (string_table_marker, string_table) = reader.read_at(
+        std::io::SeekFrom::Start(string_table_offset),
+        |reader| -> Result<(u32, Vec<String>)> {
+            let string_table_marker = reader.read_u32_le()?;
+            let num_strings = reader.read_u32_le()?;
+
+            let mut strings = Vec::new();
+            for _ in 0..num_strings {
+                let str = reader.read_cstring()?;
+                strings.push(str);
+            }
+
+            Ok((string_table_marker, strings))
+        },
+    )?;
+
+    let mut instructions = Vec::new();
     loop {
         let raw_opcode = reader.read_u8()?;
         if raw_opcode == 0 {
             break;
         }
 
-        let opcode = YackOpcode::from(raw_opcode);
-
-        let _sequence_number = reader.read_u64::<LittleEndian>()?; // Unused for now
+        let sequence_number = reader.read_u64::<LittleEndian>()?;
 
         let num_conditions = reader.read_u8()?;
-
         let mut conditions = Vec::new();
         for _ in 0..num_conditions {
-            let condition_index = reader.read_u32_le()?;
-            let condition = &string_table[condition_index as usize];
-            if condition.starts_with("?") {
-                conditions.push("once".to_string());
-            } else {
-                conditions.push(condition.clone());
+            let condition_index = reader.read_u32_le()? as usize;
+            conditions.push(read_string(&string_table, condition_index)?);
+        }
+
+        let mut args = [None, None];
+        for arg in args.iter_mut() {
+            let str_index = reader.read_i32::<LittleEndian>()?;
+            if str_index != -1 {
+                *arg = Some(read_string(&string_table, str_index as usize)?);
             }
         }
 
-        let arg_indices = [
-            reader.read_i32::<LittleEndian>()?,
-            reader.read_i32::<LittleEndian>()?,
-        ];
+        instructions.push(YackInstruction {
+            opcode: YackOpcode::from(raw_opcode),
+            raw_opcode,
+            sequence_number,
+            conditions,
+            args,
+        });
+    }
 
-        let get_arg = |index| {
-            let str_index = arg_indices[index];
-            if str_index == -1 {
-                None
-            } else {
-                Some(string_table[str_index as usize].clone())
-            }
-        };
+    Ok(YackScript {
+        instructions,
+        code_start_marker,
+        string_table_marker,
+        string_table,
+    })
+}
 
-        if pending_else {
-            if raw_opcode == 19 {
-                indentation_level -= 1;
-                emit(indentation_level, "else ");
-            } else {
-                indentation_level -= 1;
-                emit(indentation_level, "endif\n");
-            }
-            pending_else = false;
-        }
+/// The localized line of a text id as a comment to put after it, if there is one
+fn text_comment(texts: Option<&TextTable>, id: &str) -> String {
+    match texts.and_then(|texts| texts.get(id)) {
+        Some(text) => format!("\t// {}", text.replace(['\r', '\n'], " ")),
+        None => String::new(),
+    }
+}
+
+impl YackScript {
+    /// Prints the instructions as decompiled yack text
+    pub fn to_text(&self) -> Result<String> {
+        self.to_text_with_texts(None)
+    }
 
-        let mut emit_dialogue_choice = |x| {
-            let what = get_arg(0).expect("Expected arg 0");
-            let goto = get_arg(1).expect("Expected arg 1");
+    /// Prints the instructions as decompiled yack text. Text ids of lines and dialogue
+    /// choices found in `texts` are followed by a comment with the localized line.
+    ///
+    /// Each line ends with the sequence numbers of its instructions, as `\t#12`, run-once
+    /// conditions are printed with their id, as `once(12)`, and `if` blocks with the label
+    /// that ends them, so `from_text` compiles the text back to the same instructions.
+    pub fn to_text_with_texts(&self, texts: Option<&TextTable>) -> Result<String> {
+        // The block to close before the next instruction, with the sequence number of the
+        // label ending it. Conditional code is printed as an if block without a label.
+        let mut pending_else: Option<Option<u64>> = None;
+        let mut if_stack: Vec<&str> = Vec::new();
 
-            emit(indentation_level, &format!("{x} SAY({what}) -> {goto}"));
-            if conditions.len() > 0 {
-                let ored_conditions = conditions.join(" || ");
-                emit(indentation_level, &format!("[{ored_conditions}]"));
-            }
-            emit(indentation_level, "\n");
+        let mut outp = String::new();
+        let mut indentation_level: u32 = 0;
+
+        let mut emit = |indentation_level: u32, what: &str| {
+            outp += &format!(
+                "{}{}",
+                (0..indentation_level).map(|_| { '\t' }).collect::<String>(),
+                what
+            );
         };
 
-        match opcode {
-            YackOpcode::ActorSay => {
-                let talker = get_arg(0).expect("Expected arg 0");
-                let what = get_arg(1).expect("Expected arg 1");
-                emit(indentation_level, &format!("{talker}: SAY({what})\n"));
-            }
-            YackOpcode::Assign => {
-                emit(
-                    indentation_level,
-                    &format!(
-                        "{} <- {}",
-                        get_arg(0).expect("Expected arg 0"),
-                        get_arg(1).expect("Expected arg 1")
-                    ),
-                );
+        for instruction in &self.instructions {
+            let raw_opcode = instruction.raw_opcode;
+            let conditions = &instruction.conditions;
+            let expect_arg = |index: usize| instruction.expect_arg(index);
+            let conditions_suffix = if conditions.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", instruction.conditions_source())
+            };
+            let mut sequence_numbers = vec![instruction.sequence_number];
+
+            if let Some(label_sequence_number) = pending_else.take() {
+                indentation_level = indentation_level
+                    .checked_sub(1)
+                    .ok_or_else(|| unbalanced_block(raw_opcode))?;
+                if instruction.opcode == YackOpcode::ElseGoto {
+                    emit(indentation_level, "else ");
+                    if let Some(label_sequence_number) = label_sequence_number {
+                        sequence_numbers.insert(0, label_sequence_number);
+                    }
+                } else {
+                    let suffix = format_sequence_numbers(label_sequence_number.as_slice());
+                    emit(indentation_level, &format!("endif{suffix}\n"));
+                }
             }
-            YackOpcode::Pause => {
+            let sequence_suffix = format_sequence_numbers(&sequence_numbers);
+
+            let mut emit_dialogue_choice = |x| -> Result<()> {
+                let what = expect_arg(0)?;
+                let goto = expect_arg(1)?;
+
+                emit(indentation_level, &format!("{x} SAY({what}) -> {goto}"));
+                if !conditions.is_empty() {
+                    let ored_conditions = instruction.conditions_source();
+                    emit(indentation_level, &format!("[{ored_conditions}]"));
+                }
                 emit(
                     indentation_level,
-                    &format!("pause {}", get_arg(0).expect("Expected arg 0")),
+                    &format!("{sequence_suffix}{}\n", text_comment(texts, what)),
                 );
-            }
-            YackOpcode::WaitFor => {
-                let actor = get_arg(0).expect("Expected arg 0");
-                emit(indentation_level, &format!("waitfor {actor}\n"));
-            }
-            YackOpcode::EmitCode => {
-                let code = get_arg(0).expect("Expected arg 0");
-                if conditions.len() > 0 {
-                    let ored_conditions = conditions.join(" || ");
-                    emit(indentation_level, &format!("if [{ored_conditions}]\n"));
-                    pending_else = true;
+                Ok(())
+            };
+
+            match instruction.opcode {
+                YackOpcode::ActorSay => {
+                    let talker = expect_arg(0)?;
+                    let what = expect_arg(1)?;
+                    let comment = text_comment(texts, what);
+                    emit(
+                        indentation_level,
+                        &format!(
+                            "{talker}: SAY({what}){conditions_suffix}{sequence_suffix}{comment}\n"
+                        ),
+                    );
+                }
+                YackOpcode::Assign => {
+                    emit(
+                        indentation_level,
+                        &format!(
+                            "{} <- {}{conditions_suffix}{sequence_suffix}\n",
+                            expect_arg(0)?,
+                            expect_arg(1)?
+                        ),
+                    );
+                }
+                YackOpcode::Pause => {
+                    emit(
+                        indentation_level,
+                        &format!(
+                            "pause {}{conditions_suffix}{sequence_suffix}\n",
+                            expect_arg(0)?
+                        ),
+                    );
+                }
+                YackOpcode::WaitFor => {
+                    let actor = expect_arg(0)?;
+                    emit(
+                        indentation_level,
+                        &format!("waitfor {actor}{conditions_suffix}{sequence_suffix}\n"),
+                    );
+                }
+                YackOpcode::EmitCode => {
+                    let code = expect_arg(0)?;
+                    if !conditions.is_empty() {
+                        let ored_conditions = instruction.conditions_source();
+                        emit(indentation_level, &format!("if [{ored_conditions}]\n"));
+                        pending_else = Some(None);
+                        indentation_level += 1;
+                    }
+                    emit(indentation_level, &format!("{code}{sequence_suffix}\n"));
+                }
+                YackOpcode::DefineLabel => {
+                    let label = expect_arg(0)?;
+
+                    match if_stack.last() {
+                        Some(s) if *s == label => {
+                            if_stack.pop();
+                            pending_else = Some(Some(instruction.sequence_number));
+                        }
+                        _ => {
+                            emit(indentation_level, "\n");
+                            emit(
+                                indentation_level,
+                                &format!("==={label}==={sequence_suffix}\n"),
+                            );
+                        }
+                    };
+                }
+                YackOpcode::GotoLabel => {
+                    emit(
+                        indentation_level,
+                        &format!(
+                            "-> {}{conditions_suffix}{sequence_suffix}\n",
+                            expect_arg(0)?
+                        ),
+                    );
+                }
+                YackOpcode::EndChoices => {
+                    indentation_level = indentation_level
+                        .checked_sub(1)
+                        .ok_or_else(|| unbalanced_block(raw_opcode))?;
+                    emit(
+                        indentation_level,
+                        &format!("end dialogue{sequence_suffix}\n"),
+                    );
+                }
+                YackOpcode::StartChoices => {
+                    emit(
+                        indentation_level,
+                        &format!("begin dialogue{sequence_suffix}\n"),
+                    );
                     indentation_level += 1;
                 }
-                emit(indentation_level, &format!("{code}\n"));
+                YackOpcode::ElseGoto => {
+                    let goto_else = expect_arg(0)?;
+                    let ored_conditions = instruction.conditions_source();
+                    emit(
+                        indentation_level,
+                        &format!("if [{ored_conditions}] -> {goto_else}{sequence_suffix}\n"),
+                    );
+                    if_stack.push(goto_else);
+                    indentation_level += 1;
+                }
+                YackOpcode::Reply1 => emit_dialogue_choice(1)?,
+                YackOpcode::Reply2 => emit_dialogue_choice(2)?,
+                YackOpcode::Reply3 => emit_dialogue_choice(3)?,
+                YackOpcode::Reply4 => emit_dialogue_choice(4)?,
+                YackOpcode::Reply5 => emit_dialogue_choice(5)?,
+                YackOpcode::Reply6 => emit_dialogue_choice(6)?,
+                YackOpcode::Reply7 => emit_dialogue_choice(7)?,
+                YackOpcode::Reply8 => emit_dialogue_choice(8)?,
+                YackOpcode::Reply9 => emit_dialogue_choice(9)?,
+                YackOpcode::Unknown => {
+                    emit(
+                        indentation_level,
+                        &format!(
+                            "?? Unknown opcode {raw_opcode} cond={:?} arg1={:?} arg2={:?}{sequence_suffix}\n",
+                            instruction.display_conditions(),
+                            instruction.args[0],
+                            instruction.args[1]
+                        ),
+                    );
+                }
             }
-            YackOpcode::DefineLabel => {
-                let label = get_arg(0).expect("Expected arg 0");
+        }
+
+        // The endif of a block at the end of the script carries the sequence number of its label
+        if let Some(label_sequence_number) = pending_else {
+            let indentation_level = indentation_level.saturating_sub(1);
+            let suffix = format_sequence_numbers(label_sequence_number.as_slice());
+            emit(indentation_level, &format!("endif{suffix}\n"));
+        }
+
+        Ok(outp)
+    }
+}
+
+/// The sequence numbers of the instructions of a line, as `\t#1 #2`
+fn format_sequence_numbers(sequence_numbers: &[u64]) -> String {
+    if sequence_numbers.is_empty() {
+        return String::new();
+    }
+    let numbers: Vec<String> = sequence_numbers
+        .iter()
+        .map(|number| format!("#{}", number))
+        .collect();
+    format!("\t{}", numbers.join(" "))
+}
+
+enum YackBlock {
+    /// An if block, closed by defining its label
+    If(String),
+    /// A line of conditional code, printed as an if block without a label
+    Code,
+    Dialogue,
+}
+
+fn bad_line(line: usize, reason: &'static str) -> Error {
+    Error::BadYackLine { line, reason }
+}
+
+/// Parses the conditions of `[a || b]`. `once(id)` is a run-once condition, while `once`
+/// gets a run-once condition with the sequence number of the instruction as id.
+fn parse_conditions(text: &str, line: usize, sequence_number: u64) -> Result<Vec<String>> {
+    let conditions = text
+        .trim()
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+        .ok_or_else(|| bad_line(line, "conditions must be enclosed in []"))?;
+
+    Ok(conditions
+        .split("||")
+        .map(|condition| match condition.trim() {
+            "once" => format!("?{}", sequence_number),
+            condition => match condition
+                .strip_prefix("once(")
+                .and_then(|id| id.strip_suffix(')'))
+            {
+                Some(id) => format!("?{}", id),
+                None => condition.to_string(),
+            },
+        })
+        .collect())
+}
+
+/// Splits the conditions off the end of a line, `text [a || b]`
+fn split_conditions(text: &str, line: usize, sequence_number: u64) -> Result<(&str, Vec<String>)> {
+    match text.rfind(" [") {
+        Some(start) if text.ends_with(']') => Ok((
+            text[..start].trim_end(),
+            parse_conditions(&text[start..], line, sequence_number)?,
+        )),
+        _ => Ok((text, Vec::new())),
+    }
+}
+
+/// Splits the sequence numbers off the end of a line, `text\t#1 #2`
+fn split_sequence_numbers(text: &str, line: usize) -> Result<(&str, Vec<u64>)> {
+    let (text, numbers) = match text.rsplit_once("\t#") {
+        Some(split) => split,
+        None => return Ok((text, Vec::new())),
+    };
+    let numbers = numbers
+        .trim_end()
+        .split(" #")
+        .map(|number| {
+            number
+                .parse()
+                .map_err(|_| bad_line(line, "sequence numbers must be written as #1 #2"))
+        })
+        .collect::<Result<_>>()?;
+    Ok((text, numbers))
+}
+
+/// Parses a dialogue choice, `1 SAY(what) -> label[conditions]`
+fn parse_reply(text: &str, line: usize, sequence_number: u64) -> Result<Option<YackInstruction>> {
+    let reply = text.split_once(" SAY(").and_then(|(number, rest)| {
+        let number = number.parse::<u8>().ok()?;
+        (1..=9).contains(&number).then_some((number, rest))
+    });
+    let (number, rest) = match reply {
+        Some(reply) => reply,
+        None => return Ok(None),
+    };
+
+    let (what, goto) = rest
+        .split_once(") -> ")
+        .ok_or_else(|| bad_line(line, "dialogue choice is missing -> label"))?;
+    let (goto, conditions) = match goto.find('[') {
+        Some(start) => (
+            &goto[..start],
+            parse_conditions(&goto[start..], line, sequence_number)?,
+        ),
+        None => (goto, Vec::new()),
+    };
+
+    let opcode = YackOpcode::from(YackOpcode::Reply1 as u8 + number - 1);
+    let mut reply = instruction(opcode, sequence_number, &[what, goto.trim()]);
+    reply.conditions = conditions;
+    Ok(Some(reply))
+}
+
+fn instruction(opcode: YackOpcode, sequence_number: u64, args: &[&str]) -> YackInstruction {
+    YackInstruction {
+        opcode,
+        raw_opcode: opcode as u8,
+        sequence_number,
+        conditions: Vec::new(),
+        args: [
+            args.first().map(|arg| arg.to_string()),
+            args.get(1).map(|arg| arg.to_string()),
+        ],
+    }
+}
+
+/// Whether a line opens or closes a block or defines a label, rather than being a statement
+fn is_block_line(text: &str) -> bool {
+    text.is_empty()
+        || text == "endif"
+        || text == "begin dialogue"
+        || text == "end dialogue"
+        || text.starts_with("else")
+        || text.starts_with("if ")
+        || text.starts_with("??")
+        || (text.starts_with("===") && text.ends_with("==="))
+}
+
+/// Parses a statement, with the conditions at the end of the line. Lines that aren't any
+/// other statement are code.
+fn parse_statement(text: &str, line: usize, sequence_number: u64) -> Result<YackInstruction> {
+    let (statement, conditions) = split_conditions(text, line, sequence_number)?;
+    let parsed = if let Some(label) = statement.strip_prefix("-> ") {
+        Some(instruction(
+            YackOpcode::GotoLabel,
+            sequence_number,
+            &[label],
+        ))
+    } else if let Some(time) = statement.strip_prefix("pause ") {
+        Some(instruction(YackOpcode::Pause, sequence_number, &[time]))
+    } else if let Some(actor) = statement.strip_prefix("waitfor ") {
+        Some(instruction(YackOpcode::WaitFor, sequence_number, &[actor]))
+    } else if let Some((talker, what)) = statement
+        .split_once(": SAY(")
+        .and_then(|(talker, what)| Some((talker, what.strip_suffix(')')?)))
+    {
+        Some(instruction(
+            YackOpcode::ActorSay,
+            sequence_number,
+            &[talker, what],
+        ))
+    } else {
+        statement
+            .split_once(" <- ")
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(variable, value)| {
+                instruction(YackOpcode::Assign, sequence_number, &[variable, value])
+            })
+    };
+
+    // Conditional code is printed as an if block, so brackets at the end of a code line are
+    // part of the code
+    Ok(match parsed {
+        Some(mut parsed) => {
+            parsed.conditions = conditions;
+            parsed
+        }
+        None => instruction(YackOpcode::EmitCode, sequence_number, &[text]),
+    })
+}
+
+impl YackScript {
+    /// Parses yack text, as printed by `to_text`. Instructions get the sequence numbers at
+    /// the end of their line, or else the line number. `if [conditions] -> label` blocks are
+    /// compiled to an `ElseGoto` to the label, which is defined where the block ends. An `if`
+    /// without a label holds a single line of code, which gets the conditions. Lines that
+    /// aren't recognized are compiled as code. Comments, starting with a tab followed by
+    /// `// `, are skipped.
+    pub fn from_text(text: &str) -> Result<Self> {
+        let mut instructions = Vec::new();
+        let mut blocks = Vec::new();
+        // The conditions and line of an if without a label, waiting for its line of code
+        let mut pending_code: Option<(&str, usize)> = None;
+
+        let close_if = |blocks: &mut Vec<YackBlock>, line: usize| match blocks.pop() {
+            Some(YackBlock::If(label)) => Ok(Some(label)),
+            Some(YackBlock::Code) => Ok(None),
+            _ => Err(bad_line(line, "closes an if that was never opened")),
+        };
+
+        for (i, text) in text.lines().enumerate() {
+            let line = i + 1;
+            let text = text
+                .split_once("\t// ")
+                .map_or(text, |(text, _comment)| text);
+            let (text, sequence_numbers) = split_sequence_numbers(text, line)?;
+            let text = text.trim();
+            let mut sequence_numbers = sequence_numbers.into_iter();
+            let mut next_sequence_number = || sequence_numbers.next().unwrap_or(line as u64);
+
+            if let Some((conditions, if_line)) = pending_code.take() {
+                let sequence_number = next_sequence_number();
+                let mut code = match parse_reply(text, line, sequence_number)? {
+                    Some(_) => None,
+                    None if is_block_line(text) => None,
+                    None => Some(parse_statement(text, line, sequence_number)?),
+                }
+                .filter(|code| code.opcode == YackOpcode::EmitCode)
+                .ok_or_else(|| {
+                    bad_line(
+                        line,
+                        "if without a label must hold a line of code, use if [...] -> label",
+                    )
+                })?;
+                code.conditions = parse_conditions(conditions, if_line, sequence_number)?;
+                instructions.push(code);
+                blocks.push(YackBlock::Code);
+            } else {
+                // Else is followed by the if of the next condition, possibly after indentation
+                let (is_else, text) = match text.strip_prefix("else") {
+                    Some(rest) if rest.trim_start().starts_with("if [") => {
+                        (true, rest.trim_start())
+                    }
+                    _ => (false, text),
+                };
+                if !is_else && !text.is_empty() && text != "endif" {
+                    if let Some(YackBlock::Code) = blocks.last() {
+                        return Err(bad_line(
+                            line,
+                            "if without a label must hold a single line of code",
+                        ));
+                    }
+                }
+                if is_else {
+                    if let Some(label) = close_if(&mut blocks, line)? {
+                        let sequence_number = next_sequence_number();
+                        instructions.push(instruction(
+                            YackOpcode::DefineLabel,
+                            sequence_number,
+                            &[&label],
+                        ));
+                    }
+                }
 
-                match if_stack.last() {
-                    Some(s) if s.eq(&label) => {
-                        if_stack.pop();
-                        pending_else = true;
+                if text.is_empty() {
+                    // Blank lines only separate labels
+                } else if let Some(rest) = text.strip_prefix("if ") {
+                    match rest.find("] -> ") {
+                        Some(end) => {
+                            let label = &rest[end + "] -> ".len()..];
+                            let sequence_number = next_sequence_number();
+                            let mut else_goto =
+                                instruction(YackOpcode::ElseGoto, sequence_number, &[label]);
+                            else_goto.conditions =
+                                parse_conditions(&rest[..=end], line, sequence_number)?;
+                            instructions.push(else_goto);
+                            blocks.push(YackBlock::If(label.to_string()));
+                        }
+                        None => pending_code = Some((rest, line)),
                     }
-                    _ => {
-                        emit(indentation_level, "\n");
-                        emit(indentation_level, &format!("==={label}===\n"));
+                } else if text == "endif" {
+                    if let Some(label) = close_if(&mut blocks, line)? {
+                        let sequence_number = next_sequence_number();
+                        instructions.push(instruction(
+                            YackOpcode::DefineLabel,
+                            sequence_number,
+                            &[&label],
+                        ));
                     }
+                } else if text == "begin dialogue" {
+                    instructions.push(instruction(
+                        YackOpcode::StartChoices,
+                        next_sequence_number(),
+                        &[],
+                    ));
+                    blocks.push(YackBlock::Dialogue);
+                } else if text == "end dialogue" {
+                    match blocks.pop() {
+                        Some(YackBlock::Dialogue) => {}
+                        _ => return Err(bad_line(line, "ends a dialogue that was never begun")),
+                    }
+                    instructions.push(instruction(
+                        YackOpcode::EndChoices,
+                        next_sequence_number(),
+                        &[],
+                    ));
+                } else if let Some(label) = text
+                    .strip_prefix("===")
+                    .and_then(|text| text.strip_suffix("==="))
+                {
+                    instructions.push(instruction(
+                        YackOpcode::DefineLabel,
+                        next_sequence_number(),
+                        &[label],
+                    ));
+                } else if text.starts_with("??") {
+                    return Err(bad_line(line, "unknown opcodes can't be compiled"));
+                } else {
+                    let sequence_number = next_sequence_number();
+                    if let Some(reply) = parse_reply(text, line, sequence_number)? {
+                        instructions.push(reply);
+                    } else {
+                        instructions.push(parse_statement(text, line, sequence_number)?);
+                    }
+                }
+            }
+
+            if sequence_numbers.next().is_some() {
+                return Err(bad_line(line, "more sequence numbers than instructions"));
+            }
+        }
+
+        let last_line = text.lines().count();
+        if pending_code.is_some() {
+            return Err(bad_line(
+                last_line,
+                "if without a label must hold a line of code",
+            ));
+        }
+        // An if at the end of the script may not be followed by its endif
+        while let Some(YackBlock::If(_) | YackBlock::Code) = blocks.last() {
+            if let Some(label) = close_if(&mut blocks, last_line)? {
+                instructions.push(instruction(
+                    YackOpcode::DefineLabel,
+                    last_line as u64,
+                    &[&label],
+                ));
+            }
+        }
+        if !blocks.is_empty() {
+            return Err(bad_line(last_line, "dialogue is never ended"));
+        }
+
+        Ok(Self {
+            instructions,
+            code_start_marker: CODE_START_MARKER,
+            string_table_marker: STRING_TABLE_MARKER,
+            string_table: Vec::new(),
+        })
+    }
+
+    /// Writes the instructions as a compiled yack, with a string table of the conditions and
+    /// arguments. Strings are written in the order of `string_table`, followed by any other
+    /// strings in the order they are used, so a read yack is written back as it was.
+    /// Instructions with more than 255 conditions can't be written.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut strings: Vec<&str> = self.string_table.iter().map(String::as_str).collect();
+        let mut indices = HashMap::new();
+        for (index, string) in strings.iter().enumerate() {
+            indices.entry(*string).or_insert(index);
+        }
+        let mut index = |string| {
+            *indices.entry(string).or_insert_with(|| {
+                strings.push(string);
+                strings.len() - 1
+            })
+        };
+
+        let mut code = Vec::new();
+        for instruction in &self.instructions {
+            code.push(instruction.raw_opcode);
+            code.extend_from_slice(&instruction.sequence_number.to_le_bytes());
+
+            let num_conditions =
+                u8::try_from(instruction.conditions.len()).map_err(|_| Error::BadYackOpcode {
+                    opcode: instruction.raw_opcode,
+                    reason: "more than 255 conditions",
+                })?;
+            code.push(num_conditions);
+            for condition in &instruction.conditions {
+                code.extend_from_slice(&(index(condition.as_str()) as u32).to_le_bytes());
+            }
+
+            for arg in &instruction.args {
+                let index = match arg {
+                    Some(arg) => index(arg.as_str()) as i32,
+                    None => -1,
                 };
+                code.extend_from_slice(&index.to_le_bytes());
             }
-            YackOpcode::GotoLabel => {
-                emit(
-                    indentation_level,
-                    &format!("-> {}\n", get_arg(0).expect("Expected arg 0")),
-                );
+        }
+        code.push(0);
+
+        let mut data = self.code_start_marker.to_le_bytes().to_vec();
+        data.extend_from_slice(&(8 + code.len() as u32).to_le_bytes());
+        data.extend_from_slice(&code);
+
+        data.extend_from_slice(&self.string_table_marker.to_le_bytes());
+        data.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        for string in strings {
+            data.extend_from_slice(string.as_bytes());
+            data.push(0);
+        }
+
+        Ok(data)
+    }
+}
+
+/// A problem found by `lint`. Instructions are identified by their sequence number.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YackLint {
+    /// A label that is neither jumped to nor run into from the instruction before it
+    UnreachableLabel(String),
+    UndefinedLabel {
+        label: String,
+        sequence_number: u64,
+    },
+    /// A dialogue where every choice leads back to the label of the dialogue
+    ChoicesWithoutExit {
+        sequence_number: u64,
+    },
+    UnknownOpcode {
+        opcode: u8,
+        sequence_number: u64,
+    },
+    /// A dialogue that ends without having begun, begins inside another dialogue or never ends
+    UnbalancedChoices {
+        sequence_number: u64,
+    },
+}
+
+impl Display for YackLint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            YackLint::UnreachableLabel(label) => write!(f, "Label {} is unreachable", label),
+            YackLint::UndefinedLabel {
+                label,
+                sequence_number,
+            } => write!(f, "{}: Jump to undefined label {}", sequence_number, label),
+            YackLint::ChoicesWithoutExit { sequence_number } => write!(
+                f,
+                "{}: No dialogue choice leads out of the dialogue",
+                sequence_number
+            ),
+            YackLint::UnknownOpcode {
+                opcode,
+                sequence_number,
+            } => write!(f, "{}: Unknown opcode {}", sequence_number, opcode),
+            YackLint::UnbalancedChoices { sequence_number } => {
+                write!(
+                    f,
+                    "{}: Unbalanced begin and end of dialogue",
+                    sequence_number
+                )
             }
-            YackOpcode::EndChoices => {
-                indentation_level -= 1;
-                emit(indentation_level, "end dialogue\n");
+        }
+    }
+}
+
+pub(crate) fn is_reply(opcode: YackOpcode) -> bool {
+    (YackOpcode::Reply1 as u8..=YackOpcode::Reply9 as u8).contains(&(opcode as u8))
+}
+
+/// Checks a script for broken branching. Jumps to labels the yack doesn't define are reported
+/// unless listed in `external_labels`, e.g. `exit`. The first label is where the dialogue
+/// starts, but other labels may be started by the game too, so unreachable labels can be
+/// false positives.
+pub fn lint(script: &YackScript, external_labels: &[&str]) -> Vec<YackLint> {
+    let instructions = &script.instructions;
+    let mut lints = Vec::new();
+
+    let defined: HashSet<&str> = instructions
+        .iter()
+        .filter(|i| i.opcode == YackOpcode::DefineLabel)
+        .filter_map(|i| i.args[0].as_deref())
+        .collect();
+    let mut targeted = HashSet::new();
+
+    let mut current_label = None;
+    let mut choices_start: Option<&YackInstruction> = None;
+    let mut choices_exit = false;
+    // Whether the instruction before can continue with the next one
+    let mut falls_through = true;
+
+    for instruction in instructions {
+        let sequence_number = instruction.sequence_number;
+        let target = match instruction.opcode {
+            YackOpcode::GotoLabel | YackOpcode::ElseGoto => instruction.args[0].as_deref(),
+            opcode if is_reply(opcode) => instruction.args[1].as_deref(),
+            _ => None,
+        };
+        if let Some(label) = target {
+            targeted.insert(label);
+            if !defined.contains(label) && !external_labels.contains(&label) {
+                lints.push(YackLint::UndefinedLabel {
+                    label: label.to_string(),
+                    sequence_number,
+                });
+            }
+        }
+
+        match instruction.opcode {
+            YackOpcode::DefineLabel => {
+                let label = instruction.args[0].as_deref();
+                if let (false, Some(label)) = (falls_through, label) {
+                    // Dropped at the end if anything jumps to the label
+                    lints.push(YackLint::UnreachableLabel(label.to_string()));
+                }
+                current_label = label;
             }
             YackOpcode::StartChoices => {
-                emit(indentation_level, "begin dialogue\n");
-                indentation_level += 1;
-            }
-            YackOpcode::ElseGoto => {
-                let goto_else = get_arg(0).expect("Expected arg 0");
-                let ored_conditions = conditions.join(" || ");
-                emit(indentation_level, &format!("if [{ored_conditions}]\n"));
-                if_stack.push(goto_else);
-                indentation_level += 1;
-            }
-            YackOpcode::Reply1 => emit_dialogue_choice(1),
-            YackOpcode::Reply2 => emit_dialogue_choice(2),
-            YackOpcode::Reply3 => emit_dialogue_choice(3),
-            YackOpcode::Reply4 => emit_dialogue_choice(4),
-            YackOpcode::Reply5 => emit_dialogue_choice(5),
-            YackOpcode::Reply6 => emit_dialogue_choice(6),
-            YackOpcode::Reply7 => emit_dialogue_choice(7),
-            YackOpcode::Reply8 => emit_dialogue_choice(8),
-            YackOpcode::Reply9 => emit_dialogue_choice(9),
-            YackOpcode::Unknown => {
-                emit(
-                    indentation_level,
-                    &format!(
-                        "?? Unknown opcode {raw_opcode} cond={:?} arg1={:?} arg2={:?}\n",
-                        &conditions,
-                        get_arg(0),
-                        get_arg(1)
-                    ),
-                );
+                if choices_start.is_some() {
+                    lints.push(YackLint::UnbalancedChoices { sequence_number });
+                }
+                choices_start = Some(instruction);
+                choices_exit = false;
+            }
+            YackOpcode::EndChoices => match choices_start.take() {
+                Some(start) if !choices_exit => lints.push(YackLint::ChoicesWithoutExit {
+                    sequence_number: start.sequence_number,
+                }),
+                Some(_) => {}
+                None => lints.push(YackLint::UnbalancedChoices { sequence_number }),
+            },
+            YackOpcode::Unknown => lints.push(YackLint::UnknownOpcode {
+                opcode: instruction.raw_opcode,
+                sequence_number,
+            }),
+            opcode if is_reply(opcode) => {
+                choices_exit |= target.is_some() && target != current_label;
             }
+            _ => {}
         }
+
+        falls_through = match instruction.opcode {
+            YackOpcode::GotoLabel => !instruction.conditions.is_empty(),
+            // The dialogue continues at the label of the chosen reply
+            YackOpcode::EndChoices => false,
+            _ => true,
+        };
     }
 
-    Ok(outp)
+    if let Some(start) = choices_start {
+        lints.push(YackLint::UnbalancedChoices {
+            sequence_number: start.sequence_number,
+        });
+    }
+
+    lints.retain(|lint| match lint {
+        YackLint::UnreachableLabel(label) => !targeted.contains(label.as_str()),
+        _ => true,
+    });
+    lints
+}
+
+/// Decompiles a decrypted yack to text
+pub fn parse_yack(data: &[u8]) -> Result<String> {
+    read_yack(data)?.to_text()
+}
+
+/// Decompiles a decrypted yack to text, with the localized lines of text ids from `texts`
+pub fn parse_yack_with_texts(data: &[u8], texts: &TextTable) -> Result<String> {
+    read_yack(data)?.to_text_with_texts(Some(texts))
+}
+
+/// Compiles yack text to an unencrypted yack
+pub fn compile_yack(text: &str) -> Result<Vec<u8>> {
+    YackScript::from_text(text)?.to_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Opcode, conditions and arguments of an instruction
+    type Instruction<'a> = (u8, &'a [&'a str], [Option<&'a str>; 2]);
+
+    /// Builds a compiled yack from instructions
+    fn yack(instructions: &[Instruction]) -> Vec<u8> {
+        let mut strings: Vec<String> = Vec::new();
+        let mut index = |s: &str| match strings.iter().position(|x| x == s) {
+            Some(i) => i as u32,
+            None => {
+                strings.push(s.to_string());
+                strings.len() as u32 - 1
+            }
+        };
+
+        let mut code = Vec::new();
+        for (i, (opcode, conditions, args)) in instructions.iter().enumerate() {
+            code.push(*opcode);
+            code.extend_from_slice(&(i as u64 + 1).to_le_bytes());
+            code.push(u8::try_from(conditions.len()).unwrap());
+            for condition in conditions.iter() {
+                code.extend_from_slice(&index(condition).to_le_bytes());
+            }
+            for arg in args {
+                let arg = arg.map_or(-1, |arg| index(arg) as i32);
+                code.extend_from_slice(&arg.to_le_bytes());
+            }
+        }
+        code.push(0);
+
+        let mut data = 0x002F_7D8Au32.to_le_bytes().to_vec();
+        data.extend_from_slice(&(8 + code.len() as u32).to_le_bytes());
+        data.extend_from_slice(&code);
+        data.extend_from_slice(&0x02u32.to_le_bytes());
+        data.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+        for s in strings {
+            data.extend_from_slice(s.as_bytes());
+            data.push(0);
+        }
+        data
+    }
+
+    #[test]
+    fn read_instructions() {
+        let data = yack(&[
+            (1, &[], [Some("guybrush"), Some("@1")]),
+            (100, &["?1", "flag"], [Some("@2"), Some("end")]),
+            (42, &[], [None, None]),
+        ]);
+        let script = read_yack(&data).unwrap();
+
+        assert_eq!(script.instructions.len(), 3);
+        let reply = &script.instructions[1];
+        assert_eq!(reply.opcode, YackOpcode::Reply1);
+        assert_eq!(reply.sequence_number, 2);
+        assert_eq!(reply.conditions, ["?1", "flag"]);
+        assert_eq!(
+            reply.args,
+            [Some("@2".to_string()), Some("end".to_string())]
+        );
+
+        let unknown = &script.instructions[2];
+        assert_eq!(unknown.opcode, YackOpcode::Unknown);
+        assert_eq!(unknown.raw_opcode, 42);
+        assert_eq!(unknown.args, [None, None]);
+    }
+
+    #[test]
+    fn print_text() {
+        let data = yack(&[
+            (9, &[], [Some("main"), None]),
+            (1, &[], [Some("guybrush"), Some("@1")]),
+            (12, &[], [None, None]),
+            (100, &["?1"], [Some("@2"), Some("end")]),
+            (11, &[], [None, None]),
+            (8, &["flag"], [Some("doSomething()"), None]),
+            (10, &[], [Some("exit"), None]),
+        ]);
+
+        assert_eq!(
+            parse_yack(&data).unwrap(),
+            "\n===main===\t#1\nguybrush: SAY(@1)\t#2\nbegin dialogue\t#3\n\
+             \t1 SAY(@2) -> end\t[once(1)]\t\t#4\nend dialogue\t#5\n\
+             if [flag]\n\tdoSomething()\t#6\nendif\n-> exit\t#7\n"
+        );
+    }
+
+    #[test]
+    fn compile_round_trip() {
+        let text = "\n===main===\nguybrush: SAY(@1)\nx <- 1\npause 0.5\nbegin dialogue\n\
+                    \t1 SAY(@2) -> end\t[once]\t\n\t2 SAY(@3) -> main\t\nend dialogue\n\
+                    if [flag || other] -> skip\n\tdoSomething()\n\tif [nested]\n\t\tfoo()\n\
+                    \tendif\nelse if [third] -> skip2\n\twaitfor guybrush\nendif\n\n===end===\n";
+        let script = YackScript::from_text(text).unwrap();
+
+        // Without sequence numbers in the text, instructions get their line number
+        let sequence_numbers: Vec<_> = script
+            .instructions
+            .iter()
+            .map(|instruction| instruction.sequence_number)
+            .collect();
+        assert_eq!(
+            sequence_numbers,
+            [2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 15, 16, 17, 19]
+        );
+
+        let reply = &script.instructions[5];
+        assert_eq!(reply.opcode, YackOpcode::Reply1);
+        assert_eq!(reply.conditions, ["?7"]);
+        assert_eq!(script.instructions[6].opcode, YackOpcode::Reply2);
+
+        let else_goto = &script.instructions[8];
+        assert_eq!(else_goto.opcode, YackOpcode::ElseGoto);
+        assert_eq!(else_goto.conditions, ["flag", "other"]);
+        assert_eq!(else_goto.args[0].as_deref(), Some("skip"));
+
+        let code = &script.instructions[10];
+        assert_eq!(code.opcode, YackOpcode::EmitCode);
+        assert_eq!(code.conditions, ["nested"]);
+
+        let data = script.to_bytes().unwrap();
+        assert_eq!(read_yack(&data).unwrap().instructions, script.instructions);
+
+        let printed = parse_yack(&data).unwrap();
+        assert_eq!(
+            printed,
+            "\n===main===\t#2\nguybrush: SAY(@1)\t#3\nx <- 1\t#4\npause 0.5\t#5\n\
+             begin dialogue\t#6\n\t1 SAY(@2) -> end\t[once(7)]\t\t#7\n\
+             \t2 SAY(@3) -> main\t\t#8\nend dialogue\t#9\nif [flag || other] -> skip\t#10\n\
+             \tdoSomething()\t#11\n\tif [nested]\n\t\tfoo()\t#13\n\tendif\n\
+             else if [third] -> skip2\t#15 #15\n\twaitfor guybrush\t#16\nendif\t#17\n\
+             \n===end===\t#19\n"
+        );
+        assert_eq!(
+            YackScript::from_text(&printed).unwrap().instructions,
+            script.instructions
+        );
+    }
+
+    #[test]
+    fn keep_ids_through_text() {
+        let data = yack(&[
+            (9, &[], [Some("main"), None]),
+            (1, &["?77"], [Some("guybrush"), Some("@1")]),
+            (19, &["flag"], [Some("skip"), None]),
+            (8, &["other"], [Some("doSomething()"), None]),
+            (9, &[], [Some("skip"), None]),
+            (19, &["third"], [Some("skip2"), None]),
+            (7, &[], [Some("guybrush"), None]),
+            (9, &[], [Some("skip2"), None]),
+        ]);
+
+        let text = parse_yack(&data).unwrap();
+        assert_eq!(
+            text,
+            "\n===main===\t#1\nguybrush: SAY(@1) [once(77)]\t#2\nif [flag] -> skip\t#3\n\
+             \tif [other]\n\t\tdoSomething()\t#4\n\tendif\nelse if [third] -> skip2\t#5 #6\n\
+             \twaitfor guybrush\t#7\nendif\t#8\n"
+        );
+
+        // Sequence numbers, once ids and labels are compiled back as they were
+        assert_eq!(compile_yack(&text).unwrap(), data);
+    }
+
+    #[test]
+    fn write_read_yack() {
+        let data = yack(&[
+            (9, &[], [Some("main"), None]),
+            (1, &["flag"], [Some("guybrush"), Some("@1")]),
+            (100, &["?3"], [Some("@2"), Some("main")]),
+            (42, &[], [None, None]),
+        ]);
+        let mut script = read_yack(&data).unwrap();
+        assert_eq!(script.to_bytes().unwrap(), data);
+
+        // The order of the string table is kept, along with strings that aren't used
+        script.string_table.reverse();
+        script.string_table.push("unused".to_string());
+        let reordered = read_yack(&script.to_bytes().unwrap()).unwrap();
+        assert_eq!(reordered, script);
+        assert_eq!(reordered.to_bytes().unwrap(), script.to_bytes().unwrap());
+
+        script.instructions[1].conditions = vec!["flag".to_string(); 256];
+        assert!(matches!(
+            script.to_bytes(),
+            Err(Error::BadYackOpcode { opcode: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn conditional_lines() {
+        let data = yack(&[
+            (1, &["flag"], [Some("guybrush"), Some("@1")]),
+            (2, &["flag", "other"], [Some("x"), Some("1")]),
+            (5, &["?3"], [Some("0.5"), None]),
+            (7, &["other"], [Some("guybrush"), None]),
+            (10, &["flag"], [Some("main"), None]),
+            (1, &[], [Some("guybrush"), Some("@2")]),
+        ]);
+        let text = "guybrush: SAY(@1) [flag]\t#1
+x <- 1 [flag || other]\t#2
+pause 0.5 [once(3)]\t#3
+waitfor guybrush [other]\t#4
+-> main [flag]\t#5
+guybrush: SAY(@2)\t#6
+";
+        assert_eq!(parse_yack(&data).unwrap(), text);
+
+        let script = YackScript::from_text(text).unwrap();
+        let conditions: Vec<_> = script
+            .instructions
+            .iter()
+            .map(|instruction| instruction.conditions.clone())
+            .collect();
+        assert_eq!(
+            conditions,
+            [
+                vec!["flag"],
+                vec!["flag", "other"],
+                vec!["?3"],
+                vec!["other"],
+                vec!["flag"],
+                vec![]
+            ]
+        );
+        assert_eq!(script.instructions[4].args[0].as_deref(), Some("main"));
+        assert_eq!(script.to_text().unwrap(), text);
+    }
+
+    #[test]
+    fn compile_errors() {
+        for (text, line) in [
+            ("-> a\nendif\n", 2),
+            ("begin dialogue\n", 1),
+            ("end dialogue\n", 1),
+            ("if flag\n", 1),
+            ("1 SAY(@1)\n", 1),
+            ("if [flag]\n\t-> a\nendif\n", 2),
+            ("if [flag]\n\ta()\n\tb()\nendif\n", 3),
+            ("-> a\t#1 #2\n", 1),
+            ("-> a\t#b\n", 1),
+        ] {
+            assert!(
+                matches!(YackScript::from_text(text), Err(Error::BadYackLine { line: l, .. }) if l == line),
+                "{}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn missing_argument() {
+        let data = yack(&[(1, &[], [Some("guybrush"), None])]);
+        assert!(read_yack(&data).is_ok());
+        assert!(matches!(
+            parse_yack(&data),
+            Err(Error::BadYackOpcode { opcode: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn lint_branching() {
+        let text = "===main===\nbegin dialogue\n\t1 SAY(@1) -> main\nend dialogue\n\
+                    ===lost===\n-> nowhere\n===found===\nbegin dialogue\n\
+                    \t1 SAY(@2) -> main\n\t2 SAY(@3) -> exit\nend dialogue\n";
+        let mut script = YackScript::from_text(text).unwrap();
+        // Text with unbalanced dialogues or unknown opcodes doesn't compile
+        script.instructions.extend([
+            instruction(YackOpcode::EndChoices, 12, &[]),
+            instruction(YackOpcode::GotoLabel, 13, &["found"]),
+            instruction(YackOpcode::StartChoices, 14, &[]),
+            YackInstruction {
+                raw_opcode: 42,
+                ..instruction(YackOpcode::Unknown, 16, &[])
+            },
+        ]);
+
+        assert_eq!(
+            lint(&script, &["exit"]),
+            [
+                YackLint::ChoicesWithoutExit { sequence_number: 2 },
+                YackLint::UnreachableLabel("lost".to_string()),
+                YackLint::UndefinedLabel {
+                    label: "nowhere".to_string(),
+                    sequence_number: 6
+                },
+                YackLint::UnbalancedChoices {
+                    sequence_number: 12
+                },
+                YackLint::UnknownOpcode {
+                    opcode: 42,
+                    sequence_number: 16
+                },
+                YackLint::UnbalancedChoices {
+                    sequence_number: 14
+                },
+            ]
+        );
+        assert!(lint(&script, &[]).contains(&YackLint::UndefinedLabel {
+            label: "exit".to_string(),
+            sequence_number: 10
+        }));
+    }
+
+    #[test]
+    fn print_localized_texts() {
+        let text = "guybrush: SAY(@1)\nbegin dialogue\n\t1 SAY(@2) -> end\t[once]\t\n\
+                    \t2 SAY(@3) -> end\t\nend dialogue\n";
+        let texts = TextTable::from_tsv(b"1\tHello\n2\tBye\n");
+
+        let script = YackScript::from_text(text).unwrap();
+        let localized = script.to_text_with_texts(Some(&texts)).unwrap();
+        assert_eq!(
+            localized,
+            "guybrush: SAY(@1)\t#1\t// Hello\nbegin dialogue\t#2\n\
+             \t1 SAY(@2) -> end\t[once(3)]\t\t#3\t// Bye\n\t2 SAY(@3) -> end\t\t#4\n\
+             end dialogue\t#5\n"
+        );
+
+        // The comments are skipped when compiling
+        assert_eq!(
+            YackScript::from_text(&localized)
+                .unwrap()
+                .to_text()
+                .unwrap(),
+            script.to_text().unwrap()
+        );
+    }
 }
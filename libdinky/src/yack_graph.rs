@@ -0,0 +1,248 @@
+use serde::Serialize;
+
+use crate::{
+    yack::{YackInstruction, YackOpcode, YackScript},
+    Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum YackEdgeKind {
+    /// The end of a label runs into the next label
+    Next,
+    Goto,
+    /// A dialogue choice
+    Choice,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct YackEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: YackEdgeKind,
+    /// What is said for a dialogue choice
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Conditions that all have to hold for the edge to be taken, each written as in the
+    /// decompiled text, e.g. `a || once`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conditions: Vec<String>,
+}
+
+/// The labels of a yack and how the dialogue moves between them. Instructions before the
+/// first label belong to a node named `start`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct YackGraph {
+    pub nodes: Vec<String>,
+    pub edges: Vec<YackEdge>,
+}
+
+const START_NODE: &str = "start";
+
+/// Quotes a string for DOT, where line breaks are written as `\n`
+fn quote(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+impl YackGraph {
+    fn add_node(&mut self, name: &str) {
+        if !self.nodes.iter().any(|node| node == name) {
+            self.nodes.push(name.to_string());
+        }
+    }
+
+    fn add_edge(&mut self, edge: YackEdge) {
+        self.add_node(&edge.from);
+        self.add_node(&edge.to);
+        self.edges.push(edge);
+    }
+
+    /// Builds the graph of a script. Labels that only close an `if` are part of the label the
+    /// `if` is in, so jumps inside an `if` get its conditions.
+    pub fn from_script(script: &YackScript) -> Self {
+        let mut graph = Self::default();
+
+        let mut current: Option<String> = None;
+        let mut falls_through = true;
+        // Labels closing the open ifs, with their conditions
+        let mut ifs: Vec<(&str, String)> = Vec::new();
+
+        for instruction in &script.instructions {
+            let arg = |index: usize| instruction.args[index].clone().unwrap_or_default();
+            let current_node = current
+                .get_or_insert_with(|| START_NODE.to_string())
+                .clone();
+            let conditions = |instruction: &YackInstruction| {
+                let mut conditions: Vec<String> = ifs.iter().map(|(_, c)| c.clone()).collect();
+                if !instruction.conditions.is_empty() {
+                    conditions.push(instruction.conditions_text());
+                }
+                conditions
+            };
+
+            match instruction.opcode {
+                YackOpcode::DefineLabel => {
+                    let label = instruction.args[0].as_deref().unwrap_or_default();
+                    if ifs.last().map(|(l, _)| *l) == Some(label) {
+                        ifs.pop();
+                        continue;
+                    }
+
+                    // Nothing before the first label means there is no start node
+                    if graph.nodes.is_empty() && graph.edges.is_empty() {
+                        falls_through = false;
+                    }
+                    if falls_through {
+                        graph.add_edge(YackEdge {
+                            from: current_node,
+                            to: label.to_string(),
+                            kind: YackEdgeKind::Next,
+                            text: None,
+                            conditions: Vec::new(),
+                        });
+                    }
+
+                    graph.add_node(label);
+                    current = Some(label.to_string());
+                    falls_through = true;
+                }
+                YackOpcode::ElseGoto => {
+                    let label = instruction.args[0].as_deref().unwrap_or_default();
+                    ifs.push((label, instruction.conditions_text()));
+                }
+                YackOpcode::GotoLabel => {
+                    let conditions = conditions(instruction);
+                    if conditions.is_empty() {
+                        falls_through = false;
+                    }
+                    graph.add_edge(YackEdge {
+                        from: current_node,
+                        to: arg(0),
+                        kind: YackEdgeKind::Goto,
+                        text: None,
+                        conditions,
+                    });
+                }
+                YackOpcode::Reply1
+                | YackOpcode::Reply2
+                | YackOpcode::Reply3
+                | YackOpcode::Reply4
+                | YackOpcode::Reply5
+                | YackOpcode::Reply6
+                | YackOpcode::Reply7
+                | YackOpcode::Reply8
+                | YackOpcode::Reply9 => {
+                    graph.add_edge(YackEdge {
+                        from: current_node,
+                        to: arg(1),
+                        kind: YackEdgeKind::Choice,
+                        text: Some(arg(0)),
+                        conditions: conditions(instruction),
+                    });
+                }
+                // The dialogue continues at the label of the chosen reply
+                YackOpcode::EndChoices => falls_through = false,
+                _ => graph.add_node(&current_node),
+            }
+        }
+
+        graph
+    }
+
+    /// Writes the graph as JSON with a list of nodes and a list of edges
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Writes the graph in the Graphviz DOT format. Gotos are solid, choices are labeled with
+    /// what is said and labels running into the next label are dashed.
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut dot = format!("digraph {} {{\n\tnode [shape=box];\n", quote(name));
+
+        for node in &self.nodes {
+            dot += &format!("\t{};\n", quote(node));
+        }
+
+        for edge in &self.edges {
+            let mut label: Vec<String> = edge.text.iter().cloned().collect();
+            label.extend(edge.conditions.iter().map(|c| format!("[{}]", c)));
+
+            let mut attributes = Vec::new();
+            if !label.is_empty() {
+                attributes.push(format!("label={}", quote(&label.join("\n"))));
+            }
+            if edge.kind == YackEdgeKind::Next {
+                attributes.push("style=dashed".to_string());
+            }
+
+            dot += &format!("\t{} -> {}", quote(&edge.from), quote(&edge.to));
+            if !attributes.is_empty() {
+                dot += &format!(" [{}]", attributes.join(", "));
+            }
+            dot += ";\n";
+        }
+
+        dot += "}\n";
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(text: &str) -> YackGraph {
+        YackGraph::from_script(&YackScript::from_text(text).unwrap())
+    }
+
+    #[test]
+    fn build_graph() {
+        let graph = graph(
+            "guybrush: SAY(@1)\n===main===\nbegin dialogue\n\t1 SAY(@2) -> main[once]\n\
+             \t2 SAY(@3) -> done\nend dialogue\n===other===\nif [flag] -> else\n\t-> main\nendif\n\
+             ===done===\n",
+        );
+
+        assert_eq!(graph.nodes, ["start", "main", "done", "other"]);
+
+        let edges: Vec<_> = graph
+            .edges
+            .iter()
+            .map(|e| (e.from.as_str(), e.to.as_str(), e.kind))
+            .collect();
+        assert_eq!(
+            edges,
+            [
+                ("start", "main", YackEdgeKind::Next),
+                ("main", "main", YackEdgeKind::Choice),
+                ("main", "done", YackEdgeKind::Choice),
+                ("other", "main", YackEdgeKind::Goto),
+                ("other", "done", YackEdgeKind::Next),
+            ]
+        );
+
+        assert_eq!(graph.edges[1].text.as_deref(), Some("@2"));
+        assert_eq!(graph.edges[1].conditions, ["once"]);
+        assert_eq!(graph.edges[3].conditions, ["flag"]);
+
+        let json: serde_json::Value = serde_json::from_str(&graph.to_json().unwrap()).unwrap();
+        assert_eq!(json["edges"][1]["kind"], "choice");
+        assert_eq!(json["edges"][1]["text"], "@2");
+        assert!(json["edges"][0].get("conditions").is_none());
+    }
+
+    #[test]
+    fn write_dot() {
+        let graph = graph("===a===\n1 SAY(\"hi\") -> b[once]\n-> c\n===b===\n");
+
+        assert_eq!(
+            graph.to_dot("Talk.yack"),
+            "digraph \"Talk.yack\" {\n\tnode [shape=box];\n\t\"a\";\n\t\"b\";\n\t\"c\";\n\
+             \t\"a\" -> \"b\" [label=\"\\\"hi\\\"\\n[once]\"];\n\t\"a\" -> \"c\";\n}\n"
+        );
+    }
+}
@@ -0,0 +1,283 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    yack::{is_reply, YackInstruction, YackOpcode, YackScript},
+    Error, Result,
+};
+
+/// Instructions run by one call to `YackPlayer::next` before it gives up on a loop
+const MAX_STEPS: usize = 100_000;
+
+/// A dialogue choice that can be picked with `YackPlayer::choose`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct YackChoice {
+    /// The number of the choice, 1 to 9
+    pub number: u8,
+    pub text: String,
+    pub label: String,
+    index: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum YackEvent {
+    Say {
+        actor: String,
+        text: String,
+    },
+    /// The choices whose conditions hold. Continue with `YackPlayer::choose`.
+    Choices(Vec<YackChoice>),
+    /// Code run by the game, which the player doesn't run
+    Code(String),
+    Pause(String),
+    WaitFor(String),
+    /// The dialogue is over, either at the end of the script or by jumping to `label`, which
+    /// isn't defined in the yack, e.g. `exit`
+    End {
+        label: Option<String>,
+    },
+}
+
+/// Plays a yack one event at a time. Run-once conditions and variables set by `Assign` are
+/// tracked by the player, other conditions are answered by the caller.
+pub struct YackPlayer<'a> {
+    script: &'a YackScript,
+    position: usize,
+    labels: HashMap<&'a str, usize>,
+    used_once: HashSet<&'a str>,
+    variables: HashMap<String, String>,
+    exit_label: Option<String>,
+}
+
+impl<'a> YackPlayer<'a> {
+    /// Starts playing at the first instruction
+    pub fn new(script: &'a YackScript) -> Self {
+        let labels = script
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(_, instruction)| instruction.opcode == YackOpcode::DefineLabel)
+            .filter_map(|(i, instruction)| Some((instruction.args[0].as_deref()?, i)))
+            .collect();
+
+        Self {
+            script,
+            position: 0,
+            labels,
+            used_once: HashSet::new(),
+            variables: HashMap::new(),
+            exit_label: None,
+        }
+    }
+
+    /// Variables set by `Assign` instructions or `set_variable` so far
+    pub fn variables(&self) -> &HashMap<String, String> {
+        &self.variables
+    }
+
+    /// Sets a variable as if the yack assigned it, e.g. to start with state set by the game
+    pub fn set_variable(&mut self, name: &str, value: &str) {
+        self.variables.insert(name.to_string(), value.to_string());
+    }
+
+    /// Continues at `label`, or ends the dialogue if the yack doesn't define it
+    pub fn goto(&mut self, label: &str) {
+        match self.labels.get(label) {
+            Some(&position) => self.position = position,
+            None => {
+                self.position = self.script.instructions.len();
+                self.exit_label = Some(label.to_string());
+            }
+        }
+    }
+
+    /// Checks whether any of the conditions of an instruction holds. Conditions that are
+    /// neither run-once conditions nor variables are passed to `condition`.
+    fn passes(
+        &self,
+        instruction: &YackInstruction,
+        condition: &mut dyn FnMut(&str) -> bool,
+    ) -> bool {
+        if instruction.conditions.is_empty() {
+            return true;
+        }
+
+        instruction.conditions.iter().any(|c| {
+            if c.starts_with('?') {
+                !self.used_once.contains(c.as_str())
+            } else if let Some(value) = self.variables.get(c) {
+                !matches!(value.as_str(), "0" | "false" | "NO" | "null")
+            } else {
+                condition(c)
+            }
+        })
+    }
+
+    fn use_once(&mut self, instruction: &'a YackInstruction) {
+        for c in &instruction.conditions {
+            if c.starts_with('?') {
+                self.used_once.insert(c);
+            }
+        }
+    }
+
+    /// Runs instructions until something happens that the player should be shown
+    pub fn next(&mut self, condition: &mut dyn FnMut(&str) -> bool) -> Result<YackEvent> {
+        let script = self.script;
+
+        for _ in 0..MAX_STEPS {
+            let instruction = match script.instructions.get(self.position) {
+                Some(instruction) => instruction,
+                None => {
+                    return Ok(YackEvent::End {
+                        label: self.exit_label.take(),
+                    })
+                }
+            };
+            self.position += 1;
+
+            let arg = |index: usize| instruction.args[index].clone().unwrap_or_default();
+
+            match instruction.opcode {
+                // Jumps past the if block when its conditions don't hold
+                YackOpcode::ElseGoto => {
+                    if self.passes(instruction, condition) {
+                        self.use_once(instruction);
+                    } else {
+                        self.goto(&arg(0));
+                    }
+                    continue;
+                }
+                YackOpcode::StartChoices => {
+                    let mut choices = Vec::new();
+                    while let Some(reply) = script.instructions.get(self.position) {
+                        self.position += 1;
+                        if reply.opcode == YackOpcode::EndChoices {
+                            break;
+                        }
+
+                        if is_reply(reply.opcode) && self.passes(reply, condition) {
+                            choices.push(YackChoice {
+                                number: reply.raw_opcode - YackOpcode::Reply1 as u8 + 1,
+                                text: reply.args[0].clone().unwrap_or_default(),
+                                label: reply.args[1].clone().unwrap_or_default(),
+                                index: self.position - 1,
+                            });
+                        }
+                    }
+
+                    if !choices.is_empty() {
+                        return Ok(YackEvent::Choices(choices));
+                    }
+                    continue;
+                }
+                YackOpcode::DefineLabel | YackOpcode::EndChoices | YackOpcode::Unknown => continue,
+                opcode if is_reply(opcode) => continue,
+                _ => {}
+            }
+
+            if !self.passes(instruction, condition) {
+                continue;
+            }
+            self.use_once(instruction);
+
+            match instruction.opcode {
+                YackOpcode::ActorSay => {
+                    return Ok(YackEvent::Say {
+                        actor: arg(0),
+                        text: arg(1),
+                    })
+                }
+                YackOpcode::Assign => {
+                    self.variables.insert(arg(0), arg(1));
+                }
+                YackOpcode::Pause => return Ok(YackEvent::Pause(arg(0))),
+                YackOpcode::WaitFor => return Ok(YackEvent::WaitFor(arg(0))),
+                YackOpcode::EmitCode => return Ok(YackEvent::Code(arg(0))),
+                YackOpcode::GotoLabel => self.goto(&arg(0)),
+                _ => {}
+            }
+        }
+
+        Err(Error::YackStepLimit(MAX_STEPS))
+    }
+
+    /// Picks a choice from the last `YackEvent::Choices` and continues at its label
+    pub fn choose(&mut self, choice: &YackChoice) {
+        let script = self.script;
+        self.use_once(&script.instructions[choice.index]);
+        self.goto(&choice.label);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const DIALOGUE: &str = "===main===\nguybrush: SAY(@1)\nbegin dialogue\n\
+                            \t1 SAY(@2) -> main[once]\n\t2 SAY(@3) -> other[flag]\n\
+                            \t3 SAY(@4) -> exit\nend dialogue\n===other===\nmood <- 1\n\
+                            if [mood] -> else\n\telaine: SAY(@5)\nendif\n-> main\n";
+
+    fn say(text: &str) -> YackEvent {
+        YackEvent::Say {
+            actor: text.split_once(' ').unwrap().0.to_string(),
+            text: text.split_once(' ').unwrap().1.to_string(),
+        }
+    }
+
+    fn expect_choices(event: YackEvent) -> Vec<YackChoice> {
+        match event {
+            YackEvent::Choices(choices) => choices,
+            event => panic!("Expected choices, got {:?}", event),
+        }
+    }
+
+    fn texts(choices: &[YackChoice]) -> Vec<&str> {
+        choices.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn play_dialogue() {
+        let script = YackScript::from_text(DIALOGUE).unwrap();
+        let mut player = YackPlayer::new(&script);
+        let flag = Cell::new(false);
+        let mut condition = |c: &str| c == "flag" && flag.get();
+
+        assert_eq!(player.next(&mut condition).unwrap(), say("guybrush @1"));
+        let choices = expect_choices(player.next(&mut condition).unwrap());
+        assert_eq!(texts(&choices), ["@2", "@4"]);
+
+        // The once choice is gone after it has been picked
+        player.choose(&choices[0]);
+        assert_eq!(player.next(&mut condition).unwrap(), say("guybrush @1"));
+        flag.set(true);
+        let choices = expect_choices(player.next(&mut condition).unwrap());
+        assert_eq!(texts(&choices), ["@3", "@4"]);
+
+        player.choose(&choices[0]);
+        player.set_variable("mood", "0");
+        assert_eq!(player.next(&mut condition).unwrap(), say("elaine @5"));
+        assert_eq!(player.variables()["mood"], "1");
+        assert_eq!(player.next(&mut condition).unwrap(), say("guybrush @1"));
+
+        let choices = expect_choices(player.next(&mut condition).unwrap());
+        assert_eq!(choices[1].number, 3);
+        player.choose(&choices[1]);
+        assert_eq!(
+            player.next(&mut condition).unwrap(),
+            YackEvent::End {
+                label: Some("exit".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn detect_endless_loops() {
+        let script = YackScript::from_text("===main===\n-> main\n").unwrap();
+        assert!(matches!(
+            YackPlayer::new(&script).next(&mut |_| true),
+            Err(Error::YackStepLimit(_))
+        ));
+    }
+}